@@ -0,0 +1,9 @@
+-- Add migration script here
+CREATE TABLE issue_delivery_failures (
+  newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues(newsletter_issue_id),
+  subscriber_email TEXT NOT NULL,
+  n_retries SMALLINT NOT NULL,
+  last_error TEXT NOT NULL,
+  failed_at timestamptz NOT NULL,
+  PRIMARY KEY (newsletter_issue_id, subscriber_email)
+);
@@ -0,0 +1,12 @@
+-- Add migration script here
+CREATE TABLE issue_deliveries (
+  newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues(newsletter_issue_id),
+  subscriber_email TEXT NOT NULL,
+  status TEXT NOT NULL,
+  n_attempts SMALLINT NOT NULL DEFAULT 0,
+  provider_message_id TEXT NULL,
+  last_error TEXT NULL,
+  created_at timestamptz NOT NULL,
+  updated_at timestamptz NOT NULL,
+  PRIMARY KEY (newsletter_issue_id, subscriber_email)
+);
@@ -0,0 +1,18 @@
+-- Add migration script here
+BEGIN;
+    ALTER TABLE newsletter_issues ADD COLUMN status TEXT NULL;
+    -- Backfill `status` for historical entries
+    UPDATE newsletter_issues
+        SET status = CASE
+            WHEN scheduled_for > now() THEN 'scheduled'
+            WHEN EXISTS (
+                SELECT 1 FROM issue_delivery_queue q
+                WHERE q.newsletter_issue_id = newsletter_issues.newsletter_issue_id
+            ) THEN 'sending'
+            ELSE 'sent'
+        END;
+    ALTER TABLE newsletter_issues ALTER COLUMN status SET NOT NULL;
+    -- Drafts have not been published yet
+    ALTER TABLE newsletter_issues ALTER COLUMN published_at DROP NOT NULL;
+    ALTER TABLE newsletter_issues ADD COLUMN updated_at timestamptz NOT NULL DEFAULT now();
+COMMIT;
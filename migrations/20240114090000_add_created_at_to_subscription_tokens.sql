@@ -0,0 +1,4 @@
+-- Add migration script here
+-- Tokens issued before this migration count as issued now
+ALTER TABLE subscription_tokens
+   ADD COLUMN created_at timestamptz NOT NULL DEFAULT now();
@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE subscriptions ADD COLUMN confirmed_at timestamptz NULL;
+-- Confirmation links are single-use
+ALTER TABLE subscription_tokens ADD COLUMN consumed_at timestamptz NULL;
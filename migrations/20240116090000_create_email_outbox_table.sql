@@ -0,0 +1,15 @@
+-- Add migration script here
+-- Transactional emails written alongside the change that triggers them and
+-- sent by the background workers
+CREATE TABLE email_outbox(
+   email_outbox_id uuid NOT NULL,
+   recipient TEXT NOT NULL,
+   subject TEXT NOT NULL,
+   html_content TEXT NOT NULL,
+   text_content TEXT NOT NULL,
+   n_retries SMALLINT NOT NULL DEFAULT 0,
+   execute_after timestamptz NOT NULL DEFAULT now(),
+   created_at timestamptz NOT NULL DEFAULT now(),
+   PRIMARY KEY (email_outbox_id)
+);
+CREATE INDEX email_outbox_execute_after_idx ON email_outbox (execute_after);
@@ -0,0 +1,51 @@
+-- Add migration script here
+CREATE TABLE lists(
+   list_id uuid NOT NULL,
+   slug TEXT NOT NULL UNIQUE,
+   name TEXT NOT NULL,
+   created_at timestamptz NOT NULL DEFAULT now(),
+   PRIMARY KEY (list_id)
+);
+-- Everyone subscribed so far joins the list of the single-list days
+INSERT INTO lists (list_id, slug, name) VALUES (gen_random_uuid(), 'newsletter', 'Newsletter');
+
+CREATE TABLE list_memberships(
+   list_id uuid NOT NULL
+      REFERENCES lists (list_id) ON DELETE CASCADE,
+   subscriber_id uuid NOT NULL
+      REFERENCES subscriptions (id) ON DELETE CASCADE,
+   status TEXT NOT NULL,
+   subscribed_at timestamptz NOT NULL DEFAULT now(),
+   confirmed_at timestamptz NULL,
+   PRIMARY KEY (list_id, subscriber_id)
+);
+INSERT INTO list_memberships (list_id, subscriber_id, status, subscribed_at, confirmed_at)
+SELECT
+   l.list_id,
+   s.id,
+   CASE s.status
+      WHEN 'confirmed' THEN 'confirmed'
+      WHEN 'unsubscribed' THEN 'unsubscribed'
+      ELSE 'pending_confirmation'
+   END,
+   s.subscribed_at,
+   s.confirmed_at
+FROM subscriptions s CROSS JOIN lists l;
+
+-- A confirmation link confirms a single list
+ALTER TABLE subscription_tokens ADD COLUMN list_id uuid NULL REFERENCES lists (list_id);
+UPDATE subscription_tokens SET list_id = (SELECT list_id FROM lists);
+ALTER TABLE subscription_tokens ALTER COLUMN list_id SET NOT NULL;
+
+-- The lists an issue was published to
+CREATE TABLE newsletter_issue_lists(
+   newsletter_issue_id uuid NOT NULL
+      REFERENCES newsletter_issues (newsletter_issue_id) ON DELETE CASCADE,
+   list_id uuid NOT NULL
+      REFERENCES lists (list_id),
+   PRIMARY KEY (newsletter_issue_id, list_id)
+);
+INSERT INTO newsletter_issue_lists (newsletter_issue_id, list_id)
+SELECT i.newsletter_issue_id, l.list_id
+FROM newsletter_issues i CROSS JOIN lists l
+WHERE i.status <> 'draft';
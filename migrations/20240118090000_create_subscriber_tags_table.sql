@@ -0,0 +1,12 @@
+-- Add migration script here
+CREATE TABLE subscriber_tags(
+   subscriber_id uuid NOT NULL
+      REFERENCES subscriptions (id) ON DELETE CASCADE,
+   tag TEXT NOT NULL,
+   created_at timestamptz NOT NULL DEFAULT now(),
+   PRIMARY KEY (subscriber_id, tag)
+);
+CREATE INDEX subscriber_tags_tag_idx ON subscriber_tags (tag);
+
+-- The segment expression an issue was published to, if any
+ALTER TABLE newsletter_issues ADD COLUMN segment TEXT NULL;
@@ -0,0 +1,3 @@
+-- Add migration script here
+-- Custom fields available to issues as merge tags, e.g. {{ attributes.company }}
+ALTER TABLE subscriptions ADD COLUMN attributes JSONB NOT NULL DEFAULT '{}'::jsonb;
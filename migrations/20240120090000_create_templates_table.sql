@@ -0,0 +1,10 @@
+-- Add migration script here
+-- Layouts edited by admins. Layouts without a row use the built-in defaults.
+CREATE TABLE templates(
+   name TEXT NOT NULL,
+   subject TEXT NOT NULL,
+   html_content TEXT NOT NULL,
+   text_content TEXT NOT NULL,
+   updated_at timestamptz NOT NULL DEFAULT now(),
+   PRIMARY KEY (name)
+);
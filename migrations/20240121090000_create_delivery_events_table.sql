@@ -0,0 +1,17 @@
+-- Add migration script here
+-- Delivery, bounce and spam complaint notifications from the email provider
+CREATE TABLE delivery_events(
+   delivery_event_id uuid NOT NULL,
+   provider_message_id TEXT NOT NULL,
+   recipient TEXT NOT NULL,
+   kind TEXT NOT NULL,
+   details TEXT NULL,
+   -- Set when the email was a newsletter issue, pointing at its delivery
+   newsletter_issue_id uuid NULL,
+   occurred_at timestamptz NOT NULL,
+   received_at timestamptz NOT NULL DEFAULT now(),
+   PRIMARY KEY (delivery_event_id),
+   -- The provider retries notifications it could not deliver
+   UNIQUE (provider_message_id, recipient, kind)
+);
+CREATE INDEX delivery_events_newsletter_issue_id_idx ON delivery_events (newsletter_issue_id);
@@ -0,0 +1,14 @@
+-- Add migration script here
+-- Addresses we never email, whatever their subscriber status. Not tied to
+-- `subscriptions`, so deleting a subscriber keeps them suppressed.
+CREATE TABLE suppressions(
+   suppression_id uuid NOT NULL,
+   -- Either one address or every address at a domain, in lower case
+   email TEXT NULL UNIQUE,
+   domain TEXT NULL UNIQUE,
+   reason TEXT NOT NULL,
+   source TEXT NOT NULL,
+   created_at timestamptz NOT NULL DEFAULT now(),
+   PRIMARY KEY (suppression_id),
+   CHECK ((email IS NULL) <> (domain IS NULL))
+);
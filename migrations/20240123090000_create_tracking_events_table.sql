@@ -0,0 +1,15 @@
+-- Add migration script here
+-- Issues are tracked unless they opt out when published
+ALTER TABLE newsletter_issues ADD COLUMN tracking BOOLEAN NOT NULL DEFAULT TRUE;
+-- Opens and clicks recorded by the tracking pixel and link redirects
+CREATE TABLE tracking_events(
+   tracking_event_id uuid NOT NULL,
+   newsletter_issue_id uuid NOT NULL,
+   subscriber_id uuid NOT NULL,
+   kind TEXT NOT NULL,
+   -- The link that was clicked
+   url TEXT NULL,
+   occurred_at timestamptz NOT NULL DEFAULT now(),
+   PRIMARY KEY (tracking_event_id)
+);
+CREATE INDEX tracking_events_newsletter_issue_id_idx ON tracking_events (newsletter_issue_id, kind);
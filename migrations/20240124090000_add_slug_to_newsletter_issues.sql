@@ -0,0 +1,4 @@
+-- Add migration script here
+-- Where published issues can be read on the web, e.g. `/issues/our-first-issue`.
+-- Set when an issue is published.
+ALTER TABLE newsletter_issues ADD COLUMN slug TEXT NULL UNIQUE;
@@ -0,0 +1,4 @@
+-- Add migration script here
+-- Stored as the text of a timestamp so far, which doesn't sort or compare as one
+ALTER TABLE newsletter_issues
+    ALTER COLUMN published_at TYPE timestamptz USING published_at::timestamptz;
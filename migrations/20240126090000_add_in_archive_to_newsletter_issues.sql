@@ -0,0 +1,5 @@
+-- Add migration script here
+-- Whether an issue shows in the public archive and feeds. Issues sent to a
+-- segment, or kept out when published, are only read by their recipients.
+ALTER TABLE newsletter_issues ADD COLUMN in_archive BOOLEAN NOT NULL DEFAULT TRUE;
+UPDATE newsletter_issues SET in_archive = FALSE WHERE segment IS NOT NULL;
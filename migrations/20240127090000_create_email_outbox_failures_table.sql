@@ -0,0 +1,14 @@
+-- Add migration script here
+-- Outbox emails that could not be sent, kept for inspection instead of being dropped
+CREATE TABLE email_outbox_failures(
+   email_outbox_id uuid NOT NULL,
+   recipient TEXT NOT NULL,
+   subject TEXT NOT NULL,
+   html_content TEXT NOT NULL,
+   text_content TEXT NOT NULL,
+   n_retries SMALLINT NOT NULL,
+   last_error TEXT NOT NULL,
+   created_at timestamptz NOT NULL,
+   failed_at timestamptz NOT NULL,
+   PRIMARY KEY (email_outbox_id)
+);
@@ -0,0 +1,35 @@
+use actix_web::http::header::HeaderMap;
+use anyhow::Context;
+use base64::{engine::general_purpose, Engine as _};
+use secrecy::Secret;
+
+use super::Credentials;
+
+/// Reads the credentials of a `Basic` `Authorization` header.
+pub fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
+    let header_value = headers
+        .get("Authorization")
+        .context("The 'Authorization' header was missing")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF-8 string")?;
+
+    let base64encoded_scheme = header_value
+        .strip_prefix("Basic ")
+        .context("The authorization scheme is not Basic")?;
+
+    let decoded_bytes = general_purpose::STANDARD
+        .decode(base64encoded_scheme)
+        .context("Failed to base64-decode 'Basic' authentication")?;
+
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .context("The decoded credentials was not valid UTF-8 string")?;
+
+    let (username, password) = decoded_credentials
+        .split_once(':')
+        .context("A password must be provided in 'Basic' auth.")?;
+
+    Ok(Credentials {
+        username: username.to_owned(),
+        password: Secret::new(password.to_owned()),
+    })
+}
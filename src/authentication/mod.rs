@@ -1,6 +1,8 @@
+mod basic;
 mod middleware;
 mod password;
 
-pub use password::{change_password, validate_credentials, AuthError, Credentials};
+pub use password::{change_password, create_user, validate_credentials, AuthError, Credentials};
 
+pub use basic::basic_authentication;
 pub use middleware::{reject_anonymous_users, UserId};
@@ -75,7 +75,7 @@ fn verify_password_hash(
     expected_password_hash: Secret<String>,
     password_candidate: Secret<String>,
 ) -> Result<(), AuthError> {
-    let expected_password_hash = PasswordHash::new(&expected_password_hash.expose_secret())
+    let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
         .context("Failed to parse hash in PHC string format.")?;
 
     Argon2::default()
@@ -109,6 +109,27 @@ pub async fn change_password(
     Ok(())
 }
 
+#[tracing::instrument(name = "Create user", skip(credentials, pool), fields(username = %credentials.username))]
+pub async fn create_user(credentials: Credentials, pool: &PgPool) -> Result<Uuid, anyhow::Error> {
+    let password = credentials.password;
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
+        .await?
+        .context("Failed to hash password")?;
+
+    let user_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"INSERT INTO users (user_id, username, password_hash) VALUES ($1, $2, $3)"#,
+        user_id,
+        credentials.username,
+        password_hash.expose_secret()
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the new user in the database")?;
+
+    Ok(user_id)
+}
+
 fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
     let salt = SaltString::generate(&mut rand::thread_rng());
     let password_hash = Argon2::new(
@@ -1,12 +1,18 @@
-use crate::{domain::SubscriberEmail, email_client::EmailClient};
+use crate::domain::SubscriberEmail;
+use crate::email_client::{
+    EmailClientKind, EmailSender, FileSinkEmailClient, PostmarkEmailClient, SmtpEmailClient,
+};
 use config::{Config, ConfigError, File};
 use secrecy::{ExposeSecret, Secret};
+use std::num::NonZeroU32;
+use std::sync::Arc;
 
 #[derive(serde::Deserialize, Debug, Clone)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub worker: WorkerSettings,
     pub redis_uri: Secret<String>,
 }
 
@@ -25,14 +31,50 @@ pub struct ApplicationSettings {
     pub host: String,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    // How long in-flight requests and deliveries get to finish on shutdown
+    pub shutdown_deadline_milliseconds: u64,
+    // How long a confirmation link stays valid
+    pub subscription_token_ttl_hours: u64,
+}
+
+impl ApplicationSettings {
+    pub fn shutdown_deadline(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.shutdown_deadline_milliseconds)
+    }
+
+    /// Fails if the TTL is too long to be added to a timestamp.
+    pub fn subscription_token_ttl(&self) -> Result<chrono::Duration, anyhow::Error> {
+        self.subscription_token_ttl_hours
+            .checked_mul(60 * 60)
+            .and_then(|seconds| {
+                chrono::Duration::from_std(std::time::Duration::from_secs(seconds)).ok()
+            })
+            .filter(|ttl| chrono::Utc::now().checked_add_signed(*ttl).is_some())
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "A subscription token TTL of {} hours is out of range.",
+                    self.subscription_token_ttl_hours
+                )
+            })
+    }
 }
 
 #[derive(serde::Deserialize, Debug, Clone)]
 pub struct EmailClientSettings {
+    // Which backend delivers emails, defaults to Postmark
+    #[serde(default)]
+    pub kind: EmailClientKind,
     pub base_url: String,
     pub sender_email: String,
     pub authorization_token: Secret<String>,
     pub timeout_milliseconds: u64,
+    // Required by the `smtp` backend, e.g. `smtp://localhost:1025`
+    pub smtp_url: Option<Secret<String>>,
+    // Required by the `file_sink` backend
+    pub output_directory: Option<String>,
+    // Basic auth credentials the provider uses to call our webhook
+    pub webhook_username: String,
+    pub webhook_password: Secret<String>,
 }
 
 impl EmailClientSettings {
@@ -44,17 +86,74 @@ impl EmailClientSettings {
         std::time::Duration::from_millis(self.timeout_milliseconds)
     }
 
-    pub fn client(self) -> EmailClient {
+    pub fn client(self) -> Arc<dyn EmailSender> {
         let sender_email = self.sender().expect("Invalid sender email address.");
         let timeout = self.timeout();
-        EmailClient::new(
-            self.base_url,
-            sender_email,
-            self.authorization_token,
-            timeout,
-        )
+        match self.kind {
+            EmailClientKind::Postmark => Arc::new(PostmarkEmailClient::new(
+                self.base_url,
+                sender_email,
+                self.authorization_token,
+                timeout,
+            )),
+            EmailClientKind::Smtp => {
+                let smtp_url = self
+                    .smtp_url
+                    .expect("Missing `smtp_url` for the smtp email client.");
+                Arc::new(
+                    SmtpEmailClient::new(&smtp_url, sender_email, timeout)
+                        .expect("Failed to build the smtp email client."),
+                )
+            }
+            EmailClientKind::FileSink => {
+                let output_directory = self
+                    .output_directory
+                    .expect("Missing `output_directory` for the file_sink email client.");
+                Arc::new(
+                    FileSinkEmailClient::new(output_directory.into(), sender_email)
+                        .expect("Failed to build the file_sink email client."),
+                )
+            }
+        }
     }
 }
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct WorkerSettings {
+    // Number of delivery workers running concurrently in each process
+    pub concurrency: usize,
+    pub empty_queue_poll_interval_milliseconds: u64,
+    pub error_poll_interval_milliseconds: u64,
+    #[serde(default)]
+    pub rate_limit: RateLimitSettings,
+}
+
+// Budgets left out are not enforced
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+pub struct RateLimitSettings {
+    pub messages_per_second: Option<NonZeroU32>,
+    // Default budget for every recipient domain
+    pub messages_per_second_per_domain: Option<NonZeroU32>,
+    // Domains with a budget of their own
+    #[serde(default)]
+    pub domains: Vec<DomainRateLimit>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct DomainRateLimit {
+    pub domain: String,
+    pub messages_per_second: NonZeroU32,
+}
+
+impl WorkerSettings {
+    pub fn empty_queue_poll_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.empty_queue_poll_interval_milliseconds)
+    }
+
+    pub fn error_poll_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.error_poll_interval_milliseconds)
+    }
+}
+
 impl DatabaseSettings {
     pub fn connection_string(&self) -> Secret<String> {
         Secret::new(format!(
@@ -94,6 +193,13 @@ pub fn get_configuration() -> Result<Settings, ConfigError> {
             File::from(configuration_dir.join(environment.as_str().to_owned() + ".yml"))
                 .required(true),
         )
+        // Secrets kept out of the files, e.g. `APP_EMAIL_CLIENT__WEBHOOK_PASSWORD`
+        // sets `email_client.webhook_password`
+        .add_source(
+            config::Environment::with_prefix("APP")
+                .prefix_separator("_")
+                .separator("__"),
+        )
         .build()?
         .try_deserialize()
 }
@@ -0,0 +1,144 @@
+use chrono::{DateTime, Utc};
+use std::ops::DerefMut;
+use uuid::Uuid;
+
+use crate::domain::SubscriberStatus;
+use crate::issue_delivery_worker::PgTransaction;
+use crate::suppressions::SuppressionSource;
+
+/// What the email provider tells us happened to an email after we sent it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DeliveryEventKind {
+    Delivered,
+    // The address doesn't exist, the subscriber won't get emails anymore
+    HardBounced,
+    // e.g. a full mailbox: worth trying again with the next issue
+    SoftBounced,
+    Complained,
+}
+
+impl DeliveryEventKind {
+    pub const ALL: [DeliveryEventKind; 4] = [
+        DeliveryEventKind::Delivered,
+        DeliveryEventKind::HardBounced,
+        DeliveryEventKind::SoftBounced,
+        DeliveryEventKind::Complained,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryEventKind::Delivered => "delivered",
+            DeliveryEventKind::HardBounced => "hard_bounced",
+            DeliveryEventKind::SoftBounced => "soft_bounced",
+            DeliveryEventKind::Complained => "complained",
+        }
+    }
+
+    /// The status recipients are moved to, if any.
+    pub fn subscriber_status(&self) -> Option<SubscriberStatus> {
+        match self {
+            DeliveryEventKind::HardBounced => Some(SubscriberStatus::Bounced),
+            DeliveryEventKind::Complained => Some(SubscriberStatus::Complained),
+            DeliveryEventKind::Delivered | DeliveryEventKind::SoftBounced => None,
+        }
+    }
+
+    /// Whether the recipient is added to the suppression list, and as what.
+    pub fn suppression_source(&self) -> Option<SuppressionSource> {
+        match self {
+            DeliveryEventKind::HardBounced => Some(SuppressionSource::Bounce),
+            DeliveryEventKind::Complained => Some(SuppressionSource::Complaint),
+            DeliveryEventKind::Delivered | DeliveryEventKind::SoftBounced => None,
+        }
+    }
+}
+
+impl TryFrom<String> for DeliveryEventKind {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "delivered" => Ok(DeliveryEventKind::Delivered),
+            "hard_bounced" => Ok(DeliveryEventKind::HardBounced),
+            "soft_bounced" => Ok(DeliveryEventKind::SoftBounced),
+            "complained" => Ok(DeliveryEventKind::Complained),
+            other => Err(format!("{} is not a valid delivery event.", other)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct DeliveryEvent {
+    pub kind: DeliveryEventKind,
+    pub provider_message_id: String,
+    pub recipient: String,
+    pub details: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+/// Adds `event` to the log, next to the newsletter delivery it is about if
+/// there is one. Returns `false` if the event had already been recorded.
+#[tracing::instrument(name = "Record a delivery event", skip(transaction))]
+pub async fn record_event(
+    transaction: &mut PgTransaction,
+    event: &DeliveryEvent,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO delivery_events (
+            delivery_event_id,
+            provider_message_id,
+            recipient,
+            kind,
+            details,
+            newsletter_issue_id,
+            occurred_at
+        )
+        VALUES (
+            $1, $2, $3, $4, $5,
+            (
+                SELECT newsletter_issue_id FROM issue_deliveries
+                WHERE provider_message_id = $2 AND subscriber_email = $3
+                LIMIT 1
+            ),
+            $6
+        )
+        ON CONFLICT (provider_message_id, recipient, kind) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        event.provider_message_id,
+        event.recipient,
+        event.kind.as_str(),
+        event.details,
+        event.occurred_at
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// A complaint trumps a bounce, but nothing overrides a block by an admin.
+#[tracing::instrument(name = "Stop emailing an undeliverable address", skip(transaction))]
+pub async fn mark_undeliverable(
+    transaction: &mut PgTransaction,
+    email: &str,
+    status: SubscriberStatus,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = $2
+        WHERE
+            email = $1 AND
+            status <> ALL(
+                CASE WHEN $2 = 'complained'
+                    THEN ARRAY['blocked', 'complained']
+                    ELSE ARRAY['blocked', 'complained', 'bounced']
+                END
+            )
+        "#,
+        email,
+        status.as_str()
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    Ok(())
+}
@@ -1,7 +1,19 @@
 mod new_subscriber;
+mod newsletter_issue_status;
+mod segment;
 mod subscriber_email;
 mod subscriber_name;
+mod subscriber_status;
+mod subscriber_tag;
+mod tracking_token;
+mod unsubscribe_token;
 
 pub use new_subscriber::NewSubscriber;
+pub use newsletter_issue_status::NewsletterIssueStatus;
+pub use segment::Segment;
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use subscriber_status::SubscriberStatus;
+pub use subscriber_tag::SubscriberTag;
+pub use tracking_token::{TrackedEvent, TrackingToken};
+pub use unsubscribe_token::UnsubscribeToken;
@@ -0,0 +1,31 @@
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NewsletterIssueStatus {
+    Draft,
+    Scheduled,
+    Sending,
+    Sent,
+}
+
+impl NewsletterIssueStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NewsletterIssueStatus::Draft => "draft",
+            NewsletterIssueStatus::Scheduled => "scheduled",
+            NewsletterIssueStatus::Sending => "sending",
+            NewsletterIssueStatus::Sent => "sent",
+        }
+    }
+}
+
+impl TryFrom<String> for NewsletterIssueStatus {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "draft" => Ok(NewsletterIssueStatus::Draft),
+            "scheduled" => Ok(NewsletterIssueStatus::Scheduled),
+            "sending" => Ok(NewsletterIssueStatus::Sending),
+            "sent" => Ok(NewsletterIssueStatus::Sent),
+            other => Err(format!("{} is not a valid newsletter issue status.", other)),
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use sqlx::{Postgres, QueryBuilder};
+
+use super::SubscriberTag;
+
+// Bounds the work done on an expression coming from a form
+const MAX_DEPTH: usize = 16;
+const MAX_TAGS: usize = 32;
+
+/// A boolean expression over subscriber tags picking the recipients of an
+/// issue, e.g. `tag:beta AND NOT (tag:churned OR tag:paused)`.
+/// `NOT` binds tighter than `AND`, which binds tighter than `OR`.
+/// Keywords are case-insensitive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Tag(SubscriberTag),
+    Not(Box<Segment>),
+    And(Box<Segment>, Box<Segment>),
+    Or(Box<Segment>, Box<Segment>),
+}
+
+impl Segment {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let tokens = tokenize(s);
+        if tokens.is_empty() {
+            return Err("The segment is empty.".into());
+        }
+        if tokens.iter().filter(|t| matches!(t, Token::Tag(_))).count() > MAX_TAGS {
+            return Err(format!("A segment can use at most {} tags.", MAX_TAGS));
+        }
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+        };
+        let segment = parser.or(0)?;
+        match parser.peek() {
+            None => Ok(segment),
+            Some(token) => Err(format!("Unexpected {} in the segment.", token)),
+        }
+    }
+
+    /// Appends a condition matching the subscribers in the segment to `builder`.
+    /// The subscriber must be in scope as `s`, a row of `subscriptions`.
+    pub fn push_sql(&self, builder: &mut QueryBuilder<'_, Postgres>) {
+        match self {
+            Segment::Tag(tag) => {
+                builder.push(
+                    "EXISTS (SELECT 1 FROM subscriber_tags st \
+                    WHERE st.subscriber_id = s.id AND st.tag = ",
+                );
+                builder.push_bind(tag.as_ref().to_owned());
+                builder.push(")");
+            }
+            Segment::Not(segment) => {
+                builder.push("NOT (");
+                segment.push_sql(builder);
+                builder.push(")");
+            }
+            Segment::And(left, right) | Segment::Or(left, right) => {
+                let operator = if matches!(self, Segment::And(..)) {
+                    " AND "
+                } else {
+                    " OR "
+                };
+                builder.push("(");
+                left.push_sql(builder);
+                builder.push(operator);
+                right.push_sql(builder);
+                builder.push(")");
+            }
+        }
+    }
+}
+
+// Written back fully parenthesized, so the stored form reads unambiguously
+impl std::fmt::Display for Segment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Segment::Tag(tag) => write!(f, "tag:{}", tag),
+            Segment::Not(segment) => match **segment {
+                Segment::Tag(_) | Segment::Not(_) => write!(f, "NOT {}", segment),
+                _ => write!(f, "NOT ({})", segment),
+            },
+            Segment::And(left, right) => write!(f, "{} AND {}", Operand(left), Operand(right)),
+            Segment::Or(left, right) => write!(f, "{} OR {}", Operand(left), Operand(right)),
+        }
+    }
+}
+
+struct Operand<'a>(&'a Segment);
+
+impl std::fmt::Display for Operand<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Segment::And(..) | Segment::Or(..) => write!(f, "({})", self.0),
+            _ => write!(f, "{}", self.0),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    LeftParen,
+    RightParen,
+    And,
+    Or,
+    Not,
+    Tag(String),
+    Word(String),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::LeftParen => write!(f, "`(`"),
+            Token::RightParen => write!(f, "`)`"),
+            Token::And => write!(f, "`AND`"),
+            Token::Or => write!(f, "`OR`"),
+            Token::Not => write!(f, "`NOT`"),
+            Token::Tag(tag) => write!(f, "`tag:{}`", tag),
+            Token::Word(word) => write!(f, "`{}`", word),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let flush = |word: &mut String, tokens: &mut Vec<Token>| {
+        if word.is_empty() {
+            return;
+        }
+        let token = match word.to_ascii_uppercase().as_str() {
+            "AND" => Token::And,
+            "OR" => Token::Or,
+            "NOT" => Token::Not,
+            _ => match word.strip_prefix("tag:") {
+                Some(tag) => Token::Tag(tag.to_owned()),
+                None => Token::Word(word.clone()),
+            },
+        };
+        tokens.push(token);
+        word.clear();
+    };
+    for c in s.chars() {
+        match c {
+            '(' | ')' => {
+                flush(&mut word, &mut tokens);
+                tokens.push(if c == '(' {
+                    Token::LeftParen
+                } else {
+                    Token::RightParen
+                });
+            }
+            c if c.is_whitespace() => flush(&mut word, &mut tokens),
+            c => word.push(c),
+        }
+    }
+    flush(&mut word, &mut tokens);
+    tokens
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next_if(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn or(&mut self, depth: usize) -> Result<Segment, String> {
+        let mut segment = self.and(depth)?;
+        while self.next_if(&Token::Or) {
+            segment = Segment::Or(Box::new(segment), Box::new(self.and(depth)?));
+        }
+        Ok(segment)
+    }
+
+    fn and(&mut self, depth: usize) -> Result<Segment, String> {
+        let mut segment = self.not(depth)?;
+        while self.next_if(&Token::And) {
+            segment = Segment::And(Box::new(segment), Box::new(self.not(depth)?));
+        }
+        Ok(segment)
+    }
+
+    fn not(&mut self, depth: usize) -> Result<Segment, String> {
+        if depth > MAX_DEPTH {
+            return Err("The segment is nested too deeply.".into());
+        }
+        if self.next_if(&Token::Not) {
+            return Ok(Segment::Not(Box::new(self.not(depth + 1)?)));
+        }
+        self.operand(depth)
+    }
+
+    fn operand(&mut self, depth: usize) -> Result<Segment, String> {
+        let Some(token) = self.tokens.get(self.position) else {
+            return Err("The segment ends too early.".into());
+        };
+        self.position += 1;
+        match token {
+            Token::Tag(tag) => Ok(Segment::Tag(SubscriberTag::parse(tag.clone())?)),
+            Token::LeftParen => {
+                let segment = self.or(depth + 1)?;
+                if self.next_if(&Token::RightParen) {
+                    Ok(segment)
+                } else {
+                    Err("A `(` is never closed in the segment.".into())
+                }
+            }
+            Token::Word(word) => Err(format!(
+                "Unexpected `{}` in the segment, tags are written `tag:name`.",
+                word
+            )),
+            token => Err(format!("Unexpected {} in the segment.", token)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Segment;
+    use claims::assert_err;
+    use sqlx::{Execute, Postgres, QueryBuilder};
+
+    fn roundtrip(s: &str) -> String {
+        Segment::parse(s).unwrap().to_string()
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and_which_binds_tighter_than_or() {
+        assert_eq!(
+            roundtrip("tag:a OR tag:b AND NOT tag:c"),
+            "tag:a OR (tag:b AND NOT tag:c)"
+        );
+    }
+
+    #[test]
+    fn keywords_are_case_insensitive_and_parentheses_group() {
+        assert_eq!(
+            roundtrip("tag:beta and not (tag:churned or tag:paused)"),
+            "tag:beta AND NOT (tag:churned OR tag:paused)"
+        );
+    }
+
+    #[test]
+    fn malformed_segments_are_rejected() {
+        for segment in [
+            "",
+            "beta",
+            "tag:beta AND",
+            "tag:beta tag:paying",
+            "(tag:beta",
+            "tag:beta)",
+            "tag:Beta",
+            "NOT",
+        ] {
+            assert_err!(Segment::parse(segment), "{}", segment);
+        }
+    }
+
+    #[test]
+    fn deeply_nested_segments_are_rejected() {
+        let segment = format!("{}tag:a{}", "(".repeat(100), ")".repeat(100));
+        assert_err!(Segment::parse(&segment));
+        assert_err!(Segment::parse(&format!("{}tag:a", "NOT ".repeat(100))));
+    }
+
+    #[test]
+    fn tags_are_bound_as_parameters() {
+        let segment = Segment::parse("tag:beta AND NOT tag:churned").unwrap();
+        let mut builder = QueryBuilder::<Postgres>::new("");
+        segment.push_sql(&mut builder);
+        let sql = builder.build().sql().to_owned();
+
+        assert!(sql.contains("st.tag = $1"));
+        assert!(sql.contains("NOT (EXISTS"));
+        assert!(sql.contains("st.tag = $2"));
+        assert!(!sql.contains("beta"));
+    }
+}
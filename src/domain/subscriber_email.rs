@@ -11,6 +11,11 @@ impl SubscriberEmail {
             Err(format!("{} is not a valid subscriber email.", s))
         }
     }
+
+    /// The part after the `@`, e.g. `gmail.com`.
+    pub fn domain(&self) -> &str {
+        self.0.rsplit_once('@').map_or("", |(_, domain)| domain)
+    }
 }
 
 impl AsRef<str> for SubscriberEmail {
@@ -34,6 +39,12 @@ mod tests {
     use quickcheck::{Arbitrary, Gen};
     use rand::{rngs::StdRng, SeedableRng};
 
+    #[test]
+    fn domain_is_the_part_after_the_at_sign() {
+        let email = SubscriberEmail::parse("ursula@gmail.com".to_string()).unwrap();
+        assert_eq!(email.domain(), "gmail.com");
+    }
+
     #[test]
     fn empty_string_is_rejected() {
         let email = "".to_string();
@@ -0,0 +1,40 @@
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SubscriberStatus {
+    PendingConfirmation,
+    Confirmed,
+    Unsubscribed,
+    // Set by an admin: never receives emails and can't subscribe again
+    Blocked,
+    // Set when the email provider reports a hard bounce or a spam complaint:
+    // like blocked subscribers, they never receive emails again
+    Bounced,
+    Complained,
+}
+
+impl SubscriberStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubscriberStatus::PendingConfirmation => "pending_confirmation",
+            SubscriberStatus::Confirmed => "confirmed",
+            SubscriberStatus::Unsubscribed => "unsubscribed",
+            SubscriberStatus::Blocked => "blocked",
+            SubscriberStatus::Bounced => "bounced",
+            SubscriberStatus::Complained => "complained",
+        }
+    }
+}
+
+impl TryFrom<String> for SubscriberStatus {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "pending_confirmation" => Ok(SubscriberStatus::PendingConfirmation),
+            "confirmed" => Ok(SubscriberStatus::Confirmed),
+            "unsubscribed" => Ok(SubscriberStatus::Unsubscribed),
+            "blocked" => Ok(SubscriberStatus::Blocked),
+            "bounced" => Ok(SubscriberStatus::Bounced),
+            "complained" => Ok(SubscriberStatus::Complained),
+            other => Err(format!("{} is not a valid subscriber status.", other)),
+        }
+    }
+}
@@ -0,0 +1,52 @@
+/// A label attached to subscribers, e.g. `beta` or `paying`, used to target
+/// segments of the audience.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SubscriberTag(String);
+
+impl AsRef<str> for SubscriberTag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl SubscriberTag {
+    /// Tags are lowercase ASCII letters, digits, dashes and underscores.
+    pub fn parse(s: String) -> Result<Self, String> {
+        let is_valid = !s.is_empty()
+            && s.len() <= 64
+            && s.chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+        if is_valid {
+            Ok(Self(s))
+        } else {
+            Err(format!(
+                "{} is not a valid tag. Use lowercase letters, digits, dashes and underscores.",
+                s
+            ))
+        }
+    }
+}
+
+impl std::fmt::Display for SubscriberTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberTag;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn lowercase_tags_with_dashes_and_underscores_are_valid() {
+        assert_ok!(SubscriberTag::parse("early_adopter-2024".into()));
+    }
+
+    #[test]
+    fn empty_uppercase_or_spaced_tags_are_rejected() {
+        for tag in ["", "Beta", "early adopter", "tag:beta"] {
+            assert_err!(SubscriberTag::parse(tag.into()));
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use uuid::Uuid;
+
+const OPEN: u8 = b'o';
+const CLICK: u8 = b'c';
+// Kind, issue id and subscriber id
+const HEADER_LEN: usize = 1 + 16 + 16;
+const TAG_LEN: usize = 32;
+
+/// What a tracking token was issued for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrackedEvent {
+    Open {
+        newsletter_issue_id: Uuid,
+        subscriber_id: Uuid,
+    },
+    Click {
+        newsletter_issue_id: Uuid,
+        subscriber_id: Uuid,
+        url: String,
+    },
+}
+
+/// Identifies the recipient of an issue in open and click tracking URLs.
+/// Like `UnsubscribeToken`, it carries its own HMAC-SHA256 tag, so it can be
+/// verified without storing anything. Click tokens include the link they
+/// point to: we only ever redirect to URLs we signed ourselves.
+#[derive(Debug, Clone)]
+pub struct TrackingToken(String);
+
+impl TrackingToken {
+    pub fn open(
+        newsletter_issue_id: Uuid,
+        subscriber_id: Uuid,
+        hmac_secret: &Secret<String>,
+    ) -> Self {
+        Self::sign(OPEN, newsletter_issue_id, subscriber_id, "", hmac_secret)
+    }
+
+    pub fn click(
+        newsletter_issue_id: Uuid,
+        subscriber_id: Uuid,
+        url: &str,
+        hmac_secret: &Secret<String>,
+    ) -> Self {
+        Self::sign(CLICK, newsletter_issue_id, subscriber_id, url, hmac_secret)
+    }
+
+    fn sign(
+        kind: u8,
+        newsletter_issue_id: Uuid,
+        subscriber_id: Uuid,
+        url: &str,
+        hmac_secret: &Secret<String>,
+    ) -> Self {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + url.len() + TAG_LEN);
+        bytes.push(kind);
+        bytes.extend_from_slice(newsletter_issue_id.as_bytes());
+        bytes.extend_from_slice(subscriber_id.as_bytes());
+        bytes.extend_from_slice(url.as_bytes());
+        let mut mac = Hmac::<Sha256>::new_from_slice(hmac_secret.expose_secret().as_bytes())
+            .expect("HMAC can take a key of any size");
+        mac.update(&bytes);
+        bytes.extend_from_slice(&mac.finalize().into_bytes());
+        Self(URL_SAFE_NO_PAD.encode(bytes))
+    }
+
+    /// Returns what the token tracks if it was signed with `hmac_secret`.
+    pub fn verify(
+        token: &str,
+        hmac_secret: &Secret<String>,
+    ) -> Result<TrackedEvent, anyhow::Error> {
+        let bytes = URL_SAFE_NO_PAD.decode(token)?;
+        if bytes.len() < HEADER_LEN + TAG_LEN {
+            anyhow::bail!("The tracking token is too short");
+        }
+        let (payload, tag) = bytes.split_at(bytes.len() - TAG_LEN);
+        let mut mac = Hmac::<Sha256>::new_from_slice(hmac_secret.expose_secret().as_bytes())?;
+        mac.update(payload);
+        mac.verify_slice(tag)?;
+
+        let newsletter_issue_id = Uuid::from_slice(&payload[1..17])?;
+        let subscriber_id = Uuid::from_slice(&payload[17..HEADER_LEN])?;
+        let url = &payload[HEADER_LEN..];
+        match payload[0] {
+            OPEN if url.is_empty() => Ok(TrackedEvent::Open {
+                newsletter_issue_id,
+                subscriber_id,
+            }),
+            CLICK => Ok(TrackedEvent::Click {
+                newsletter_issue_id,
+                subscriber_id,
+                url: String::from_utf8(url.to_vec())?,
+            }),
+            _ => anyhow::bail!("Unknown tracking token kind"),
+        }
+    }
+}
+
+impl AsRef<str> for TrackingToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TrackedEvent, TrackingToken};
+    use crate::domain::UnsubscribeToken;
+    use claims::{assert_err, assert_ok_eq};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn secret() -> Secret<String> {
+        Secret::new("super-secret".to_string())
+    }
+
+    #[test]
+    fn open_and_click_tokens_are_verified() {
+        let (issue_id, subscriber_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let open = TrackingToken::open(issue_id, subscriber_id, &secret());
+        let click = TrackingToken::click(issue_id, subscriber_id, "https://a.com/?q=é", &secret());
+
+        assert_ok_eq!(
+            TrackingToken::verify(open.as_ref(), &secret()),
+            TrackedEvent::Open {
+                newsletter_issue_id: issue_id,
+                subscriber_id
+            }
+        );
+        assert_ok_eq!(
+            TrackingToken::verify(click.as_ref(), &secret()),
+            TrackedEvent::Click {
+                newsletter_issue_id: issue_id,
+                subscriber_id,
+                url: "https://a.com/?q=é".into()
+            }
+        );
+    }
+
+    #[test]
+    fn a_token_signed_with_another_secret_is_rejected() {
+        let token =
+            TrackingToken::click(Uuid::new_v4(), Uuid::new_v4(), "https://a.com", &secret());
+        let other_secret = Secret::new("another-secret".to_string());
+        assert_err!(TrackingToken::verify(token.as_ref(), &other_secret));
+    }
+
+    #[test]
+    fn the_link_of_a_click_token_cannot_be_swapped() {
+        let token =
+            TrackingToken::click(Uuid::new_v4(), Uuid::new_v4(), "https://a.com", &secret());
+        let mut bytes = base64::Engine::decode(
+            &base64::engine::general_purpose::URL_SAFE_NO_PAD,
+            token.as_ref(),
+        )
+        .unwrap();
+        // https://a.com -> https://e.com
+        bytes[33 + 8] = b'e';
+        let tampered =
+            base64::Engine::encode(&base64::engine::general_purpose::URL_SAFE_NO_PAD, bytes);
+        assert_err!(TrackingToken::verify(&tampered, &secret()));
+    }
+
+    #[test]
+    fn unsubscribe_tokens_and_garbage_are_rejected() {
+        let unsubscribe = UnsubscribeToken::generate(Uuid::new_v4(), &secret());
+        assert_err!(TrackingToken::verify(unsubscribe.as_ref(), &secret()));
+        assert_err!(TrackingToken::verify("not a token", &secret()));
+        assert_err!(TrackingToken::verify("", &secret()));
+    }
+}
@@ -0,0 +1,85 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// A per-subscriber token that lets the holder unsubscribe without logging in.
+/// It is the subscriber id followed by an HMAC-SHA256 tag over it,
+/// so it can be verified without storing anything in the database.
+#[derive(Debug, Clone)]
+pub struct UnsubscribeToken(String);
+
+impl UnsubscribeToken {
+    pub fn generate(subscriber_id: Uuid, hmac_secret: &Secret<String>) -> Self {
+        let mut mac = Hmac::<Sha256>::new_from_slice(hmac_secret.expose_secret().as_bytes())
+            .expect("HMAC can take a key of any size");
+        mac.update(subscriber_id.as_bytes());
+        let mut bytes = subscriber_id.as_bytes().to_vec();
+        bytes.extend_from_slice(&mac.finalize().into_bytes());
+        Self(URL_SAFE_NO_PAD.encode(bytes))
+    }
+
+    /// Returns the subscriber id if the token was signed with `hmac_secret`.
+    pub fn verify(token: &str, hmac_secret: &Secret<String>) -> Result<Uuid, anyhow::Error> {
+        let bytes = URL_SAFE_NO_PAD.decode(token)?;
+        if bytes.len() <= 16 {
+            anyhow::bail!("The unsubscribe token is too short");
+        }
+        let (id, tag) = bytes.split_at(16);
+        let mut mac = Hmac::<Sha256>::new_from_slice(hmac_secret.expose_secret().as_bytes())?;
+        mac.update(id);
+        mac.verify_slice(tag)?;
+        Ok(Uuid::from_slice(id)?)
+    }
+}
+
+impl AsRef<str> for UnsubscribeToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UnsubscribeToken;
+    use claims::{assert_err, assert_ok_eq};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn secret() -> Secret<String> {
+        Secret::new("super-secret".to_string())
+    }
+
+    #[test]
+    fn a_generated_token_is_verified() {
+        let subscriber_id = Uuid::new_v4();
+        let token = UnsubscribeToken::generate(subscriber_id, &secret());
+        assert_ok_eq!(
+            UnsubscribeToken::verify(token.as_ref(), &secret()),
+            subscriber_id
+        );
+    }
+
+    #[test]
+    fn a_token_signed_with_another_secret_is_rejected() {
+        let token = UnsubscribeToken::generate(Uuid::new_v4(), &secret());
+        let other_secret = Secret::new("another-secret".to_string());
+        assert_err!(UnsubscribeToken::verify(token.as_ref(), &other_secret));
+    }
+
+    #[test]
+    fn a_tampered_token_is_rejected() {
+        let token = UnsubscribeToken::generate(Uuid::new_v4(), &secret());
+        let other = UnsubscribeToken::generate(Uuid::new_v4(), &secret());
+        // Take the subscriber id from one token and the tag from another
+        let tampered = format!("{}{}", &token.as_ref()[..22], &other.as_ref()[22..]);
+        assert_err!(UnsubscribeToken::verify(&tampered, &secret()));
+    }
+
+    #[test]
+    fn garbage_is_rejected() {
+        assert_err!(UnsubscribeToken::verify("not a token", &secret()));
+        assert_err!(UnsubscribeToken::verify("", &secret()));
+    }
+}
@@ -0,0 +1,92 @@
+use super::{build_message, message_id, EmailSender};
+use crate::domain::SubscriberEmail;
+use anyhow::Context;
+use lettre::{AsyncFileTransport, AsyncTransport, Tokio1Executor};
+use std::path::PathBuf;
+
+/// Writes every email to `<directory>/<uuid>.eml` instead of sending it.
+/// Meant for local development, where no real email should leave the machine.
+pub struct FileSinkEmailClient {
+    transport: AsyncFileTransport<Tokio1Executor>,
+    sender: SubscriberEmail,
+}
+
+impl FileSinkEmailClient {
+    pub fn new(directory: PathBuf, sender: SubscriberEmail) -> Result<Self, anyhow::Error> {
+        std::fs::create_dir_all(&directory).with_context(|| {
+            format!(
+                "Failed to create the email output directory {}",
+                directory.display()
+            )
+        })?;
+        Ok(Self {
+            transport: AsyncFileTransport::new(directory),
+            sender,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for FileSinkEmailClient {
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<Option<String>, anyhow::Error> {
+        let message = build_message(
+            &self.sender,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            headers,
+        )?;
+        let message_id = message_id(&message);
+        let file_id = self
+            .transport
+            .send(message)
+            .await
+            .context("Failed to write the email to disk")?;
+        tracing::info!(%file_id, "Wrote an email to the output directory.");
+        Ok(message_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{EmailSender, FileSinkEmailClient};
+    use fake::faker::internet::en::SafeEmail;
+    use fake::Fake;
+    use uuid::Uuid;
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn send_email_writes_an_eml_file_to_the_directory() {
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let email_client = FileSinkEmailClient::new(directory.clone(), email()).unwrap();
+        let recipient = email();
+
+        email_client
+            .send_email(&recipient, "Newsletter", "<p>Hello</p>", "Hello", &[])
+            .await
+            .unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].extension().unwrap(), "eml");
+        let contents = std::fs::read_to_string(&files[0]).unwrap();
+        assert!(contents.contains("Subject: Newsletter"));
+        assert!(contents.contains(&format!("To: {}", recipient.as_ref())));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,128 @@
+mod file_sink;
+mod postmark;
+mod smtp;
+
+pub use file_sink::FileSinkEmailClient;
+pub use postmark::PostmarkEmailClient;
+pub use smtp::SmtpEmailClient;
+
+use crate::domain::SubscriberEmail;
+use anyhow::Context;
+use lettre::message::header::{HeaderName, HeaderValue};
+use lettre::message::MultiPart;
+use lettre::Message;
+
+/// Largest batch callers may hand to `EmailSender::send_batch`,
+/// matching the limit of Postmark's batch API.
+pub const MAX_BATCH_SIZE: usize = 500;
+
+/// A fully rendered email, ready to be handed to a transport.
+pub struct Email {
+    pub recipient: SubscriberEmail,
+    pub subject: String,
+    pub html_content: String,
+    pub text_content: String,
+    pub headers: Vec<(String, String)>,
+}
+
+/// The transport refused the email because we are sending too fast.
+/// Callers should slow down, for at least `retry_after` if the transport said so.
+#[derive(thiserror::Error, Debug)]
+#[error("The email provider is rate limiting us")]
+pub struct RateLimited {
+    pub retry_after: Option<std::time::Duration>,
+}
+
+/// A transport able to deliver emails on behalf of the application.
+#[async_trait::async_trait]
+pub trait EmailSender: Send + Sync {
+    /// `headers` are extra message headers, e.g. `List-Unsubscribe`.
+    /// Returns the message id assigned by the transport, if it has one.
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<Option<String>, anyhow::Error>;
+
+    /// Whether `send_batch` sends the emails in a single call. Without a batch
+    /// API callers are better off sending, and settling, one email at a time.
+    fn supports_batch(&self) -> bool {
+        false
+    }
+
+    /// Sends up to `MAX_BATCH_SIZE` emails at once.
+    /// The outcomes line up with `emails`, so each recipient can be committed or
+    /// retried on its own; an `Err` means the batch as a whole was not accepted.
+    /// Transports without a batch API send the emails one by one.
+    async fn send_batch(
+        &self,
+        emails: &[Email],
+    ) -> Result<Vec<Result<Option<String>, anyhow::Error>>, anyhow::Error> {
+        let mut outcomes = Vec::with_capacity(emails.len());
+        for email in emails {
+            let headers: Vec<(&str, &str)> = email
+                .headers
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+                .collect();
+            let outcome = self
+                .send_email(
+                    &email.recipient,
+                    &email.subject,
+                    &email.html_content,
+                    &email.text_content,
+                    &headers,
+                )
+                .await;
+            outcomes.push(outcome);
+        }
+        Ok(outcomes)
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailClientKind {
+    #[default]
+    Postmark,
+    Smtp,
+    FileSink,
+}
+
+// Builds the MIME message shared by the SMTP and file-sink backends.
+fn build_message(
+    sender: &SubscriberEmail,
+    recipient: &SubscriberEmail,
+    subject: &str,
+    html_content: &str,
+    text_content: &str,
+    headers: &[(&str, &str)],
+) -> Result<Message, anyhow::Error> {
+    let mut builder = Message::builder()
+        .from(sender.as_ref().parse().context("Invalid sender address")?)
+        .to(recipient
+            .as_ref()
+            .parse()
+            .context("Invalid recipient address")?)
+        .subject(subject)
+        .message_id(None);
+    for &(name, value) in headers {
+        let name = HeaderName::new_from_ascii(name.to_owned())
+            .with_context(|| format!("{} is not a valid header name", name))?;
+        builder = builder.raw_header(HeaderValue::new(name, value.to_owned()));
+    }
+    let message = builder
+        .multipart(MultiPart::alternative_plain_html(
+            text_content.to_owned(),
+            html_content.to_owned(),
+        ))
+        .context("Failed to build the email message")?;
+    Ok(message)
+}
+
+fn message_id(message: &Message) -> Option<String> {
+    message.headers().get_raw("Message-ID").map(str::to_owned)
+}
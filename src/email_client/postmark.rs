@@ -0,0 +1,463 @@
+use super::RateLimited;
+use super::{Email, EmailSender, MAX_BATCH_SIZE};
+use crate::domain::SubscriberEmail;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client, StatusCode};
+use secrecy::{ExposeSecret, Secret};
+
+/// Sends emails through Postmark's `/email` HTTP API.
+pub struct PostmarkEmailClient {
+    http_client: Client,
+    base_url: String,
+    sender: SubscriberEmail,
+    authorization_token: Secret<String>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<MessageHeader<'a>>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct MessageHeader<'a> {
+    name: &'a str,
+    value: &'a str,
+}
+
+#[derive(serde::Deserialize)]
+struct SendEmailResponse {
+    #[serde(rename = "MessageID")]
+    message_id: String,
+}
+
+// One entry per message of a `/email/batch` request, in the same order
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BatchResponseEntry {
+    error_code: i64,
+    #[serde(default)]
+    message: String,
+    #[serde(rename = "MessageID", default)]
+    message_id: Option<String>,
+}
+
+impl PostmarkEmailClient {
+    pub fn new(
+        base_url: String,
+        sender: SubscriberEmail,
+        authorization_token: Secret<String>,
+        timeout: std::time::Duration,
+    ) -> Self {
+        let client = Client::builder().timeout(timeout).build().unwrap();
+        Self {
+            http_client: client,
+            base_url,
+            sender,
+            authorization_token,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for PostmarkEmailClient {
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<Option<String>, anyhow::Error> {
+        let url = format!("{}/email", self.base_url);
+        let request_body = SendEmailRequest {
+            from: self.sender.as_ref(),
+            to: recipient.as_ref(),
+            subject,
+            html_body: html_content,
+            text_body: text_content,
+            headers: headers
+                .iter()
+                .map(|&(name, value)| MessageHeader { name, value })
+                .collect(),
+        };
+        let response = self
+            .http_client
+            .post(&url)
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .json(&request_body)
+            .send()
+            .await?;
+        check_rate_limit(&response)?;
+        let body = response.error_for_status()?.bytes().await?;
+        let message_id = serde_json::from_slice::<SendEmailResponse>(&body)
+            .ok()
+            .map(|r| r.message_id);
+        Ok(message_id)
+    }
+
+    fn supports_batch(&self) -> bool {
+        true
+    }
+
+    async fn send_batch(
+        &self,
+        emails: &[Email],
+    ) -> Result<Vec<Result<Option<String>, anyhow::Error>>, anyhow::Error> {
+        anyhow::ensure!(
+            emails.len() <= MAX_BATCH_SIZE,
+            "Postmark accepts at most {} messages per batch",
+            MAX_BATCH_SIZE
+        );
+        let url = format!("{}/email/batch", self.base_url);
+        let request_body: Vec<SendEmailRequest> = emails
+            .iter()
+            .map(|email| SendEmailRequest {
+                from: self.sender.as_ref(),
+                to: email.recipient.as_ref(),
+                subject: &email.subject,
+                html_body: &email.html_content,
+                text_body: &email.text_content,
+                headers: email
+                    .headers
+                    .iter()
+                    .map(|(name, value)| MessageHeader { name, value })
+                    .collect(),
+            })
+            .collect();
+        let response = self
+            .http_client
+            .post(&url)
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .json(&request_body)
+            .send()
+            .await?;
+        check_rate_limit(&response)?;
+        let body = response.error_for_status()?.bytes().await?;
+        // The batch was accepted: if the per-message results can't be read we
+        // treat every message as sent rather than risk sending it twice.
+        let entries = serde_json::from_slice::<Vec<BatchResponseEntry>>(&body)
+            .ok()
+            .filter(|entries| entries.len() == emails.len());
+        let Some(entries) = entries else {
+            return Ok(emails.iter().map(|_| Ok(None)).collect());
+        };
+        let outcomes = entries
+            .into_iter()
+            .map(|entry| match entry.error_code {
+                0 => Ok(entry.message_id),
+                code => Err(anyhow::anyhow!(
+                    "Postmark rejected the message (error code {}): {}",
+                    code,
+                    entry.message
+                )),
+            })
+            .collect();
+        Ok(outcomes)
+    }
+}
+
+// Postmark answers 429 when we exceed its rate limits
+fn check_rate_limit(response: &reqwest::Response) -> Result<(), RateLimited> {
+    if response.status() != StatusCode::TOO_MANY_REQUESTS {
+        return Ok(());
+    }
+    let retry_after = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+        .map(std::time::Duration::from_secs);
+    Err(RateLimited { retry_after })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{Email, EmailSender, PostmarkEmailClient, RateLimited};
+    use claims::{assert_err, assert_ok};
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::lorem::en::{Paragraph, Sentence};
+    use fake::{Fake, Faker};
+    use secrecy::Secret;
+    use wiremock::matchers::{body_partial_json, header, header_exists, method, path};
+    use wiremock::{Match, Request};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    struct SendEmailBodyMatcher;
+
+    impl Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                body.get("From").is_some()
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("HtmlBody").is_some()
+                    && body.get("TextBody").is_some()
+            } else {
+                false
+            }
+        }
+    }
+
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+
+    fn content() -> String {
+        Paragraph(1..10).fake()
+    }
+
+    // generate random email
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    // get a instance of `PostmarkEmailClient`
+    fn email_client(base_url: String) -> PostmarkEmailClient {
+        PostmarkEmailClient::new(
+            base_url,
+            email(),
+            Secret::new(Faker.fake()),
+            std::time::Duration::from_millis(200),
+        )
+    }
+
+    #[tokio::test]
+    async fn send_email_succeeds_if_the_server_returns_200() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let email_client = email_client(mock_server.uri());
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), &[])
+            .await;
+
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_returns_500() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let email_client = email_client(mock_server.uri());
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), &[])
+            .await;
+
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_returns_the_provider_message_id() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "To": "receiver@example.com",
+                "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817",
+                "ErrorCode": 0,
+                "Message": "OK"
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let email_client = email_client(mock_server.uri());
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), &[])
+            .await;
+
+        assert_eq!(
+            outcome.unwrap().as_deref(),
+            Some("b7bc2f4a-e38e-4336-af7d-e6c392c2f817")
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_passes_extra_headers_to_the_provider() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .and(body_partial_json(serde_json::json!({
+                "Headers": [
+                    { "Name": "List-Unsubscribe-Post", "Value": "List-Unsubscribe=One-Click" }
+                ]
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let email_client = email_client(mock_server.uri());
+
+        let outcome = email_client
+            .send_email(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                &[("List-Unsubscribe-Post", "List-Unsubscribe=One-Click")],
+            )
+            .await;
+
+        assert_ok!(outcome);
+    }
+
+    fn batch(n: usize) -> Vec<Email> {
+        (0..n)
+            .map(|_| Email {
+                recipient: email(),
+                subject: subject(),
+                html_content: content(),
+                text_content: content(),
+                headers: vec![],
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn send_batch_maps_results_back_to_each_recipient() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(path("/email/batch"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "ErrorCode": 0, "Message": "OK", "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817" },
+                { "ErrorCode": 300, "Message": "Invalid email request" }
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let email_client = email_client(mock_server.uri());
+
+        let outcomes = email_client.send_batch(&batch(2)).await.unwrap();
+
+        assert_eq!(
+            outcomes[0].as_ref().unwrap().as_deref(),
+            Some("b7bc2f4a-e38e-4336-af7d-e6c392c2f817")
+        );
+        assert_err!(&outcomes[1]);
+    }
+
+    #[tokio::test]
+    async fn send_batch_fails_if_the_server_returns_500() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(path("/email/batch"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let email_client = email_client(mock_server.uri());
+
+        let outcome = email_client.send_batch(&batch(2)).await;
+
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_batch_reports_rate_limiting_if_the_server_returns_429() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(path("/email/batch"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "30"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let email_client = email_client(mock_server.uri());
+
+        let e = email_client.send_batch(&batch(2)).await.unwrap_err();
+
+        let rate_limited = e.downcast_ref::<RateLimited>().unwrap();
+        assert_eq!(
+            rate_limited.retry_after,
+            Some(std::time::Duration::from_secs(30))
+        );
+    }
+
+    #[tokio::test]
+    async fn send_batch_rejects_batches_over_the_postmark_limit() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(path("/email/batch"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        let email_client = email_client(mock_server.uri());
+
+        let outcome = email_client.send_batch(&batch(501)).await;
+
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_the_server_takes_too_long() {
+        let mock_server = MockServer::start().await;
+        let response = ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180));
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let email_client = email_client(mock_server.uri());
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), &[])
+            .await;
+
+        assert_err!(outcome);
+    }
+}
@@ -0,0 +1,273 @@
+use chrono::{DateTime, Utc};
+use minijinja::{context, Value};
+use sqlx::{PgConnection, PgPool};
+
+use crate::email_template::{EmailTemplate, RenderedEmail, TemplateError};
+
+/// The emails whose layout admins can change.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LayoutName {
+    // Sent when someone subscribes, with the link confirming their subscription
+    Confirmation,
+    // Sent once a subscription is confirmed. There is none until an admin writes one.
+    Welcome,
+    // Wraps every newsletter issue
+    Issue,
+}
+
+impl LayoutName {
+    pub const ALL: [LayoutName; 3] = [
+        LayoutName::Confirmation,
+        LayoutName::Welcome,
+        LayoutName::Issue,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LayoutName::Confirmation => "confirmation",
+            LayoutName::Welcome => "welcome",
+            LayoutName::Issue => "issue",
+        }
+    }
+
+    /// The variables the layout can use.
+    pub fn variables(&self) -> &'static [&'static str] {
+        match self {
+            LayoutName::Confirmation => &["name", "list_name", "confirmation_link"],
+            LayoutName::Welcome => &["name", "list_name"],
+            LayoutName::Issue => &["title", "content", "unsubscribe_url", "web_url"],
+        }
+    }
+
+    /// The variables both bodies must use: confirmations need their link and
+    /// issues a way to unsubscribe.
+    pub fn required_variables(&self) -> &'static [&'static str] {
+        match self {
+            LayoutName::Confirmation => &["confirmation_link"],
+            LayoutName::Welcome => &[],
+            LayoutName::Issue => &["unsubscribe_url"],
+        }
+    }
+
+    /// The layout used until an admin saves their own.
+    pub fn default_layout(&self) -> Option<Layout> {
+        let (subject, html_content, text_content) = match self {
+            LayoutName::Confirmation => (
+                "Welcome!",
+                "Welcome to our newsletter!<br />\
+                Click <a href=\"{{ confirmation_link }}\">here</a> to confirm your subscription.",
+                "Welcome to our newsletter!\n\
+                Visit {{ confirmation_link }} to confirm your subscription.",
+            ),
+            LayoutName::Welcome => return None,
+            LayoutName::Issue => (
+                "{{ title }}",
+                "{{ content }}<p><a href=\"{{ unsubscribe_url }}\">Unsubscribe</a> from this newsletter.\
+                {% if web_url %} <a href=\"{{ web_url }}\">View in your browser</a>{% endif %}</p>",
+                "{{ content }}\n\nUnsubscribe from this newsletter: {{ unsubscribe_url }}\
+                {% if web_url %}\nView in your browser: {{ web_url }}{% endif %}",
+            ),
+        };
+        Some(Layout {
+            subject: subject.into(),
+            html_content: html_content.into(),
+            text_content: text_content.into(),
+            updated_at: None,
+        })
+    }
+
+    // Placeholder values, used to check layouts before they are saved
+    fn sample_context(&self) -> Value {
+        match self {
+            LayoutName::Confirmation => context! {
+                name => "Subscriber",
+                list_name => "Newsletter",
+                confirmation_link => "https://example.com/subscriptions/confirm",
+            },
+            LayoutName::Welcome => context! {
+                name => "Subscriber",
+                list_name => "Newsletter",
+            },
+            LayoutName::Issue => context! {
+                title => "Issue title",
+                content => "Issue content",
+                unsubscribe_url => "https://example.com/subscriptions/unsubscribe",
+                web_url => "https://example.com/issues/issue-title",
+            },
+        }
+    }
+}
+
+impl TryFrom<String> for LayoutName {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "confirmation" => Ok(LayoutName::Confirmation),
+            "welcome" => Ok(LayoutName::Welcome),
+            "issue" => Ok(LayoutName::Issue),
+            other => Err(format!("{} is not a layout.", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Layout {
+    pub subject: String,
+    pub html_content: String,
+    pub text_content: String,
+    // `None` for the built-in defaults
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl Layout {
+    pub fn compile(&self, name: LayoutName) -> Result<EmailTemplate, TemplateError> {
+        EmailTemplate::compile(
+            &self.subject,
+            &self.html_content,
+            &self.text_content,
+            name.variables(),
+        )
+    }
+
+    /// Compiles the layout, checks that it uses its required variables and
+    /// renders it once with placeholder values.
+    pub fn validate(&self, name: LayoutName) -> Result<(), TemplateError> {
+        let template = self.compile(name)?;
+        for variable in name.required_variables() {
+            template.require_in_bodies(variable)?;
+        }
+        template.render(&name.sample_context())?;
+        Ok(())
+    }
+
+    pub fn render(&self, name: LayoutName, ctx: &Value) -> Result<RenderedEmail, TemplateError> {
+        self.compile(name)?.render(ctx)
+    }
+}
+
+/// The layout saved by an admin, or the built-in default.
+#[tracing::instrument(name = "Get email layout", skip(connection))]
+pub async fn get_layout(
+    connection: &mut PgConnection,
+    name: LayoutName,
+) -> Result<Option<Layout>, sqlx::Error> {
+    let saved = sqlx::query!(
+        r#"
+        SELECT subject, html_content, text_content, updated_at
+        FROM templates
+        WHERE name = $1
+        "#,
+        name.as_str()
+    )
+    .fetch_optional(connection)
+    .await?;
+    Ok(match saved {
+        Some(r) => Some(Layout {
+            subject: r.subject,
+            html_content: r.html_content,
+            text_content: r.text_content,
+            updated_at: Some(r.updated_at),
+        }),
+        None => name.default_layout(),
+    })
+}
+
+#[tracing::instrument(name = "Save email layout", skip(pool, layout))]
+pub async fn save_layout(
+    pool: &PgPool,
+    name: LayoutName,
+    layout: &Layout,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO templates (name, subject, html_content, text_content, updated_at)
+        VALUES ($1, $2, $3, $4, now())
+        ON CONFLICT (name) DO UPDATE
+        SET subject = EXCLUDED.subject,
+            html_content = EXCLUDED.html_content,
+            text_content = EXCLUDED.text_content,
+            updated_at = EXCLUDED.updated_at
+        "#,
+        name.as_str(),
+        layout.subject,
+        layout.html_content,
+        layout.text_content
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Goes back to the built-in default.
+#[tracing::instrument(name = "Reset email layout", skip(pool))]
+pub async fn reset_layout(pool: &PgPool, name: LayoutName) -> Result<(), sqlx::Error> {
+    sqlx::query!(r#"DELETE FROM templates WHERE name = $1"#, name.as_str())
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LayoutName;
+    use claims::{assert_err, assert_ok};
+    use minijinja::{context, Value};
+
+    #[test]
+    fn the_default_layouts_are_valid() {
+        for name in LayoutName::ALL {
+            if let Some(layout) = name.default_layout() {
+                assert_ok!(layout.validate(name), "{}", name.as_str());
+            }
+        }
+    }
+
+    #[test]
+    fn the_default_confirmation_email_keeps_its_wording() {
+        let layout = LayoutName::Confirmation.default_layout().unwrap();
+        let link = "http://127.0.0.1/subscriptions/confirm?subscription_token=abc";
+
+        let rendered = layout
+            .render(
+                LayoutName::Confirmation,
+                &context! { confirmation_link => Value::from_safe_string(link.into()) },
+            )
+            .unwrap();
+
+        assert_eq!(rendered.subject, "Welcome!");
+        assert_eq!(
+            rendered.html_content,
+            format!(
+                "Welcome to our newsletter!<br />\
+                Click <a href=\"{}\">here</a> to confirm your subscription.",
+                link
+            )
+        );
+        assert_eq!(
+            rendered.text_content,
+            format!(
+                "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
+                link
+            )
+        );
+    }
+
+    #[test]
+    fn layouts_only_see_their_own_variables() {
+        let mut layout = LayoutName::Confirmation.default_layout().unwrap();
+        layout.html_content = "{{ confirmation_link }}".into();
+        assert_err!(layout.validate(LayoutName::Welcome));
+        assert_ok!(layout.validate(LayoutName::Confirmation));
+    }
+
+    #[test]
+    fn layouts_must_use_their_required_variables() {
+        let mut layout = LayoutName::Issue.default_layout().unwrap();
+        layout.text_content = "{{ content }}".into();
+        assert_err!(layout.validate(LayoutName::Issue));
+
+        let mut layout = LayoutName::Confirmation.default_layout().unwrap();
+        layout.html_content = "<p>Welcome, {{ name }}!</p>".into();
+        assert_err!(layout.validate(LayoutName::Confirmation));
+    }
+}
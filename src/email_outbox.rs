@@ -0,0 +1,256 @@
+use std::ops::DerefMut;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::SubscriberEmail;
+use crate::email_client::{EmailSender, RateLimited};
+use crate::issue_delivery_worker::{backoff, ExecutionOutcome, PgTransaction, MAX_RETRIES};
+use crate::rate_limiter::SendRateLimiter;
+use crate::suppressions::is_suppressed;
+
+/// Postgres channel notified whenever an email is written to the outbox.
+pub const EMAIL_OUTBOX_CHANNEL: &str = "email_outbox";
+
+/// Writes an email to the outbox as part of `transaction`: it is sent by the
+/// background workers if, and only if, the transaction commits.
+#[tracing::instrument(name = "Add an email to the outbox", skip_all)]
+pub async fn enqueue_email(
+    transaction: &mut PgTransaction,
+    recipient: &SubscriberEmail,
+    subject: &str,
+    html_content: &str,
+    text_content: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_outbox (
+            email_outbox_id,
+            recipient,
+            subject,
+            html_content,
+            text_content
+        )
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        Uuid::new_v4(),
+        recipient.as_ref(),
+        subject,
+        html_content,
+        text_content
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    // Delivered on commit, waking up idle workers
+    sqlx::query!("SELECT pg_notify($1, '')", EMAIL_OUTBOX_CHANNEL)
+        .execute(transaction.deref_mut())
+        .await?;
+    Ok(())
+}
+
+// Locks a single outbox email and sends it within that transaction. Outbox
+// emails are low volume and someone is usually waiting on them, so unlike
+// newsletter deliveries, which are claimed in batches and settled one row per
+// transaction, they go out one at a time and a slow send holds no other
+// email's lock. Sent emails leave the outbox, failed ones are retried with the
+// same backoff as newsletter deliveries.
+#[tracing::instrument(skip_all, err)]
+pub async fn try_dispatch_email(
+    pool: &PgPool,
+    email_client: &dyn EmailSender,
+    rate_limiter: &SendRateLimiter,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let Some((mut transaction, entry)) = dequeue_email(pool).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+
+    let recipient = match SubscriberEmail::parse(entry.recipient.clone()) {
+        Ok(recipient) => recipient,
+        Err(e) => {
+            tracing::error!(e.cause_chain = ?e, e.message = %e, recipient = %entry.recipient, "Giving up on an outbox email. \
+              Its recipient is invalid");
+            move_to_failures(&mut transaction, &entry, &e.to_string()).await?;
+            transaction.commit().await?;
+            return Ok(ExecutionOutcome::TaskCompleted);
+        }
+    };
+    // The address may have been suppressed after the email was queued
+    if is_suppressed(&mut transaction, recipient.as_ref()).await? {
+        tracing::info!(recipient = %entry.recipient, "Dropping an outbox email. \
+          Its recipient is suppressed");
+        delete_email(&mut transaction, entry.email_outbox_id).await?;
+        transaction.commit().await?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    }
+    rate_limiter.until_ready(&recipient).await;
+    let outcome = email_client
+        .send_email(
+            &recipient,
+            &entry.subject,
+            &entry.html_content,
+            &entry.text_content,
+            &[],
+        )
+        .await;
+    match outcome {
+        Ok(_) => {
+            rate_limiter.record_success();
+            delete_email(&mut transaction, entry.email_outbox_id).await?;
+        }
+        Err(e) => match e.downcast_ref::<RateLimited>() {
+            Some(rate_limited) => {
+                let pause = rate_limiter.back_off(rate_limited.retry_after);
+                tracing::warn!(
+                    ?pause,
+                    "The email provider is rate limiting us. Pausing outbox emails."
+                );
+                defer_email(&mut transaction, entry.email_outbox_id, pause).await?;
+            }
+            None => handle_failed_email(&mut transaction, &entry, &e).await?,
+        },
+    }
+
+    transaction.commit().await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+async fn handle_failed_email(
+    transaction: &mut PgTransaction,
+    entry: &OutboxEmail,
+    e: &anyhow::Error,
+) -> Result<(), anyhow::Error> {
+    if entry.n_retries + 1 >= MAX_RETRIES {
+        tracing::error!(e.cause_chain = ?e, e.message = %e, recipient = %entry.recipient, "Failed to send an outbox email. \
+          Giving up.");
+        move_to_failures(transaction, entry, &e.to_string()).await
+    } else {
+        tracing::warn!(e.cause_chain = ?e, e.message = %e, recipient = %entry.recipient, "Failed to send an outbox email. \
+          Retrying later.");
+        retry_email(transaction, entry).await
+    }
+}
+
+struct OutboxEmail {
+    email_outbox_id: Uuid,
+    recipient: String,
+    subject: String,
+    html_content: String,
+    text_content: String,
+    n_retries: i16,
+    created_at: DateTime<Utc>,
+}
+
+// Locks the next due email. Rows already claimed by another worker are skipped.
+async fn dequeue_email(pool: &PgPool) -> Result<Option<(PgTransaction, OutboxEmail)>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let entry = sqlx::query_as!(
+        OutboxEmail,
+        r#"
+        SELECT
+            email_outbox_id,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            n_retries,
+            created_at
+        FROM email_outbox
+        WHERE execute_after <= now()
+        ORDER BY execute_after
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(transaction.deref_mut())
+    .await?;
+    Ok(entry.map(|entry| (transaction, entry)))
+}
+
+// Keeps the email and why it failed for the admins to look into. Subscribing
+// again queues a fresh confirmation email.
+async fn move_to_failures(
+    transaction: &mut PgTransaction,
+    entry: &OutboxEmail,
+    last_error: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_outbox_failures (
+            email_outbox_id,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            n_retries,
+            last_error,
+            created_at,
+            failed_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, now())
+        "#,
+        entry.email_outbox_id,
+        entry.recipient,
+        entry.subject,
+        entry.html_content,
+        entry.text_content,
+        entry.n_retries + 1,
+        last_error,
+        entry.created_at
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    delete_email(transaction, entry.email_outbox_id).await
+}
+
+async fn delete_email(
+    transaction: &mut PgTransaction,
+    email_outbox_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"DELETE FROM email_outbox WHERE email_outbox_id = $1"#,
+        email_outbox_id
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    Ok(())
+}
+
+async fn retry_email(
+    transaction: &mut PgTransaction,
+    entry: &OutboxEmail,
+) -> Result<(), anyhow::Error> {
+    let execute_after = Utc::now() + chrono::Duration::from_std(backoff(entry.n_retries))?;
+    sqlx::query!(
+        r#"
+        UPDATE email_outbox
+        SET
+            n_retries = n_retries + 1,
+            execute_after = $2
+        WHERE email_outbox_id = $1
+        "#,
+        entry.email_outbox_id,
+        execute_after
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    Ok(())
+}
+
+async fn defer_email(
+    transaction: &mut PgTransaction,
+    email_outbox_id: Uuid,
+    delay: Duration,
+) -> Result<(), anyhow::Error> {
+    let execute_after = Utc::now() + chrono::Duration::from_std(delay)?;
+    sqlx::query!(
+        r#"UPDATE email_outbox SET execute_after = $2 WHERE email_outbox_id = $1"#,
+        email_outbox_id,
+        execute_after
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,147 @@
+use minijinja::{Environment, Value};
+
+// Bounds the work a single render can do, whatever loops the template contains
+const FUEL_PER_RENDER: u64 = 50_000;
+
+const SUBJECT: &str = "subject.txt";
+// The `.html` extension turns on HTML escaping of variables
+const HTML_CONTENT: &str = "content.html";
+const TEXT_CONTENT: &str = "content.txt";
+
+#[derive(thiserror::Error, Debug)]
+pub enum TemplateError {
+    #[error("The {part} is not a valid template: {source}")]
+    Syntax {
+        part: &'static str,
+        source: minijinja::Error,
+    },
+    #[error("The {part} uses `{tag}`, which is not a merge tag. Use one of: {}.", allowed.join(", "))]
+    UnknownMergeTag {
+        part: &'static str,
+        tag: String,
+        allowed: &'static [&'static str],
+    },
+    #[error("The {part} must use `{{{{ {variable} }}}}`.")]
+    MissingMergeTag {
+        part: &'static str,
+        variable: &'static str,
+    },
+    #[error("The {part} could not be rendered: {source}")]
+    Render {
+        part: &'static str,
+        source: minijinja::Error,
+    },
+}
+
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html_content: String,
+    pub text_content: String,
+}
+
+/// The subject, HTML and text bodies of an email, compiled once and rendered
+/// as many times as needed. Templates only see the variables they are given:
+/// there is no access to files or to anything else on the server.
+pub struct EmailTemplate {
+    env: Environment<'static>,
+}
+
+impl EmailTemplate {
+    /// Fails on syntax errors and on variables outside of `allowed`, which
+    /// would otherwise silently render as empty strings.
+    pub fn compile(
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        allowed: &'static [&'static str],
+    ) -> Result<Self, TemplateError> {
+        let mut env = Environment::new();
+        env.set_fuel(Some(FUEL_PER_RENDER));
+        // Content is sent as written, down to the last newline
+        env.set_keep_trailing_newline(true);
+        for (name, source) in [
+            (SUBJECT, subject),
+            (HTML_CONTENT, html_content),
+            (TEXT_CONTENT, text_content),
+        ] {
+            env.add_template_owned(name, source.to_owned())
+                .map_err(|source| TemplateError::Syntax {
+                    part: part(name),
+                    source,
+                })?;
+        }
+        for (name, template) in env.templates() {
+            let mut unknown: Vec<String> = template
+                .undeclared_variables(false)
+                .into_iter()
+                .filter(|tag| !allowed.contains(&tag.as_str()))
+                .filter(|tag| !env.globals().any(|(global, _)| global == tag))
+                .collect();
+            unknown.sort();
+            if let Some(tag) = unknown.into_iter().next() {
+                return Err(TemplateError::UnknownMergeTag {
+                    part: part(name),
+                    tag,
+                    allowed,
+                });
+            }
+        }
+        Ok(Self { env })
+    }
+
+    /// Fails unless both bodies use `variable`, e.g. a link that every email
+    /// built from the template must contain.
+    pub fn require_in_bodies(&self, variable: &'static str) -> Result<(), TemplateError> {
+        for name in [HTML_CONTENT, TEXT_CONTENT] {
+            let template = self
+                .env
+                .get_template(name)
+                .expect("Both bodies are added on compilation");
+            if !template.undeclared_variables(false).contains(variable) {
+                return Err(TemplateError::MissingMergeTag {
+                    part: part(name),
+                    variable,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Renders the three parts with `ctx`, e.g. built with `minijinja::context!`.
+    /// Values we generate ourselves, such as links, can be passed with
+    /// `Value::from_safe_string` to be left unescaped in the HTML body.
+    pub fn render(&self, ctx: &Value) -> Result<RenderedEmail, TemplateError> {
+        self.render_html_and_text(ctx, ctx)
+    }
+
+    /// Like `render`, for variables whose value depends on the format, e.g.
+    /// the body of an issue wrapped in a layout. The subject is plain text.
+    pub fn render_html_and_text(
+        &self,
+        html_ctx: &Value,
+        text_ctx: &Value,
+    ) -> Result<RenderedEmail, TemplateError> {
+        let render = |name: &'static str, ctx: &Value| {
+            self.env
+                .get_template(name)
+                .and_then(|template| template.render(ctx))
+                .map_err(|source| TemplateError::Render {
+                    part: part(name),
+                    source,
+                })
+        };
+        Ok(RenderedEmail {
+            subject: render(SUBJECT, text_ctx)?,
+            html_content: render(HTML_CONTENT, html_ctx)?,
+            text_content: render(TEXT_CONTENT, text_ctx)?,
+        })
+    }
+}
+
+fn part(template_name: &str) -> &'static str {
+    match template_name {
+        SUBJECT => "subject",
+        HTML_CONTENT => "HTML content",
+        _ => "text content",
+    }
+}
@@ -1,7 +1,7 @@
 use actix_web::HttpResponse;
 use actix_web::{body::to_bytes, http::StatusCode};
 use sqlx::postgres::PgHasArrayType;
-use sqlx::{Executor, PgPool, Postgres, Transaction};
+use sqlx::{PgPool, Postgres, Transaction};
 use std::ops::DerefMut;
 use uuid::Uuid;
 
@@ -53,7 +53,6 @@ pub async fn get_saved_response(
 }
 
 pub async fn save_response(
-    pool: &PgPool,
     idempotency_key: &IdempotencyKey,
     user_id: Uuid,
     response: HttpResponse,
@@ -97,6 +96,7 @@ pub async fn save_response(
     Ok(http_response)
 }
 
+#[allow(clippy::large_enum_variant)]
 pub enum NextAction {
     StartProcessing(Transaction<'static, Postgres>),
     ReturnSavedResponse(HttpResponse),
@@ -125,7 +125,7 @@ pub async fn try_processing(
     .rows_affected();
 
     if n_inserted_rows > 0 {
-        return Ok(NextAction::StartProcessing(transaction));
+        Ok(NextAction::StartProcessing(transaction))
     } else {
         let saved_response = get_saved_response(pool, idempotency_key, user_id)
             .await?
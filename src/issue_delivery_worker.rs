@@ -1,92 +1,582 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
 use std::ops::DerefMut;
+use std::sync::Arc;
 use std::time::Duration;
 
+use chrono::Utc;
+use minijinja::{context, Value};
+use rand::{thread_rng, Rng};
+use sqlx::postgres::PgListener;
 use sqlx::PgPool;
 use sqlx::{Postgres, Transaction};
-use tracing::{field::display, Span};
+use tokio::sync::Notify;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use tracing::Span;
 use uuid::Uuid;
 
-use crate::configuration::Settings;
-use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
-use crate::startup::get_database_pool;
+use crate::configuration::{Settings, WorkerSettings};
+use crate::domain::{SubscriberEmail, UnsubscribeToken};
+use crate::email_client::{Email, EmailSender, RateLimited, MAX_BATCH_SIZE};
+use crate::email_layout::{get_layout, LayoutName};
+use crate::email_outbox::{try_dispatch_email, EMAIL_OUTBOX_CHANNEL};
+use crate::email_template::{EmailTemplate, TemplateError};
+use crate::issue_template::{IssueTemplate, MergeTags};
+use crate::rate_limiter::SendRateLimiter;
+use crate::routes::enqueue_delivery_tasks;
+use crate::startup::{get_database_pool, ApplicationBaseUrl, HmacSecret};
+use crate::suppressions::is_suppressed;
+use crate::tracking::add_tracking;
+
+// Maximum number of delivery attempts before a task is moved to the dead-letter table
+pub(crate) const MAX_RETRIES: i16 = 8;
+const BASE_BACKOFF: Duration = Duration::from_secs(10);
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+// How long claimed tasks stay out of reach of the other workers
+const CLAIM_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+// Tasks claimed at once when the transport sends emails one by one
+const PER_EMAIL_BATCH_SIZE: usize = 20;
+
+/// Postgres channel notified whenever new delivery tasks are enqueued.
+pub const DELIVERY_QUEUE_CHANNEL: &str = "issue_delivery_queue";
 
 pub enum ExecutionOutcome {
     TaskCompleted,
     EmptyQueue,
 }
 
-#[tracing::instrument(skip_all, fields(newsletter_issue_id=tracing::field::Empty, subscriber_email=tracing::field::Empty), err)]
+// Claims a batch of tasks and sends them, in a single call to the email
+// provider if it has a batch API or one by one otherwise. Each task is then
+// settled in a transaction of its own, so that a failure on one recipient
+// never rolls back the emails already sent to the others. Once `shutdown` is
+// cancelled, tasks that were not sent yet go back to the queue.
+#[tracing::instrument(skip_all, fields(n_tasks=tracing::field::Empty), err)]
 pub async fn try_execute_task(
     pool: &PgPool,
-    email_client: &EmailClient,
+    email_client: &dyn EmailSender,
+    base_url: &ApplicationBaseUrl,
+    hmac_secret: &HmacSecret,
+    rate_limiter: &SendRateLimiter,
+    shutdown: &CancellationToken,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
-    let task = dequeue_task(pool).await?;
-    if task.is_none() {
+    update_issue_statuses(pool).await?;
+    let batch_size = if email_client.supports_batch() {
+        MAX_BATCH_SIZE
+    } else {
+        // Without a batch API, claiming more only delays other workers
+        PER_EMAIL_BATCH_SIZE
+    };
+    let tasks = claim_tasks(pool, batch_size as i64).await?;
+    if tasks.is_empty() {
         return Ok(ExecutionOutcome::EmptyQueue);
     }
-    if let (transaction, issue_id, email) = task.unwrap() {
-        Span::current().record("newsletter_issus_id", &display(issue_id));
-        Span::current().record("subscriber_email", &display(&email));
-
-        match SubscriberEmail::parse(email.clone()) {
-            Ok(email) => {
-                let issue = get_issue(pool, issue_id).await?;
-                if let Err(e) = email_client
-                    .send_email(
-                        &email,
-                        &issue.title,
-                        &issue.html_content,
-                        &issue.text_content,
-                    )
-                    .await
-                {
-                    tracing::error!(e.cause_chain = ?e, e.message = %e, "Failed to deliver issue to a confirmed subscriber. \
-                      Skipping.");
-                }
-            }
-            Err(e) => {
-                tracing::error!(e.cause_chain = ?e, e.message = %e, "Skipping a confirmed subscriber. \
-                  Their stored contact details are invalid");
+    Span::current().record("n_tasks", tasks.len());
+
+    let layout = get_issue_layout(pool).await?;
+    let mut issues = HashMap::new();
+    let mut batch = Vec::with_capacity(tasks.len());
+    let mut tasks = tasks.into_iter();
+    while let Some(task) = tasks.next() {
+        if shutdown.is_cancelled() {
+            let unsent = batch.into_iter().map(|(task, _)| task);
+            release_tasks(pool, unsent.chain(std::iter::once(task)).chain(tasks)).await?;
+            return Ok(ExecutionOutcome::TaskCompleted);
+        }
+        let Some(email) =
+            prepare_email(pool, &task, &layout, &mut issues, base_url, hmac_secret).await?
+        else {
+            continue;
+        };
+        rate_limiter.until_ready(&email.recipient).await;
+        if email_client.supports_batch() {
+            batch.push((task, email));
+            continue;
+        }
+        let headers: Vec<(&str, &str)> = email
+            .headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        let outcome = email_client
+            .send_email(
+                &email.recipient,
+                &email.subject,
+                &email.html_content,
+                &email.text_content,
+                &headers,
+            )
+            .await;
+        match outcome {
+            Ok(message_id) => {
+                rate_limiter.record_success();
+                settle_task(pool, &task, Settlement::Sent(message_id.as_deref())).await?;
             }
+            Err(e) => match e.downcast_ref::<RateLimited>() {
+                Some(rate_limited) => {
+                    let pause = rate_limiter.back_off(rate_limited.retry_after);
+                    tracing::warn!(
+                        ?pause,
+                        "The email provider is rate limiting us. Pausing deliveries."
+                    );
+                    for task in std::iter::once(task).chain(tasks.by_ref()) {
+                        settle_task(pool, &task, Settlement::Deferred(pause, &e)).await?;
+                    }
+                }
+                None => settle_task(pool, &task, Settlement::SendFailed(&e)).await?,
+            },
         }
-        delete_task(transaction, issue_id, &email).await?;
     }
 
+    if !batch.is_empty() {
+        let (tasks, emails): (Vec<Task>, Vec<Email>) = batch.into_iter().unzip();
+        match email_client.send_batch(&emails).await {
+            Ok(outcomes) => {
+                rate_limiter.record_success();
+                for (task, outcome) in tasks.iter().zip(outcomes) {
+                    let settlement = match &outcome {
+                        Ok(message_id) => Settlement::Sent(message_id.as_deref()),
+                        Err(e) => Settlement::SendFailed(e),
+                    };
+                    settle_task(pool, task, settlement).await?;
+                }
+            }
+            Err(e) => match e.downcast_ref::<RateLimited>() {
+                Some(rate_limited) => {
+                    let pause = rate_limiter.back_off(rate_limited.retry_after);
+                    tracing::warn!(
+                        ?pause,
+                        "The email provider is rate limiting us. Pausing deliveries."
+                    );
+                    for task in &tasks {
+                        settle_task(pool, task, Settlement::Deferred(pause, &e)).await?;
+                    }
+                }
+                None => {
+                    for task in &tasks {
+                        settle_task(pool, task, Settlement::SendFailed(&e)).await?;
+                    }
+                }
+            },
+        }
+    }
+    rate_limiter.forget_idle_domains();
     Ok(ExecutionOutcome::TaskCompleted)
 }
 
-type PgTransaction = Transaction<'static, Postgres>;
+// Renders the email for a task, or settles the task right away when there is
+// nothing to send: the subscriber left, their address is suppressed or the
+// email can't be built.
+async fn prepare_email(
+    pool: &PgPool,
+    task: &Task,
+    layout: &EmailTemplate,
+    issues: &mut HashMap<Uuid, Result<CompiledIssue, TemplateError>>,
+    base_url: &ApplicationBaseUrl,
+    hmac_secret: &HmacSecret,
+) -> Result<Option<Email>, anyhow::Error> {
+    let Some(subscriber) = get_confirmed_subscriber(pool, &task.email).await? else {
+        tracing::info!(
+            subscriber_email = %task.email,
+            "Skipping a subscriber who is no longer confirmed."
+        );
+        settle_task(pool, task, Settlement::Skipped(None)).await?;
+        return Ok(None);
+    };
+    if is_suppressed(&mut *pool.acquire().await?, &task.email).await? {
+        tracing::info!(
+            subscriber_email = %task.email,
+            "Skipping a subscriber whose address is suppressed."
+        );
+        settle_task(
+            pool,
+            task,
+            Settlement::Skipped(Some("The address is suppressed.")),
+        )
+        .await?;
+        return Ok(None);
+    }
+    let recipient = match SubscriberEmail::parse(task.email.clone()) {
+        Ok(recipient) => recipient,
+        Err(e) => {
+            tracing::error!(e.cause_chain = ?e, e.message = %e, subscriber_email = %task.email, "Skipping a confirmed subscriber. \
+              Their stored contact details are invalid");
+            settle_task(pool, task, Settlement::Unsendable(&e)).await?;
+            return Ok(None);
+        }
+    };
+    let issue = match issues.entry(task.issue_id) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => entry.insert(get_issue(pool, task.issue_id).await?),
+    };
+    // Templates are checked when the issue is published, so this only
+    // catches what slipped through: retrying would fail the same way.
+    let email = issue.as_ref().map_err(|e| e.to_string()).and_then(|issue| {
+        personalize(issue, layout, recipient, &subscriber, base_url, hmac_secret)
+            .map_err(|e| e.to_string())
+    });
+    match email {
+        Ok(email) => Ok(Some(email)),
+        Err(e) => {
+            tracing::error!(error = %e, subscriber_email = %task.email, "Failed to render an issue for a confirmed subscriber");
+            settle_task(pool, task, Settlement::Unsendable(&e)).await?;
+            Ok(None)
+        }
+    }
+}
+
+// What became of a claimed task
+enum Settlement<'a> {
+    Sent(Option<&'a str>),
+    // Nothing to send, with the reason if it is worth recording
+    Skipped(Option<&'a str>),
+    // The email can't be built: retrying would fail the same way
+    Unsendable(&'a str),
+    SendFailed(&'a anyhow::Error),
+    // Being throttled is not the recipient's fault: try again once the pause
+    // is over, without using up a retry.
+    Deferred(Duration, &'a anyhow::Error),
+}
 
-async fn dequeue_task(pool: &PgPool) -> Result<Option<(PgTransaction, Uuid, String)>, sqlx::Error> {
+// Records the outcome of a task and takes it off the queue, or puts it back
+// for later, in a transaction of its own.
+async fn settle_task(
+    pool: &PgPool,
+    task: &Task,
+    settlement: Settlement<'_>,
+) -> Result<(), anyhow::Error> {
     let mut transaction = pool.begin().await?;
-    let row = sqlx::query!(
-        r#"
-        SELECT newsletter_issue_id, subscriber_email
-        FROM issue_delivery_queue
-        FOR UPDATE
-        SKIP LOCKED
-        LIMIT 1
-    "#
-    )
-    .fetch_optional(transaction.deref_mut())
-    .await?;
-    if let Some(r) = row {
-        Ok(Some((
+    match settlement {
+        Settlement::Sent(message_id) => {
+            record_delivery(
+                &mut transaction,
+                task,
+                DeliveryStatus::Sent,
+                true,
+                message_id,
+                None,
+            )
+            .await?;
+            delete_task(&mut transaction, task).await?;
+        }
+        Settlement::Skipped(reason) => {
+            record_delivery(
+                &mut transaction,
+                task,
+                DeliveryStatus::Skipped,
+                false,
+                None,
+                reason,
+            )
+            .await?;
+            delete_task(&mut transaction, task).await?;
+        }
+        Settlement::Unsendable(e) => {
+            record_delivery(
+                &mut transaction,
+                task,
+                DeliveryStatus::Failed,
+                false,
+                None,
+                Some(e),
+            )
+            .await?;
+            move_to_dead_letter(&mut transaction, task, e).await?;
+        }
+        Settlement::SendFailed(e) => handle_failed_delivery(&mut transaction, task, e).await?,
+        Settlement::Deferred(pause, e) => {
+            record_delivery(
+                &mut transaction,
+                task,
+                DeliveryStatus::Pending,
+                false,
+                None,
+                Some(&e.to_string()),
+            )
+            .await?;
+            defer_task(&mut transaction, task, pause).await?;
+        }
+    }
+    transaction.commit().await?;
+    Ok(())
+}
+
+// Renders the merge tags and the issue layout, with its unsubscribe footer
+// and link to the archive, tracks opens and clicks unless the issue opted out, and adds the
+// unsubscribe headers for a specific subscriber.
+fn personalize(
+    issue: &CompiledIssue,
+    layout: &EmailTemplate,
+    recipient: SubscriberEmail,
+    subscriber: &ConfirmedSubscriber,
+    base_url: &ApplicationBaseUrl,
+    hmac_secret: &HmacSecret,
+) -> Result<Email, TemplateError> {
+    let unsubscribe_link = format!(
+        "{}/subscriptions/unsubscribe?token={}",
+        base_url.0,
+        UnsubscribeToken::generate(subscriber.id, &hmac_secret.0).as_ref()
+    );
+    // Left empty for issues kept out of the archive, which have no web version
+    let web_url = issue
+        .path
+        .as_ref()
+        .map(|path| format!("{}/issues/{}", base_url.0, path))
+        .unwrap_or_default();
+    let rendered = issue.template.render(&MergeTags {
+        name: &subscriber.name,
+        email: recipient.as_ref(),
+        unsubscribe_url: &unsubscribe_link,
+        attributes: &subscriber.attributes,
+    })?;
+    let email = layout.render_html_and_text(
+        &context! {
+            title => &rendered.subject,
+            content => Value::from_safe_string(rendered.html_content),
+            unsubscribe_url => Value::from_safe_string(unsubscribe_link.clone()),
+            web_url => Value::from_safe_string(web_url.clone()),
+        },
+        &context! {
+            title => &rendered.subject,
+            content => rendered.text_content,
+            unsubscribe_url => &unsubscribe_link,
+            web_url => &web_url,
+        },
+    )?;
+    let html_content = if issue.tracking {
+        // Unsubscribing must work without going through us first
+        add_tracking(
+            &email.html_content,
+            issue.id,
+            subscriber.id,
+            &base_url.0,
+            &hmac_secret.0,
+            &[&unsubscribe_link],
+        )
+    } else {
+        email.html_content
+    };
+    // RFC 8058 one-click unsubscribe
+    let headers = vec![
+        (
+            "List-Unsubscribe".to_owned(),
+            format!("<{}>", unsubscribe_link),
+        ),
+        (
+            "List-Unsubscribe-Post".to_owned(),
+            "List-Unsubscribe=One-Click".to_owned(),
+        ),
+    ];
+    Ok(Email {
+        recipient,
+        subject: email.subject,
+        html_content,
+        text_content: email.text_content,
+        headers,
+    })
+}
+
+/// An issue as the workers would send it, layout, unsubscribe footer and
+/// headers included, personalized for a placeholder subscriber. Used to send
+/// tests of drafts: opens and clicks aren't tracked, there is no one to
+/// attribute them to.
+/// The outer error is a database failure, the inner one an issue that can't be compiled.
+#[tracing::instrument(skip(pool, base_url, hmac_secret))]
+pub async fn personalize_sample(
+    pool: &PgPool,
+    issue_id: Uuid,
+    recipient: SubscriberEmail,
+    base_url: &ApplicationBaseUrl,
+    hmac_secret: &HmacSecret,
+) -> Result<Result<Email, TemplateError>, anyhow::Error> {
+    let layout = get_issue_layout(pool).await?;
+    let issue = match get_issue(pool, issue_id).await? {
+        Ok(issue) => CompiledIssue {
+            tracking: false,
+            ..issue
+        },
+        Err(e) => return Ok(Err(e)),
+    };
+    let sample = MergeTags::sample();
+    let subscriber = ConfirmedSubscriber {
+        id: Uuid::nil(),
+        name: sample.name.into(),
+        attributes: sample.attributes.clone(),
+    };
+    let sample_email =
+        SubscriberEmail::parse(sample.email.into()).expect("The sample email address is valid");
+    Ok(personalize(
+        &issue,
+        &layout,
+        sample_email,
+        &subscriber,
+        base_url,
+        hmac_secret,
+    )
+    .map(|email| Email { recipient, ..email }))
+}
+
+// Layouts are checked when they are saved. Should a saved one still fail to
+// compile, issues go out with the default layout rather than not at all.
+async fn get_issue_layout(pool: &PgPool) -> Result<EmailTemplate, anyhow::Error> {
+    let mut connection = pool.acquire().await?;
+    let default = || {
+        LayoutName::Issue
+            .default_layout()
+            .expect("Issues always have a default layout")
+    };
+    let layout = get_layout(&mut connection, LayoutName::Issue)
+        .await?
+        .unwrap_or_else(default);
+    match layout.compile(LayoutName::Issue) {
+        Ok(layout) => Ok(layout),
+        Err(e) => {
+            tracing::error!(e.cause_chain = ?e, e.message = %e, "The issue layout is invalid. Using the default one.");
+            Ok(default().compile(LayoutName::Issue)?)
+        }
+    }
+}
+
+async fn handle_failed_delivery(
+    transaction: &mut PgTransaction,
+    task: &Task,
+    e: &anyhow::Error,
+) -> Result<(), anyhow::Error> {
+    let last_error = e.to_string();
+    if task.n_retries + 1 >= MAX_RETRIES {
+        tracing::error!(e.cause_chain = ?e, e.message = %e, subscriber_email = %task.email, "Failed to deliver issue to a confirmed subscriber. \
+          Giving up.");
+        record_delivery(
             transaction,
-            r.newsletter_issue_id,
-            r.subscriber_email,
-        )))
+            task,
+            DeliveryStatus::Failed,
+            true,
+            None,
+            Some(&last_error),
+        )
+        .await?;
+        move_to_dead_letter(transaction, task, &last_error).await
     } else {
-        Ok(None)
+        tracing::warn!(e.cause_chain = ?e, e.message = %e, subscriber_email = %task.email, "Failed to deliver issue to a confirmed subscriber. \
+          Retrying later.");
+        record_delivery(
+            transaction,
+            task,
+            DeliveryStatus::Pending,
+            true,
+            None,
+            Some(&last_error),
+        )
+        .await?;
+        retry_task(transaction, task).await
     }
 }
 
-async fn delete_task(
-    mut transaction: PgTransaction,
+pub(crate) type PgTransaction = Transaction<'static, Postgres>;
+
+// Moves issues along their lifecycle: scheduled issues start sending once
+// their publication time has passed, to whoever is subscribed by then, and
+// sending issues are sent once nothing is left in the queue for them.
+async fn update_issue_statuses(pool: &PgPool) -> Result<(), anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    // Row locks keep two workers from firing the same schedule
+    let fired = sqlx::query_scalar!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'sending', updated_at = now()
+        WHERE status = 'scheduled' AND scheduled_for <= now()
+        RETURNING newsletter_issue_id
+        "#
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+    for newsletter_issue_id in fired {
+        enqueue_delivery_tasks(&mut transaction, newsletter_issue_id).await?;
+    }
+    transaction.commit().await?;
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues i
+        SET status = 'sent', updated_at = now()
+        WHERE
+            i.status = 'sending' AND
+            NOT EXISTS (
+                SELECT 1 FROM issue_delivery_queue q
+                WHERE q.newsletter_issue_id = i.newsletter_issue_id
+            )
+        "#
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+struct Task {
     issue_id: Uuid,
-    email: &str,
-) -> Result<(), anyhow::Error> {
+    email: String,
+    n_retries: i16,
+}
+
+// Claims up to `batch_size` due tasks by pushing them out of reach of the
+// other workers for `CLAIM_TIMEOUT`. The claim is committed right away: no
+// lock is held while emails are being sent. A worker that dies before
+// settling its tasks leaves them to be retried once the claim expires.
+async fn claim_tasks(pool: &PgPool, batch_size: i64) -> Result<Vec<Task>, sqlx::Error> {
+    let claimed_until = Utc::now()
+        + chrono::Duration::from_std(CLAIM_TIMEOUT).expect("The claim timeout is in range");
+    sqlx::query_as!(
+        Task,
+        r#"
+        UPDATE issue_delivery_queue q
+        SET execute_after = $2
+        FROM (
+            SELECT newsletter_issue_id, subscriber_email
+            FROM issue_delivery_queue
+            WHERE execute_after <= now()
+            FOR UPDATE
+            SKIP LOCKED
+            LIMIT $1
+        ) due
+        WHERE
+            q.newsletter_issue_id = due.newsletter_issue_id AND
+            q.subscriber_email = due.subscriber_email
+        RETURNING
+            q.newsletter_issue_id AS issue_id,
+            q.subscriber_email AS email,
+            q.n_retries
+        "#,
+        batch_size,
+        claimed_until
+    )
+    .fetch_all(pool)
+    .await
+}
+
+// Gives up the claim on tasks that were not sent, e.g. on shutdown.
+async fn release_tasks(
+    pool: &PgPool,
+    tasks: impl Iterator<Item = Task>,
+) -> Result<(), sqlx::Error> {
+    let (issue_ids, emails): (Vec<Uuid>, Vec<String>) =
+        tasks.map(|task| (task.issue_id, task.email)).unzip();
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue q
+        SET execute_after = now()
+        FROM UNNEST($1::uuid[], $2::text[]) AS released (newsletter_issue_id, subscriber_email)
+        WHERE
+            q.newsletter_issue_id = released.newsletter_issue_id AND
+            q.subscriber_email = released.subscriber_email
+        "#,
+        &issue_ids,
+        &emails
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+async fn delete_task(transaction: &mut PgTransaction, task: &Task) -> Result<(), anyhow::Error> {
     sqlx::query!(
         r#"
         DELETE FROM issue_delivery_queue
@@ -94,27 +584,235 @@ async fn delete_task(
             newsletter_issue_id = $1 AND
             subscriber_email = $2
         "#,
-        issue_id,
-        email
+        task.issue_id,
+        task.email
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    Ok(())
+}
+
+async fn retry_task(transaction: &mut PgTransaction, task: &Task) -> Result<(), anyhow::Error> {
+    let execute_after = Utc::now() + chrono::Duration::from_std(backoff(task.n_retries))?;
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET
+            n_retries = n_retries + 1,
+            execute_after = $3
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        task.issue_id,
+        task.email,
+        execute_after
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    Ok(())
+}
+
+async fn defer_task(
+    transaction: &mut PgTransaction,
+    task: &Task,
+    delay: Duration,
+) -> Result<(), anyhow::Error> {
+    let execute_after = Utc::now() + chrono::Duration::from_std(delay)?;
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET execute_after = $3
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        task.issue_id,
+        task.email,
+        execute_after
     )
     .execute(transaction.deref_mut())
     .await?;
-    transaction.commit().await?;
     Ok(())
 }
 
+async fn move_to_dead_letter(
+    transaction: &mut PgTransaction,
+    task: &Task,
+    last_error: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_failures (
+          newsletter_issue_id,
+          subscriber_email,
+          n_retries,
+          last_error,
+          failed_at
+        )
+        VALUES ($1, $2, $3, $4, now())
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+        SET n_retries = EXCLUDED.n_retries,
+            last_error = EXCLUDED.last_error,
+            failed_at = EXCLUDED.failed_at
+        "#,
+        task.issue_id,
+        task.email,
+        task.n_retries + 1,
+        last_error
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    delete_task(transaction, task).await
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    Pending,
+    Sent,
+    Failed,
+    // The subscriber unsubscribed, or was suppressed, after the issue was published
+    Skipped,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Sent => "sent",
+            DeliveryStatus::Failed => "failed",
+            DeliveryStatus::Skipped => "skipped",
+        }
+    }
+}
+
+impl TryFrom<String> for DeliveryStatus {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "pending" => Ok(DeliveryStatus::Pending),
+            "sent" => Ok(DeliveryStatus::Sent),
+            "failed" => Ok(DeliveryStatus::Failed),
+            "skipped" => Ok(DeliveryStatus::Skipped),
+            other => Err(format!("{} is not a valid delivery status.", other)),
+        }
+    }
+}
+
+// Keeps the `issue_deliveries` log in sync with the outcome of each task.
+// It runs inside the task transaction, so the log never disagrees with the queue.
+// `attempted` is whether the email was handed to the transport: only those count
+// as attempts, not skips or pauses while being rate limited.
+async fn record_delivery(
+    transaction: &mut PgTransaction,
+    task: &Task,
+    status: DeliveryStatus,
+    attempted: bool,
+    provider_message_id: Option<&str>,
+    last_error: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_deliveries (
+          newsletter_issue_id,
+          subscriber_email,
+          status,
+          n_attempts,
+          provider_message_id,
+          last_error,
+          created_at,
+          updated_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, now(), now())
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+        SET status = EXCLUDED.status,
+            n_attempts = issue_deliveries.n_attempts + EXCLUDED.n_attempts,
+            provider_message_id = EXCLUDED.provider_message_id,
+            last_error = EXCLUDED.last_error,
+            updated_at = EXCLUDED.updated_at
+        "#,
+        task.issue_id,
+        task.email,
+        status.as_str(),
+        i16::from(attempted),
+        provider_message_id,
+        last_error
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    Ok(())
+}
+
+// Exponential backoff capped at `MAX_BACKOFF`, with up to 50% random jitter on top
+// so that tasks which failed together don't all retry at the same instant.
+pub(crate) fn backoff(n_retries: i16) -> Duration {
+    let exponent = n_retries.clamp(0, 16) as u32;
+    let delay = BASE_BACKOFF
+        .saturating_mul(2u32.pow(exponent))
+        .min(MAX_BACKOFF);
+    let jitter_ms = thread_rng().gen_range(0..=delay.as_millis() as u64 / 2);
+    delay + Duration::from_millis(jitter_ms)
+}
+
+struct ConfirmedSubscriber {
+    id: Uuid,
+    name: String,
+    attributes: serde_json::Value,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_confirmed_subscriber(
+    pool: &PgPool,
+    email: &str,
+) -> Result<Option<ConfirmedSubscriber>, sqlx::Error> {
+    sqlx::query_as!(
+        ConfirmedSubscriber,
+        r#"
+        SELECT id, name, attributes
+        FROM subscriptions
+        WHERE email = $1 AND status = 'confirmed'
+        "#,
+        email
+    )
+    .fetch_optional(pool)
+    .await
+}
+
 struct NewsletterIssue {
     title: String,
     text_content: String,
     html_content: String,
+    tracking: bool,
+    // Where the issue can be read in the archive, its id for issues published before slugs
+    path: String,
+    in_archive: bool,
+}
+
+// An issue ready to be personalized for each of its recipients
+struct CompiledIssue {
+    id: Uuid,
+    template: IssueTemplate,
+    tracking: bool,
+    // `None` for issues kept out of the archive
+    path: Option<String>,
 }
 
+// The outer error is a database failure, the inner one an issue that can't be compiled.
 #[tracing::instrument(skip_all)]
-async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, anyhow::Error> {
+async fn get_issue(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Result<CompiledIssue, TemplateError>, anyhow::Error> {
     let issue = sqlx::query_as!(
         NewsletterIssue,
         r#"
-      SELECT title, text_content, html_content
+      SELECT
+        title,
+        text_content,
+        html_content,
+        tracking,
+        COALESCE(slug, newsletter_issue_id::text) AS "path!",
+        in_archive
       FROM newsletter_issues
       WHERE
         newsletter_issue_id = $1
@@ -123,23 +821,205 @@ async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, any
     )
     .fetch_one(pool)
     .await?;
-    Ok(issue)
+    Ok(
+        IssueTemplate::compile(&issue.title, &issue.html_content, &issue.text_content).map(
+            |template| CompiledIssue {
+                id: issue_id,
+                template,
+                tracking: issue.tracking,
+                path: issue.in_archive.then_some(issue.path),
+            },
+        ),
+    )
 }
 
-async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
-    loop {
-        match try_execute_task(&pool, &email_client).await {
-            Ok(ExecutionOutcome::EmptyQueue) => tokio::time::sleep(Duration::from_secs(10)).await,
+// State shared by all the workers of a pool
+#[derive(Clone)]
+struct WorkerContext {
+    pool: PgPool,
+    email_client: Arc<dyn EmailSender>,
+    base_url: ApplicationBaseUrl,
+    hmac_secret: HmacSecret,
+    rate_limiter: Arc<SendRateLimiter>,
+}
+
+async fn worker_loop(
+    context: WorkerContext,
+    settings: WorkerSettings,
+    new_tasks: Arc<Notify>,
+    shutdown: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    // An email that was handed to the transport is always settled before
+    // stopping: shutdown is only checked in between emails.
+    while !shutdown.is_cancelled() {
+        // Registered before polling, so tasks enqueued while we are busy still wake us up
+        let notified = new_tasks.notified();
+        tokio::pin!(notified);
+        notified.as_mut().enable();
+        match execute_pending_work(&context, &shutdown).await {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::select! {
+                    _ = notified => {}
+                    _ = tokio::time::sleep(settings.empty_queue_poll_interval()) => {}
+                    _ = shutdown.cancelled() => {}
+                }
+            }
             Ok(ExecutionOutcome::TaskCompleted) => {}
-            Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+            Err(_) => {
+                tokio::select! {
+                    _ = tokio::time::sleep(settings.error_poll_interval()) => {}
+                    _ = shutdown.cancelled() => {}
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Sends outbox emails first, they are usually someone waiting on a confirmation
+// link, then newsletter deliveries. A failure on one side doesn't hold up the other.
+async fn execute_pending_work(
+    context: &WorkerContext,
+    shutdown: &CancellationToken,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let emails = try_dispatch_email(
+        &context.pool,
+        context.email_client.as_ref(),
+        &context.rate_limiter,
+    )
+    .await;
+    let deliveries = try_execute_task(
+        &context.pool,
+        context.email_client.as_ref(),
+        &context.base_url,
+        &context.hmac_secret,
+        &context.rate_limiter,
+        shutdown,
+    )
+    .await;
+    match (emails?, deliveries?) {
+        (ExecutionOutcome::EmptyQueue, ExecutionOutcome::EmptyQueue) => {
+            Ok(ExecutionOutcome::EmptyQueue)
+        }
+        _ => Ok(ExecutionOutcome::TaskCompleted),
+    }
+}
+
+// Wakes idle workers whenever new delivery tasks or outbox emails are committed.
+// Workers keep polling on their own, so losing the listener only delays deliveries.
+async fn wake_workers_on_new_tasks(pool: PgPool, new_tasks: Arc<Notify>, retry_interval: Duration) {
+    let mut listener = loop {
+        match listen_for_new_tasks(&pool).await {
+            Ok(listener) => break listener,
+            Err(e) => {
+                tracing::warn!(e.cause_chain = ?e, e.message = %e, "Failed to listen for new delivery tasks");
+                tokio::time::sleep(retry_interval).await;
+            }
+        }
+    };
+    loop {
+        match listener.recv().await {
+            Ok(_) => new_tasks.notify_waiters(),
+            Err(e) => {
+                tracing::warn!(e.cause_chain = ?e, e.message = %e, "Lost the delivery queue listener");
+                tokio::time::sleep(retry_interval).await;
+            }
         }
     }
 }
 
-pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+async fn listen_for_new_tasks(pool: &PgPool) -> Result<PgListener, sqlx::Error> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener
+        .listen_all([DELIVERY_QUEUE_CHANNEL, EMAIL_OUTBOX_CHANNEL])
+        .await?;
+    Ok(listener)
+}
+
+/// Runs `settings.concurrency` workers sharing a single queue listener. They
+/// deliver newsletter issues and send the emails waiting in the outbox.
+/// Once `shutdown` is cancelled every worker finishes the task it is working
+/// on and the function returns; it returns early if one of the workers fails.
+pub async fn run_workers(
+    pool: PgPool,
+    email_client: Arc<dyn EmailSender>,
+    base_url: ApplicationBaseUrl,
+    hmac_secret: HmacSecret,
+    settings: WorkerSettings,
+    shutdown: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    let new_tasks = Arc::new(Notify::new());
+    let context = WorkerContext {
+        pool: pool.clone(),
+        email_client,
+        base_url,
+        hmac_secret,
+        rate_limiter: Arc::new(SendRateLimiter::new(&settings.rate_limit)),
+    };
+    let mut workers = JoinSet::new();
+    for _ in 0..settings.concurrency.max(1) {
+        workers.spawn(worker_loop(
+            context.clone(),
+            settings.clone(),
+            new_tasks.clone(),
+            shutdown.clone(),
+        ));
+    }
+    let listener = tokio::spawn(wake_workers_on_new_tasks(
+        pool,
+        new_tasks,
+        settings.error_poll_interval(),
+    ));
+    let outcome = async {
+        while let Some(outcome) = workers.join_next().await {
+            outcome??;
+        }
+        Ok(())
+    }
+    .await;
+    listener.abort();
+    outcome
+}
+
+pub async fn run_worker_until_stopped(
+    configuration: Settings,
+    shutdown: CancellationToken,
+) -> Result<(), anyhow::Error> {
     let connection_pool = get_database_pool(&configuration);
 
     let email_client = configuration.email_client.client();
+    let base_url = ApplicationBaseUrl(configuration.application.base_url);
+    let hmac_secret = HmacSecret(configuration.application.hmac_secret);
+
+    run_workers(
+        connection_pool,
+        email_client,
+        base_url,
+        hmac_secret,
+        configuration.worker,
+        shutdown,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{backoff, BASE_BACKOFF, MAX_BACKOFF};
+
+    #[test]
+    fn backoff_grows_exponentially() {
+        for n_retries in 0..4 {
+            let delay = backoff(n_retries);
+            let expected = BASE_BACKOFF * 2u32.pow(n_retries as u32);
+            assert!(delay >= expected);
+            assert!(delay <= expected + expected / 2);
+        }
+    }
 
-    worker_loop(connection_pool, email_client).await
+    #[test]
+    fn backoff_is_capped() {
+        let delay = backoff(i16::MAX);
+        assert!(delay >= MAX_BACKOFF);
+        assert!(delay <= MAX_BACKOFF + MAX_BACKOFF / 2);
+    }
 }
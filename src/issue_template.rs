@@ -0,0 +1,162 @@
+use minijinja::{context, Value as TemplateValue};
+use serde_json::Value;
+
+use crate::email_template::{EmailTemplate, RenderedEmail, TemplateError};
+
+// Variables an issue can use: `{{ name }}`, `{{ email }}`, `{{ unsubscribe_url }}`
+// and the subscriber's custom attributes, e.g. `{{ attributes.company }}`.
+const MERGE_TAGS: &[&str] = &["name", "email", "unsubscribe_url", "attributes"];
+
+/// The merge tag values of one recipient.
+pub struct MergeTags<'a> {
+    pub name: &'a str,
+    pub email: &'a str,
+    pub unsubscribe_url: &'a str,
+    pub attributes: &'a Value,
+}
+
+impl MergeTags<'_> {
+    /// Placeholder values, used to check templates and to send tests.
+    pub fn sample() -> MergeTags<'static> {
+        static NO_ATTRIBUTES: Value = Value::Null;
+        MergeTags {
+            name: "Subscriber",
+            email: "subscriber@example.com",
+            unsubscribe_url: "https://example.com/subscriptions/unsubscribe",
+            attributes: &NO_ATTRIBUTES,
+        }
+    }
+
+    /// The values seen by readers of the issue archive, who aren't anyone in particular.
+    pub fn public() -> MergeTags<'static> {
+        static NO_ATTRIBUTES: Value = Value::Null;
+        MergeTags {
+            name: "reader",
+            email: "",
+            unsubscribe_url: "",
+            attributes: &NO_ATTRIBUTES,
+        }
+    }
+}
+
+/// The title and content of an issue, compiled once and rendered for each
+/// recipient.
+pub struct IssueTemplate(EmailTemplate);
+
+impl IssueTemplate {
+    pub fn compile(
+        title: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<Self, TemplateError> {
+        EmailTemplate::compile(title, html_content, text_content, MERGE_TAGS).map(Self)
+    }
+
+    /// Compiles the templates and renders them once with placeholder
+    /// values, so mistakes surface before anything is sent.
+    pub fn validate(
+        title: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), TemplateError> {
+        Self::compile(title, html_content, text_content)?.render(&MergeTags::sample())?;
+        Ok(())
+    }
+
+    /// The rendered title is the `subject` of the result.
+    pub fn render(&self, merge_tags: &MergeTags) -> Result<RenderedEmail, TemplateError> {
+        let attributes = match merge_tags.attributes {
+            Value::Object(_) => merge_tags.attributes.clone(),
+            _ => Value::Object(Default::default()),
+        };
+        self.0.render(&context! {
+            name => merge_tags.name,
+            email => merge_tags.email,
+            unsubscribe_url => TemplateValue::from_safe_string(merge_tags.unsubscribe_url.to_owned()),
+            attributes => attributes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IssueTemplate, MergeTags};
+    use crate::email_template::TemplateError;
+    use claims::{assert_err, assert_matches, assert_ok};
+    use serde_json::json;
+
+    fn merge_tags(attributes: &serde_json::Value) -> MergeTags<'_> {
+        MergeTags {
+            name: "Ursula <3",
+            email: "ursula@gmail.com",
+            unsubscribe_url: "https://example.com/unsubscribe?token=abc",
+            attributes,
+        }
+    }
+
+    #[test]
+    fn merge_tags_are_replaced_and_escaped_in_html_only() {
+        let attributes = json!({ "company": "Earthsea" });
+        let template = IssueTemplate::compile(
+            "Hi {{ name }}",
+            "<p>Hi {{ name }} from {{ attributes.company }}</p>",
+            "Hi {{ name }}, leave at {{ unsubscribe_url }}",
+        )
+        .unwrap();
+
+        let rendered = template.render(&merge_tags(&attributes)).unwrap();
+
+        assert_eq!(rendered.subject, "Hi Ursula <3");
+        assert_eq!(
+            rendered.html_content,
+            "<p>Hi Ursula &lt;3 from Earthsea</p>"
+        );
+        assert_eq!(
+            rendered.text_content,
+            "Hi Ursula <3, leave at https://example.com/unsubscribe?token=abc"
+        );
+    }
+
+    #[test]
+    fn missing_attributes_render_empty_or_with_a_default() {
+        let attributes = json!({});
+        let template = IssueTemplate::compile(
+            "Title",
+            "{{ attributes.company }}",
+            "{{ attributes.company | default('friend') }}",
+        )
+        .unwrap();
+
+        let rendered = template.render(&merge_tags(&attributes)).unwrap();
+
+        assert_eq!(rendered.html_content, "");
+        assert_eq!(rendered.text_content, "friend");
+    }
+
+    #[test]
+    fn unknown_merge_tags_are_rejected() {
+        let result = IssueTemplate::validate("Title", "Hi {{ nmae }}", "Hi");
+        assert_matches!(result, Err(TemplateError::UnknownMergeTag { ref tag, .. }) if tag == "nmae");
+    }
+
+    #[test]
+    fn syntax_errors_and_unknown_filters_are_rejected() {
+        assert_err!(IssueTemplate::validate("Title", "{{ name ", "Hi"));
+        assert_err!(IssueTemplate::validate("Title", "{{ name | shout }}", "Hi"));
+    }
+
+    #[test]
+    fn loops_are_bounded() {
+        let result = IssueTemplate::validate(
+            "Title",
+            "{% for i in range(100000) %}{{ i }}{% endfor %}",
+            "Hi",
+        );
+        assert_err!(result);
+        assert_ok!(IssueTemplate::validate(
+            "Title",
+            "{% for i in range(3) %}{{ i }}{% endfor %}",
+            "Hi"
+        ));
+    }
+}
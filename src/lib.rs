@@ -1,11 +1,20 @@
 pub mod authentication;
 pub mod configuration;
+pub mod delivery_events;
 pub mod domain;
 pub mod email_client;
+pub mod email_layout;
+pub mod email_outbox;
+pub mod email_template;
 pub mod idempotency;
 pub mod issue_delivery_worker;
+pub mod issue_template;
+pub mod mailing_lists;
+pub mod rate_limiter;
 pub mod routes;
 pub mod session_state;
 pub mod startup;
+pub mod suppressions;
 pub mod telemetry;
+pub mod tracking;
 pub mod utils;
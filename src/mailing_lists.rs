@@ -0,0 +1,220 @@
+use std::ops::DerefMut;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{PgConnection, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// The list subscribers join, and issues are published to, when none is picked.
+pub const DEFAULT_LIST_SLUG: &str = "newsletter";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MailingList {
+    pub list_id: Uuid,
+    pub slug: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Slugs show up in URLs and forms: lowercase ASCII letters, digits and dashes.
+pub fn validate_slug(slug: &str) -> Result<(), String> {
+    let is_valid = !slug.is_empty()
+        && slug.len() <= 64
+        && !slug.starts_with('-')
+        && !slug.ends_with('-')
+        && slug
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+    if is_valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} is not a valid list slug. Use lowercase letters, digits and dashes.",
+            slug
+        ))
+    }
+}
+
+/// Splits a comma-separated list of slugs, e.g. `news, product-updates`.
+/// Blank entries are dropped and an empty input means the default list.
+pub fn parse_slugs(s: Option<&str>) -> Vec<String> {
+    let mut slugs = Vec::new();
+    for slug in s.unwrap_or_default().split(',').map(str::trim) {
+        if !slug.is_empty() && !slugs.iter().any(|s| s == slug) {
+            slugs.push(slug.to_owned());
+        }
+    }
+    if slugs.is_empty() {
+        slugs.push(DEFAULT_LIST_SLUG.to_owned());
+    }
+    slugs
+}
+
+#[tracing::instrument(name = "Get mailing lists", skip(pool))]
+pub async fn get_lists(pool: &PgPool) -> Result<Vec<MailingList>, anyhow::Error> {
+    let lists = sqlx::query_as!(
+        MailingList,
+        r#"SELECT list_id, slug, name, created_at FROM lists ORDER BY created_at, slug"#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve mailing lists.")?;
+    Ok(lists)
+}
+
+#[tracing::instrument(name = "Get mailing list by slug", skip(connection))]
+pub async fn get_list_by_slug(
+    connection: &mut PgConnection,
+    slug: &str,
+) -> Result<Option<MailingList>, sqlx::Error> {
+    sqlx::query_as!(
+        MailingList,
+        r#"SELECT list_id, slug, name, created_at FROM lists WHERE slug = $1"#,
+        slug
+    )
+    .fetch_optional(connection)
+    .await
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ResolveListsError {
+    #[error("There is no mailing list called {0}")]
+    UnknownList(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] sqlx::Error),
+}
+
+/// Looks up every slug in `slugs`, failing on the first one that doesn't exist.
+pub async fn resolve_lists(
+    connection: &mut PgConnection,
+    slugs: &[String],
+) -> Result<Vec<MailingList>, ResolveListsError> {
+    let mut lists = Vec::with_capacity(slugs.len());
+    for slug in slugs {
+        match get_list_by_slug(connection, slug).await? {
+            Some(list) => lists.push(list),
+            None => return Err(ResolveListsError::UnknownList(slug.clone())),
+        }
+    }
+    Ok(lists)
+}
+
+/// Creates a list, returning `None` if the slug is already taken.
+#[tracing::instrument(name = "Insert mailing list", skip(pool))]
+pub async fn insert_list(
+    pool: &PgPool,
+    slug: &str,
+    name: &str,
+) -> Result<Option<MailingList>, sqlx::Error> {
+    sqlx::query_as!(
+        MailingList,
+        r#"
+        INSERT INTO lists (list_id, slug, name)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (slug) DO NOTHING
+        RETURNING list_id, slug, name, created_at
+        "#,
+        Uuid::new_v4(),
+        slug,
+        name
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// The status of a subscriber on one list, if they ever joined it.
+#[tracing::instrument(name = "Get list membership status", skip(transaction))]
+pub async fn get_membership_status(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    let status = sqlx::query_scalar!(
+        r#"
+        SELECT status FROM list_memberships
+        WHERE list_id = $1 AND subscriber_id = $2
+        FOR UPDATE
+        "#,
+        list_id,
+        subscriber_id
+    )
+    .fetch_optional(transaction.deref_mut())
+    .await?;
+    Ok(status)
+}
+
+/// Adds the subscriber to a list, waiting for them to confirm. Rejoining a
+/// list they left puts them back to pending.
+#[tracing::instrument(name = "Add a subscriber to a mailing list", skip(transaction))]
+pub async fn add_pending_membership(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (list_id, subscriber_id, status)
+        VALUES ($1, $2, 'pending_confirmation')
+        ON CONFLICT (list_id, subscriber_id)
+        DO UPDATE SET status = 'pending_confirmation', confirmed_at = NULL
+        WHERE list_memberships.status <> 'confirmed'
+        "#,
+        list_id,
+        subscriber_id
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Confirm a list membership", skip(transaction))]
+pub async fn confirm_membership(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (list_id, subscriber_id, status, confirmed_at)
+        VALUES ($1, $2, 'confirmed', now())
+        ON CONFLICT (list_id, subscriber_id)
+        DO UPDATE SET status = 'confirmed', confirmed_at = now()
+        WHERE list_memberships.status <> 'confirmed'
+        "#,
+        list_id,
+        subscriber_id
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_slugs, validate_slug, DEFAULT_LIST_SLUG};
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn no_slugs_means_the_default_list() {
+        assert_eq!(parse_slugs(None), vec![DEFAULT_LIST_SLUG]);
+        assert_eq!(parse_slugs(Some(" , ")), vec![DEFAULT_LIST_SLUG]);
+    }
+
+    #[test]
+    fn slugs_are_trimmed_and_deduplicated() {
+        assert_eq!(
+            parse_slugs(Some("news, product-updates,news")),
+            vec!["news", "product-updates"]
+        );
+    }
+
+    #[test]
+    fn slugs_are_lowercase_letters_digits_and_dashes() {
+        assert_ok!(validate_slug("product-updates-2024"));
+        assert_err!(validate_slug(""));
+        assert_err!(validate_slug("Product"));
+        assert_err!(validate_slug("-news"));
+        assert_err!(validate_slug("news letter"));
+    }
+}
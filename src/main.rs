@@ -1,39 +1,198 @@
-use server_scaffold::configuration::get_configuration;
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use secrecy::Secret;
+use server_scaffold::authentication::{create_user, Credentials};
+use server_scaffold::configuration::{get_configuration, Settings};
 use server_scaffold::issue_delivery_worker::run_worker_until_stopped;
-use server_scaffold::startup::Application;
+use server_scaffold::startup::{get_database_pool, Application};
 use server_scaffold::telemetry::{get_subscriber, init_subscriber};
-use tokio::task::JoinError;
+use std::time::Duration;
+use tokio::task::{JoinError, JoinSet};
+use tokio_util::sync::CancellationToken;
+
+#[derive(Parser)]
+#[command(about = "Newsletter API and delivery workers")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Serve the HTTP API
+    Serve,
+    /// Run the newsletter delivery workers
+    Worker,
+    /// Run the HTTP API and the delivery workers in one process (default)
+    All,
+    /// Apply pending database migrations
+    Migrate,
+    /// Create a user who can log into the admin area
+    CreateAdmin {
+        #[arg(long)]
+        username: String,
+        #[arg(long, env = "ADMIN_PASSWORD", hide_env_values = true)]
+        password: String,
+    },
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
     let subscriber = get_subscriber("scaffold".into(), "info".into(), std::io::stdout);
     init_subscriber(subscriber);
 
     let configuration = get_configuration().expect("Failed to read configuration.");
 
-    let application = Application::build(configuration.clone()).await?;
+    match cli.command.unwrap_or(Command::All) {
+        Command::Serve => serve(configuration).await,
+        Command::Worker => worker(configuration).await,
+        Command::All => all(configuration).await,
+        Command::Migrate => migrate(configuration).await,
+        Command::CreateAdmin { username, password } => {
+            create_admin(configuration, username, password).await
+        }
+    }
+}
+
+type Task = (&'static str, anyhow::Result<()>);
+
+async fn serve(configuration: Settings) -> anyhow::Result<()> {
+    let shutdown = CancellationToken::new();
+    let deadline = configuration.application.shutdown_deadline();
+    let mut tasks = JoinSet::new();
+    spawn_api(&mut tasks, configuration, shutdown.clone()).await?;
+    run_until_shutdown(tasks, shutdown, deadline).await;
+    Ok(())
+}
+
+async fn worker(configuration: Settings) -> anyhow::Result<()> {
+    let shutdown = CancellationToken::new();
+    let deadline = configuration.application.shutdown_deadline();
+    let mut tasks = JoinSet::new();
+    spawn_worker(&mut tasks, configuration, shutdown.clone());
+    run_until_shutdown(tasks, shutdown, deadline).await;
+    Ok(())
+}
 
-    let application_task = tokio::spawn(application.run_until_stopped());
+async fn all(configuration: Settings) -> anyhow::Result<()> {
+    let shutdown = CancellationToken::new();
+    let deadline = configuration.application.shutdown_deadline();
+    let mut tasks = JoinSet::new();
+    spawn_api(&mut tasks, configuration.clone(), shutdown.clone()).await?;
+    spawn_worker(&mut tasks, configuration, shutdown.clone());
+    run_until_shutdown(tasks, shutdown, deadline).await;
+    Ok(())
+}
+
+async fn spawn_api(
+    tasks: &mut JoinSet<Task>,
+    configuration: Settings,
+    shutdown: CancellationToken,
+) -> anyhow::Result<()> {
+    let application = Application::build(configuration).await?;
+    let server = application.handle();
+    tokio::spawn(async move {
+        shutdown.cancelled().await;
+        // Stops accepting connections and lets in-flight requests complete
+        server.stop(true).await;
+    });
+    tasks.spawn(async move {
+        let outcome = application.run_until_stopped().await;
+        ("API", outcome.map_err(Into::into))
+    });
+    Ok(())
+}
 
-    let worker_task = tokio::spawn(run_worker_until_stopped(configuration));
+fn spawn_worker(tasks: &mut JoinSet<Task>, configuration: Settings, shutdown: CancellationToken) {
+    tasks.spawn(async move {
+        let outcome = run_worker_until_stopped(configuration, shutdown).await;
+        ("Background worker", outcome)
+    });
+}
 
+// Runs until we are asked to stop or one of the tasks exits on its own, then
+// gives the remaining tasks up to `deadline` to wrap up what they are doing.
+// Workers stop in between emails and put back what they didn't send: only
+// an email still in flight at the deadline is cut short, and retried once
+// its claim expires.
+async fn run_until_shutdown(
+    mut tasks: JoinSet<Task>,
+    shutdown: CancellationToken,
+    deadline: Duration,
+) {
     tokio::select! {
-        o = application_task => report_exit("API", o),
-        o = worker_task => report_exit("Background worker", o),
+        Some(outcome) = tasks.join_next() => report_exit(outcome),
+        _ = shutdown_signal() => tracing::info!("Shutting down"),
+    };
+    shutdown.cancel();
+
+    let drain = async {
+        while let Some(outcome) = tasks.join_next().await {
+            report_exit(outcome);
+        }
     };
+    if tokio::time::timeout(deadline, drain).await.is_err() {
+        tracing::warn!("Shutdown deadline exceeded, aborting the remaining tasks");
+        tasks.shutdown().await;
+    }
+}
 
+async fn migrate(configuration: Settings) -> anyhow::Result<()> {
+    let connection_pool = get_database_pool(&configuration);
+    sqlx::migrate!("./migrations")
+        .run(&connection_pool)
+        .await
+        .context("Failed to migrate the database")?;
+    tracing::info!("The database is up to date");
     Ok(())
 }
 
-fn report_exit(
-    task_name: &str,
-    outcome: Result<Result<(), impl std::fmt::Debug + std::fmt::Display>, JoinError>,
-) {
+async fn create_admin(
+    configuration: Settings,
+    username: String,
+    password: String,
+) -> anyhow::Result<()> {
+    let connection_pool = get_database_pool(&configuration);
+    let credentials = Credentials {
+        username,
+        password: Secret::new(password),
+    };
+    let user_id = create_user(credentials, &connection_pool).await?;
+    tracing::info!(%user_id, "Created a new admin user");
+    Ok(())
+}
+
+// Resolves on SIGTERM (e.g. `docker stop`) or Ctrl+C
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to listen for Ctrl+C");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+fn report_exit(outcome: Result<Task, JoinError>) {
     match outcome {
-        Ok(Ok(())) => {
+        Ok((task_name, Ok(()))) => {
             tracing::info!("{} has exited", task_name);
         }
-        Ok(Err(e)) => {
+        Ok((task_name, Err(e))) => {
             tracing::error!(
                 error.cause_chain = ?e,
                 error.message = %e,
@@ -45,8 +204,7 @@ fn report_exit(
             tracing::error!(
                 error.cause_chain = ?e,
                 error.message = %e,
-                "{}' task failed to complete",
-                task_name
+                "A task failed to complete",
             )
         }
     }
@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use governor::{DefaultDirectRateLimiter, DefaultKeyedRateLimiter, Quota, RateLimiter};
+use tokio::time::Instant;
+
+use crate::configuration::RateLimitSettings;
+use crate::domain::SubscriberEmail;
+
+// Pause applied after a 429 that didn't say how long to wait, doubled on every
+// consecutive 429 up to `MAX_THROTTLE_PAUSE`.
+const BASE_THROTTLE_PAUSE: Duration = Duration::from_secs(1);
+const MAX_THROTTLE_PAUSE: Duration = Duration::from_secs(60);
+
+/// Send budget shared by all the delivery workers of a process.
+pub struct SendRateLimiter {
+    global: Option<DefaultDirectRateLimiter>,
+    per_domain: Option<DefaultKeyedRateLimiter<String>>,
+    // Domains with a budget of their own, e.g. `gmail.com`
+    domains: HashMap<String, DefaultDirectRateLimiter>,
+    paused_until: Mutex<Option<Instant>>,
+    consecutive_throttles: AtomicU32,
+}
+
+impl SendRateLimiter {
+    pub fn new(settings: &RateLimitSettings) -> Self {
+        Self {
+            global: settings
+                .messages_per_second
+                .map(|n| RateLimiter::direct(Quota::per_second(n))),
+            per_domain: settings
+                .messages_per_second_per_domain
+                .map(|n| RateLimiter::keyed(Quota::per_second(n))),
+            domains: settings
+                .domains
+                .iter()
+                .map(|d| {
+                    (
+                        d.domain.to_lowercase(),
+                        RateLimiter::direct(Quota::per_second(d.messages_per_second)),
+                    )
+                })
+                .collect(),
+            paused_until: Mutex::new(None),
+            consecutive_throttles: AtomicU32::new(0),
+        }
+    }
+
+    /// A limiter that never waits.
+    pub fn unlimited() -> Self {
+        Self::new(&RateLimitSettings::default())
+    }
+
+    /// Waits until an email to `recipient` fits in both the global budget
+    /// and the budget of the recipient's domain.
+    pub async fn until_ready(&self, recipient: &SubscriberEmail) {
+        loop {
+            let paused_until = *self.paused_until.lock().unwrap();
+            match paused_until {
+                Some(t) if t > Instant::now() => tokio::time::sleep_until(t).await,
+                _ => break,
+            }
+        }
+        if let Some(global) = &self.global {
+            global.until_ready().await;
+        }
+        let domain = recipient.domain().to_lowercase();
+        if let Some(limiter) = self.domains.get(&domain) {
+            limiter.until_ready().await;
+        } else if let Some(per_domain) = &self.per_domain {
+            per_domain.until_key_ready(&domain).await;
+        }
+    }
+
+    /// Forgets the domains whose budget has fully recovered, so that the
+    /// per-domain budgets don't keep one entry for every domain ever sent to.
+    pub fn forget_idle_domains(&self) {
+        if let Some(per_domain) = &self.per_domain {
+            per_domain.retain_recent();
+            per_domain.shrink_to_fit();
+        }
+    }
+
+    /// Pauses every worker after the provider answered 429.
+    /// Returns how long sending is paused for.
+    pub fn back_off(&self, retry_after: Option<Duration>) -> Duration {
+        let n = self.consecutive_throttles.fetch_add(1, Ordering::SeqCst);
+        let pause = retry_after.unwrap_or_else(|| {
+            BASE_THROTTLE_PAUSE
+                .saturating_mul(2u32.saturating_pow(n))
+                .min(MAX_THROTTLE_PAUSE)
+        });
+        let until = Instant::now() + pause;
+        let mut paused_until = self.paused_until.lock().unwrap();
+        if !matches!(*paused_until, Some(t) if t >= until) {
+            *paused_until = Some(until);
+        }
+        pause
+    }
+
+    /// Resets the 429 backoff once the provider accepts emails again.
+    pub fn record_success(&self) {
+        self.consecutive_throttles.store(0, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        matches!(*self.paused_until.lock().unwrap(), Some(t) if t > Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SendRateLimiter;
+    use crate::configuration::{DomainRateLimit, RateLimitSettings};
+    use crate::domain::SubscriberEmail;
+    use std::num::NonZeroU32;
+    use std::time::{Duration, Instant};
+
+    fn email(address: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(address.into()).unwrap()
+    }
+
+    fn per_second(n: u32) -> Option<NonZeroU32> {
+        NonZeroU32::new(n)
+    }
+
+    #[tokio::test]
+    async fn the_global_budget_spaces_out_sends() {
+        let limiter = SendRateLimiter::new(&RateLimitSettings {
+            messages_per_second: per_second(10),
+            ..Default::default()
+        });
+
+        let start = Instant::now();
+        for i in 0..15 {
+            limiter
+                .until_ready(&email(&format!("user{}@domain{}.com", i, i)))
+                .await;
+        }
+
+        // A burst of 10 goes out right away, the next 5 take 100ms each
+        assert!(start.elapsed() >= Duration::from_millis(450));
+    }
+
+    #[tokio::test]
+    async fn the_domain_budget_only_applies_to_that_domain() {
+        let limiter = SendRateLimiter::new(&RateLimitSettings {
+            messages_per_second_per_domain: per_second(2),
+            ..Default::default()
+        });
+
+        let start = Instant::now();
+        for i in 0..4 {
+            limiter
+                .until_ready(&email(&format!("user{}@domain{}.com", i, i)))
+                .await;
+        }
+        assert!(start.elapsed() < Duration::from_millis(100));
+
+        let start = Instant::now();
+        for i in 0..4 {
+            limiter
+                .until_ready(&email(&format!("user{}@gmail.com", i)))
+                .await;
+        }
+        assert!(start.elapsed() >= Duration::from_millis(900));
+    }
+
+    #[tokio::test]
+    async fn domains_can_have_a_budget_of_their_own() {
+        let limiter = SendRateLimiter::new(&RateLimitSettings {
+            messages_per_second_per_domain: per_second(1),
+            domains: vec![DomainRateLimit {
+                domain: "gmail.com".into(),
+                messages_per_second: NonZeroU32::new(100).unwrap(),
+            }],
+            ..Default::default()
+        });
+
+        let start = Instant::now();
+        for i in 0..10 {
+            limiter
+                .until_ready(&email(&format!("user{}@gmail.com", i)))
+                .await;
+        }
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn idle_domains_are_forgotten() {
+        let limiter = SendRateLimiter::new(&RateLimitSettings {
+            messages_per_second_per_domain: per_second(10),
+            ..Default::default()
+        });
+        for i in 0..3 {
+            limiter
+                .until_ready(&email(&format!("user@domain{}.com", i)))
+                .await;
+        }
+        let per_domain = limiter.per_domain.as_ref().unwrap();
+        limiter.forget_idle_domains();
+        assert_eq!(per_domain.len(), 3);
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        limiter.forget_idle_domains();
+        assert!(per_domain.is_empty());
+    }
+
+    #[tokio::test]
+    async fn sending_is_paused_after_a_429() {
+        let limiter = SendRateLimiter::unlimited();
+
+        let pause = limiter.back_off(Some(Duration::from_millis(300)));
+        assert_eq!(pause, Duration::from_millis(300));
+        assert!(limiter.is_paused());
+
+        let start = Instant::now();
+        limiter.until_ready(&email("user@gmail.com")).await;
+        assert!(start.elapsed() >= Duration::from_millis(250));
+        assert!(!limiter.is_paused());
+    }
+
+    #[test]
+    fn consecutive_429s_back_off_exponentially() {
+        let limiter = SendRateLimiter::unlimited();
+
+        assert_eq!(limiter.back_off(None), Duration::from_secs(1));
+        assert_eq!(limiter.back_off(None), Duration::from_secs(2));
+        assert_eq!(limiter.back_off(None), Duration::from_secs(4));
+
+        limiter.record_success();
+        assert_eq!(limiter.back_off(None), Duration::from_secs(1));
+    }
+}
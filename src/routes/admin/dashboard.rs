@@ -32,7 +32,11 @@ pub async fn admin_dashboard(
     <p>Welcome {username}!</p>
     <p>Available actions:</p>
     <ol>
+        <li><a href="/admin/newsletters">Send a newsletter issue</a></li>
+        <li><a href="/admin/newsletters/drafts">Drafts</a></li>
+        <li><a href="/admin/templates">Email layouts</a></li>
         <li><a href="/admin/password">Change password</a></li>
+        <li><a href="/admin/email">Change email address</a></li>
         <li>
         <form name="logoutForm" action="/admin/logout" method="post">
             <input type="submit" value="Logout">
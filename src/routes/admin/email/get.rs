@@ -0,0 +1,61 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use htmlescape::encode_attribute;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::{authentication::UserId, utils::e500};
+
+pub async fn account_email_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let email = get_user_email(*user_id.into_inner(), &pool)
+        .await
+        .map_err(e500)?
+        .unwrap_or_default();
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Account email</title>
+</head>
+<body>
+    {msg_html}
+    <form action="/admin/email" method="post">
+        <label>Email address
+            <input
+                type="email"
+                placeholder="Enter your email address"
+                name="email"
+                value="{email}"
+            >
+        </label>
+        <button type="submit">Save</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+            email = encode_attribute(&email)
+        )))
+}
+
+#[tracing::instrument(name = "Get user email", skip(pool))]
+pub async fn get_user_email(user_id: Uuid, pool: &PgPool) -> Result<Option<String>, anyhow::Error> {
+    let row = sqlx::query!(r#"SELECT email FROM users WHERE user_id = $1"#, user_id)
+        .fetch_one(pool)
+        .await
+        .context("Failed to perform a query to retrieve a user email.")?;
+    Ok(row.email)
+}
@@ -0,0 +1,5 @@
+mod get;
+pub use get::{account_email_form, get_user_email};
+
+mod post;
+pub use post::change_account_email;
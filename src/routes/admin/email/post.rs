@@ -0,0 +1,39 @@
+use crate::{
+    authentication::UserId,
+    domain::SubscriberEmail,
+    utils::{e500, see_other},
+};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    pub email: String,
+}
+
+pub async fn change_account_email(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = match SubscriberEmail::parse(form.0.email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/email"));
+        }
+    };
+    sqlx::query!(
+        r#"UPDATE users SET email = $1 WHERE user_id = $2"#,
+        email.as_ref(),
+        *user_id.into_inner()
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to change the user's email address in the database")
+    .map_err(e500)?;
+    FlashMessage::info("Your email address has been changed.").send();
+    Ok(see_other("/admin/email"))
+}
@@ -0,0 +1,36 @@
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+use crate::mailing_lists::{get_lists, insert_list, validate_slug};
+use crate::utils::{e400, e500};
+
+#[tracing::instrument(name = "List mailing lists", skip(pool))]
+pub async fn list_mailing_lists(pool: web::Data<PgPool>) -> Result<HttpResponse, actix_web::Error> {
+    let lists = get_lists(&pool).await.map_err(e500)?;
+    Ok(HttpResponse::Ok().json(lists))
+}
+
+#[derive(serde::Deserialize)]
+pub struct NewListData {
+    slug: String,
+    name: String,
+}
+
+#[tracing::instrument(name = "Create a mailing list", skip(body, pool))]
+pub async fn create_mailing_list(
+    body: web::Json<NewListData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let NewListData { slug, name } = body.into_inner();
+    validate_slug(&slug).map_err(e400)?;
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > 256 {
+        return Err(e400(
+            "A list name must be between 1 and 256 characters long.",
+        ));
+    }
+    match insert_list(&pool, &slug, name).await.map_err(e500)? {
+        Some(list) => Ok(HttpResponse::Created().json(list)),
+        None => Ok(HttpResponse::Conflict().body(format!("The list {} already exists.", slug))),
+    }
+}
@@ -1,9 +1,19 @@
 mod dashboard;
+mod email;
+mod lists;
 mod logout;
 mod newsletter;
 mod password;
+mod subscribers;
+mod suppressions;
+mod templates;
 
 pub use dashboard::*;
+pub use email::*;
+pub use lists::*;
 pub use logout::*;
 pub use newsletter::*;
 pub use password::*;
+pub use subscribers::*;
+pub use suppressions::*;
+pub use templates::*;
@@ -0,0 +1,137 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use super::persistence::{get_draft, get_drafts};
+use crate::mailing_lists::{get_lists, DEFAULT_LIST_SLUG};
+use crate::routes::admin::newsletter::recipients::recipient_count_script;
+use crate::utils::e500;
+
+pub async fn list_drafts(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut drafts_html = String::new();
+    for draft in get_drafts(&pool).await.map_err(e500)? {
+        writeln!(
+            drafts_html,
+            r#"<li><a href="/admin/newsletters/drafts/{}">{}</a> (last edited {})</li>"#,
+            draft.newsletter_issue_id,
+            encode_minimal(&draft.title),
+            draft.updated_at.to_rfc3339()
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Drafts</title>
+</head>
+<body>
+    {msg_html}
+    <h1>Drafts</h1>
+    <ul>
+{drafts_html}    </ul>
+    <p><a href="/admin/newsletters">&lt;- Back</a></p>
+</body>
+</html>"#
+        )))
+}
+
+pub async fn edit_draft_form(
+    newsletter_issue_id: web::Path<Uuid>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let Some(draft) = get_draft(&pool, newsletter_issue_id).await.map_err(e500)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let idempotency_key = Uuid::new_v4();
+    let available_lists = get_lists(&pool)
+        .await
+        .map_err(e500)?
+        .into_iter()
+        .map(|list| encode_minimal(&list.slug))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let recipient_count = recipient_count_script("publish-draft-form");
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Edit draft</title>
+</head>
+<body>
+    {msg_html}
+    <form action="/admin/newsletters/drafts/{newsletter_issue_id}" method="post">
+        <label>Title
+            <input type="text" name="title" value="{title}">
+        </label>
+        <label>TextContent
+            <textarea name="text_content">{text_content}</textarea>
+        </label>
+        <label>HtmlContent
+            <textarea name="html_content">{html_content}</textarea>
+        </label>
+        <button type="submit">Save draft</button>
+    </form>
+    <p><a href="/admin/newsletters/drafts/{newsletter_issue_id}/preview">Preview</a></p>
+    <form action="/admin/newsletters/drafts/{newsletter_issue_id}/test" method="post">
+        <button type="submit">Send test to me</button>
+    </form>
+    <form id="publish-draft-form" action="/admin/newsletters/drafts/{newsletter_issue_id}/publish" method="post">
+        <label>Publish at (UTC, leave empty to publish now)
+            <input type="datetime-local" name="scheduled_for">
+        </label>
+        <label>Lists (comma-separated, one of {available_lists})
+            <input type="text" name="lists" placeholder="{DEFAULT_LIST_SLUG}">
+        </label>
+        <label>Segment (e.g. tag:beta AND NOT tag:churned, leave empty for everyone)
+            <input type="text" name="segment">
+        </label>
+        <label>
+            <input type="checkbox" name="disable_tracking" value="true">
+            Don't track opens and clicks
+        </label>
+        <label>
+            <input type="checkbox" name="hide_from_archive" value="true">
+            Keep out of the public archive and feeds
+        </label>
+        <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
+        <button type="submit">Publish</button>
+    </form>
+    {recipient_count}
+    <form action="/admin/newsletters/drafts/{newsletter_issue_id}/delete" method="post">
+        <button type="submit">Delete draft</button>
+    </form>
+    <p><a href="/admin/newsletters/drafts">&lt;- Back</a></p>
+</body>
+</html>"#,
+            title = encode_attribute(&draft.title),
+            text_content = encode_minimal(&draft.text_content),
+            html_content = encode_minimal(&draft.html_content),
+        )))
+}
@@ -0,0 +1,12 @@
+mod get;
+mod persistence;
+mod post;
+mod preview;
+mod publish;
+mod test_send;
+
+pub use get::{edit_draft_form, list_drafts};
+pub use post::{create_draft, edit_draft, remove_draft};
+pub use preview::preview_draft;
+pub use publish::publish_draft;
+pub use test_send::send_test_draft;
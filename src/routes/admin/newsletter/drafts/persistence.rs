@@ -0,0 +1,112 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+pub struct Draft {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[tracing::instrument(name = "Get drafts", skip(pool))]
+pub async fn get_drafts(pool: &PgPool) -> Result<Vec<Draft>, sqlx::Error> {
+    sqlx::query_as!(
+        Draft,
+        r#"
+        SELECT newsletter_issue_id, title, text_content, html_content, updated_at
+        FROM newsletter_issues
+        WHERE status = 'draft'
+        ORDER BY updated_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Get draft", skip(pool))]
+pub async fn get_draft(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<Draft>, sqlx::Error> {
+    sqlx::query_as!(
+        Draft,
+        r#"
+        SELECT newsletter_issue_id, title, text_content, html_content, updated_at
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1 AND status = 'draft'
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Insert draft", skip_all)]
+pub async fn insert_draft(
+    pool: &PgPool,
+    title: &str,
+    text_content: &str,
+    html_content: &str,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+          newsletter_issue_id,
+          title,
+          text_content,
+          html_content,
+          status,
+          updated_at
+        )
+        VALUES ($1, $2, $3, $4, 'draft', now())
+        "#,
+        newsletter_issue_id,
+        title,
+        text_content,
+        html_content
+    )
+    .execute(pool)
+    .await?;
+    Ok(newsletter_issue_id)
+}
+
+/// Returns `false` if there is no draft with the given id.
+#[tracing::instrument(name = "Update draft", skip(pool, title, text_content, html_content))]
+pub async fn update_draft(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    title: &str,
+    text_content: &str,
+    html_content: &str,
+) -> Result<bool, sqlx::Error> {
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET title = $2, text_content = $3, html_content = $4, updated_at = now()
+        WHERE newsletter_issue_id = $1 AND status = 'draft'
+        "#,
+        newsletter_issue_id,
+        title,
+        text_content,
+        html_content
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(n_updated_rows > 0)
+}
+
+/// Returns `false` if there is no draft with the given id.
+#[tracing::instrument(name = "Delete draft", skip(pool))]
+pub async fn delete_draft(pool: &PgPool, newsletter_issue_id: Uuid) -> Result<bool, sqlx::Error> {
+    let n_deleted_rows = sqlx::query!(
+        r#"DELETE FROM newsletter_issues WHERE newsletter_issue_id = $1 AND status = 'draft'"#,
+        newsletter_issue_id
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(n_deleted_rows > 0)
+}
@@ -0,0 +1,75 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::persistence::{delete_draft, insert_draft, update_draft};
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+}
+
+#[tracing::instrument(name = "Save a new draft", skip(form, pool))]
+pub async fn create_draft(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = insert_draft(
+        &pool,
+        &form.0.title,
+        &form.0.text_content,
+        &form.0.html_content,
+    )
+    .await
+    .map_err(e500)?;
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(see_other(&format!(
+        "/admin/newsletters/drafts/{}",
+        newsletter_issue_id
+    )))
+}
+
+#[tracing::instrument(name = "Edit a draft", skip(form, pool))]
+pub async fn edit_draft(
+    newsletter_issue_id: web::Path<Uuid>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let found = update_draft(
+        &pool,
+        newsletter_issue_id,
+        &form.0.title,
+        &form.0.text_content,
+        &form.0.html_content,
+    )
+    .await
+    .map_err(e500)?;
+    if !found {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(see_other(&format!(
+        "/admin/newsletters/drafts/{}",
+        newsletter_issue_id
+    )))
+}
+
+#[tracing::instrument(name = "Delete a draft", skip(pool))]
+pub async fn remove_draft(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let found = delete_draft(&pool, newsletter_issue_id.into_inner())
+        .await
+        .map_err(e500)?;
+    if !found {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    FlashMessage::info("The draft has been deleted.").send();
+    Ok(see_other("/admin/newsletters/drafts"))
+}
@@ -0,0 +1,42 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::persistence::get_draft;
+use crate::utils::e500;
+
+// The HTML content is rendered inside a sandboxed iframe, so that whatever
+// the draft contains cannot run scripts against the admin session.
+pub async fn preview_draft(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let Some(draft) = get_draft(&pool, newsletter_issue_id).await.map_err(e500)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Preview</title>
+</head>
+<body>
+    <h1>{title}</h1>
+    <h2>HTML</h2>
+    <iframe sandbox srcdoc="{html_content}" width="100%" height="600"></iframe>
+    <h2>Plain text</h2>
+    <pre>{text_content}</pre>
+    <p><a href="/admin/newsletters/drafts/{newsletter_issue_id}">&lt;- Back</a></p>
+</body>
+</html>"#,
+            title = encode_minimal(&draft.title),
+            html_content = encode_attribute(&draft.html_content),
+            text_content = encode_minimal(&draft.text_content),
+        )))
+}
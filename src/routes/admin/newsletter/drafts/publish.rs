@@ -0,0 +1,154 @@
+use std::ops::DerefMut;
+
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use super::persistence::get_draft;
+use crate::{
+    authentication::UserId,
+    domain::NewsletterIssueStatus,
+    idempotency::{save_response, try_processing, IdempotencyKey, NextAction},
+    issue_template::IssueTemplate,
+    routes::admin::newsletter::post::{
+        assign_slug, enqueue_delivery_tasks, in_archive, parse_scheduled_for, publication_message,
+        publication_status, resolve_target_lists, set_audience, success_message, tracking_enabled,
+    },
+    routes::admin::newsletter::recipients::parse_segment,
+    utils::{e400, e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    idempotency_key: String,
+    #[serde(default)]
+    scheduled_for: Option<String>,
+    #[serde(default)]
+    lists: Option<String>,
+    #[serde(default)]
+    segment: Option<String>,
+    #[serde(default)]
+    disable_tracking: Option<String>,
+    #[serde(default)]
+    hide_from_archive: Option<String>,
+}
+
+#[tracing::instrument(name = "Publish a draft", skip(form, pool))]
+pub async fn publish_draft(
+    newsletter_issue_id: web::Path<Uuid>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let user_id = *user_id.into_inner();
+    let FormData {
+        idempotency_key,
+        scheduled_for,
+        lists,
+        segment,
+        disable_tracking,
+        hide_from_archive,
+    } = form.0;
+    let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    let scheduled_for = match scheduled_for {
+        Some(s) => parse_scheduled_for(&s).map_err(e400)?,
+        None => None,
+    };
+    let segment = parse_segment(segment.as_deref()).map_err(e400)?;
+    let list_ids = resolve_target_lists(&pool, lists.as_deref()).await?;
+
+    let mut transaction = match try_processing(&pool, &idempotency_key, user_id)
+        .await
+        .map_err(e500)?
+    {
+        NextAction::StartProcessing(t) => t,
+        NextAction::ReturnSavedResponse(saved_response) => {
+            success_message().send();
+            return Ok(saved_response);
+        }
+    };
+
+    // Checked once the request is known not to be a retry, which would find
+    // the draft already published
+    let Some(draft) = get_draft(&pool, newsletter_issue_id).await.map_err(e500)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    IssueTemplate::validate(&draft.title, &draft.html_content, &draft.text_content)
+        .map_err(e400)?;
+
+    let found = mark_draft_as_published(
+        &mut transaction,
+        newsletter_issue_id,
+        scheduled_for,
+        tracking_enabled(disable_tracking.as_deref()),
+        in_archive(hide_from_archive.as_deref(), segment.as_ref()),
+    )
+    .await
+    .context("Failed to publish the draft")
+    .map_err(e500)?;
+    if !found {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    assign_slug(&mut transaction, newsletter_issue_id, &draft.title)
+        .await
+        .context("Failed to assign a slug to the newsletter issue")
+        .map_err(e500)?;
+
+    set_audience(
+        &mut transaction,
+        newsletter_issue_id,
+        &list_ids,
+        segment.as_ref(),
+    )
+    .await
+    .context("Failed to store the audience of the newsletter issue")
+    .map_err(e500)?;
+    // Scheduled issues get their recipients when the schedule fires
+    if publication_status(scheduled_for) != NewsletterIssueStatus::Scheduled {
+        enqueue_delivery_tasks(&mut transaction, newsletter_issue_id)
+            .await
+            .context("Failed to enqueue delivery tasks")
+            .map_err(e500)?;
+    }
+
+    publication_message(scheduled_for).send();
+    let response = see_other(&format!("/admin/newsletters/{}", newsletter_issue_id));
+    let response = save_response(&idempotency_key, user_id, response, transaction)
+        .await
+        .map_err(e500)?;
+    Ok(response)
+}
+
+async fn mark_draft_as_published(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    scheduled_for: Option<DateTime<Utc>>,
+    tracking: bool,
+    in_archive: bool,
+) -> Result<bool, sqlx::Error> {
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            status = $3,
+            scheduled_for = $2,
+            published_at = COALESCE($2, now()),
+            tracking = $4,
+            in_archive = $5,
+            updated_at = now()
+        WHERE newsletter_issue_id = $1 AND status = 'draft'
+        "#,
+        newsletter_issue_id,
+        scheduled_for,
+        publication_status(scheduled_for).as_str(),
+        tracking,
+        in_archive
+    )
+    .execute(transaction.deref_mut())
+    .await?
+    .rows_affected();
+    Ok(n_updated_rows > 0)
+}
@@ -0,0 +1,96 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::persistence::get_draft;
+use crate::{
+    authentication::UserId,
+    domain::SubscriberEmail,
+    email_client::EmailSender,
+    issue_delivery_worker::personalize_sample,
+    routes::admin::email::get_user_email,
+    startup::{ApplicationBaseUrl, HmacSecret},
+    suppressions::is_suppressed,
+    utils::{e500, see_other},
+};
+
+#[tracing::instrument(
+    name = "Send a test of a draft",
+    skip(pool, email_client, base_url, hmac_secret)
+)]
+pub async fn send_test_draft(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<dyn EmailSender>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let edit_url = format!("/admin/newsletters/drafts/{}", newsletter_issue_id);
+    if get_draft(&pool, newsletter_issue_id)
+        .await
+        .map_err(e500)?
+        .is_none()
+    {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+
+    let email = get_user_email(*user_id.into_inner(), &pool)
+        .await
+        .map_err(e500)?;
+    let Some(email) = email.and_then(|e| SubscriberEmail::parse(e).ok()) else {
+        FlashMessage::error("Set an email address for your account before sending a test.").send();
+        return Ok(see_other(&edit_url));
+    };
+    let mut connection = pool
+        .acquire()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    if is_suppressed(&mut connection, email.as_ref())
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error(format!("{} is on the suppression list.", email)).send();
+        return Ok(see_other(&edit_url));
+    }
+
+    // Rendered as subscribers will get it, with placeholder values for the
+    // merge tags and the unsubscribe link
+    let email = match personalize_sample(&pool, newsletter_issue_id, email, &base_url, &hmac_secret)
+        .await
+        .map_err(e500)?
+    {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e.to_string()).send();
+            return Ok(see_other(&edit_url));
+        }
+    };
+    let headers: Vec<(&str, &str)> = email
+        .headers
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect();
+    email_client
+        .send_email(
+            &email.recipient,
+            &format!("[Test] {}", email.subject),
+            &email.html_content,
+            &email.text_content,
+            &headers,
+        )
+        .await
+        .context("Failed to send a test of the draft")
+        .map_err(e500)?;
+
+    FlashMessage::info(format!(
+        "A test email has been sent to {}.",
+        email.recipient
+    ))
+    .send();
+    Ok(see_other(&edit_url))
+}
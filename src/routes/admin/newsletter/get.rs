@@ -1,9 +1,18 @@
-use actix_web::{http::header::ContentType, HttpResponse};
+use actix_web::{http::header::ContentType, web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
 use std::fmt::Write;
 
+use super::recipients::recipient_count_script;
+use crate::mailing_lists::{get_lists, DEFAULT_LIST_SLUG};
+use crate::utils::e500;
+
 pub async fn publish_newsletter_form(
     flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let idempotency_key = uuid::Uuid::new_v4();
     let mut msg_html = String::new();
@@ -11,6 +20,29 @@ pub async fn publish_newsletter_form(
         writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
     }
 
+    let available_lists = get_lists(&pool)
+        .await
+        .map_err(e500)?
+        .into_iter()
+        .map(|list| encode_minimal(&list.slug))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let recipient_count = recipient_count_script("publish-form");
+
+    let mut issues_html = String::new();
+    for issue in get_published_issues(&pool).await.map_err(e500)? {
+        writeln!(
+            issues_html,
+            r#"<li><a href="/admin/newsletters/{}">{}</a> ({}, {})</li>"#,
+            issue.newsletter_issue_id,
+            encode_minimal(&issue.title),
+            issue.status,
+            issue.published_at.to_rfc3339()
+        )
+        .unwrap();
+    }
+
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
@@ -23,7 +55,7 @@ pub async fn publish_newsletter_form(
             </head>
             <body>
             {msg_html}
-            <form action="/admin/newsletters" method="post">
+            <form id="publish-form" action="/admin/newsletters" method="post">
                 <label>Tittle
                     <input
                         type="text"
@@ -45,11 +77,69 @@ pub async fn publish_newsletter_form(
                         name="html_content"
                     >
                 </label>
+                <label>Publish at (UTC, leave empty to publish now)
+                    <input
+                        type="datetime-local"
+                        name="scheduled_for"
+                    >
+                </label>
+                <label>Lists (comma-separated, one of {available_lists})
+                    <input
+                        type="text"
+                        placeholder="{DEFAULT_LIST_SLUG}"
+                        name="lists"
+                    >
+                </label>
+                <label>Segment (e.g. tag:beta AND NOT tag:churned, leave empty for everyone)
+                    <input
+                        type="text"
+                        name="segment"
+                    >
+                </label>
+                <label>
+                    <input type="checkbox" name="disable_tracking" value="true">
+                    Don't track opens and clicks
+                </label>
+                <label>
+                    <input type="checkbox" name="hide_from_archive" value="true">
+                    Keep out of the public archive and feeds
+                </label>
                 <input hidden type="text" name="idempotency_key" value={idempotency_key}/>
-                <button type="submit">Login</button>
+                <button type="submit">Publish</button>
+                <button type="submit" formaction="/admin/newsletters/drafts">Save as draft</button>
             </form>
+            {recipient_count}
+            <p><a href="/admin/newsletters/drafts">Drafts</a></p>
+            <h2>Published issues</h2>
+            <ul>
+            {issues_html}
+            </ul>
             </body>
             </html>
         "#
         )))
 }
+
+struct PublishedIssue {
+    newsletter_issue_id: uuid::Uuid,
+    title: String,
+    status: String,
+    published_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Get published issues", skip(pool))]
+async fn get_published_issues(pool: &PgPool) -> Result<Vec<PublishedIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        PublishedIssue,
+        r#"
+        SELECT newsletter_issue_id, title, status, published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE status <> 'draft'
+        ORDER BY published_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve published newsletter issues.")?;
+    Ok(issues)
+}
@@ -1,5 +1,13 @@
+mod drafts;
 mod get;
 mod post;
+mod recipients;
+mod report;
+mod schedule;
 
+pub use drafts::*;
 pub use get::publish_newsletter_form;
-pub use post::publish_newsletter;
+pub use post::{enqueue_delivery_tasks, publish_newsletter};
+pub use recipients::count_newsletter_recipients;
+pub use report::newsletter_delivery_report;
+pub use schedule::{cancel_scheduled_newsletter, reschedule_newsletter};
@@ -1,16 +1,20 @@
 use std::ops::DerefMut;
 
-use actix_web::{web, HttpResponse};
+use actix_web::{web, Either, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
 use anyhow::Context;
-use sqlx::{PgPool, Postgres, Transaction};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sqlx::{PgPool, Postgres, QueryBuilder, Transaction};
 use uuid::Uuid;
 
+use super::recipients::{parse_segment, push_recipients};
 use crate::{
     authentication::UserId,
-    domain::SubscriberEmail,
-    email_client::EmailClient,
+    domain::{NewsletterIssueStatus, Segment},
     idempotency::{save_response, try_processing, IdempotencyKey, NextAction},
+    issue_delivery_worker::DELIVERY_QUEUE_CHANNEL,
+    issue_template::IssueTemplate,
+    mailing_lists::{parse_slugs, resolve_lists, ResolveListsError},
     utils::{e400, e500, see_other},
 };
 
@@ -20,13 +24,28 @@ pub struct FormData {
     pub text_content: String,
     pub html_content: String,
     pub idempotency_key: String,
+    // Left empty to publish right away
+    #[serde(default)]
+    pub scheduled_for: Option<String>,
+    // Comma-separated slugs of the lists to publish to, the default list if left empty
+    #[serde(default)]
+    pub lists: Option<String>,
+    // A tag expression narrowing down the recipients, e.g. `tag:beta AND NOT tag:churned`
+    #[serde(default)]
+    pub segment: Option<String>,
+    // Set, as by a ticked checkbox, to send the issue without open and click tracking
+    #[serde(default)]
+    pub disable_tracking: Option<String>,
+    // Set, as by a ticked checkbox, to keep the issue out of the public archive and feeds
+    #[serde(default)]
+    pub hide_from_archive: Option<String>,
 }
 
-#[tracing::instrument(name = "publish newsletter", skip(form, pool, email_client))]
+// The same handler serves both the admin form and JSON API clients.
+#[tracing::instrument(name = "publish newsletter", skip(body, pool))]
 pub async fn publish_newsletter(
-    form: web::Form<FormData>,
+    body: Either<web::Form<FormData>, web::Json<FormData>>,
     pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
     user_id: web::ReqData<UserId>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = *user_id.into_inner();
@@ -35,8 +54,24 @@ pub async fn publish_newsletter(
         text_content,
         html_content,
         idempotency_key,
-    } = form.0;
+        scheduled_for,
+        lists,
+        segment,
+        disable_tracking,
+        hide_from_archive,
+    } = match body {
+        Either::Left(form) => form.into_inner(),
+        Either::Right(json) => json.into_inner(),
+    };
     let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    // Merge tags are rendered by the workers, a mistake must not surface halfway through a send
+    IssueTemplate::validate(&title, &html_content, &text_content).map_err(e400)?;
+    let scheduled_for = match scheduled_for {
+        Some(s) => parse_scheduled_for(&s).map_err(e400)?,
+        None => None,
+    };
+    let segment = parse_segment(segment.as_deref()).map_err(e400)?;
+    let list_ids = resolve_target_lists(&pool, lists.as_deref()).await?;
 
     let mut transaction = match try_processing(&pool, &idempotency_key, user_id)
         .await
@@ -49,69 +84,118 @@ pub async fn publish_newsletter(
         }
     };
 
-    let issue_id = insert_newsletter_issue(&mut transaction, &title, &text_content, &html_content)
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &title,
+        &text_content,
+        &html_content,
+        scheduled_for,
+        tracking_enabled(disable_tracking.as_deref()),
+        in_archive(hide_from_archive.as_deref(), segment.as_ref()),
+    )
+    .await
+    .context("Failed to store newsletter issue details")
+    .map_err(e500)?;
+    assign_slug(&mut transaction, issue_id, &title)
         .await
-        .context("Failed to store newsletter issue details")
+        .context("Failed to assign a slug to the newsletter issue")
         .map_err(e500)?;
 
-    enqueue_delivery_tasks(&mut transaction, issue_id)
+    set_audience(&mut transaction, issue_id, &list_ids, segment.as_ref())
         .await
-        .context("Failed to enqueue delivery tasks")
+        .context("Failed to store the audience of the newsletter issue")
         .map_err(e500)?;
+    // Scheduled issues get their recipients when the schedule fires
+    if publication_status(scheduled_for) != NewsletterIssueStatus::Scheduled {
+        enqueue_delivery_tasks(&mut transaction, issue_id)
+            .await
+            .context("Failed to enqueue delivery tasks")
+            .map_err(e500)?;
+    }
 
-    // let subscribers = get_confirmed_subscribers(&pool).await.map_err(e500)?;
-    // for subscriber in subscribers {
-    //     match subscriber {
-    //         Ok(subscriber) => {
-    //             email_client
-    //                 .send_email(&subscriber.email, &title, &html_content, &text_content)
-    //                 .await
-    //                 .with_context(|| {
-    //                     format!(
-    //                         "Failed to send newsletter issue to {}",
-    //                         subscriber.email.as_ref().clone()
-    //                     )
-    //                 })
-    //                 .map_err(e500)?;
-    //         }
-    //         Err(error) => {
-    //             tracing::warn!(error.cause_chain =? error, "Skipping a confirmed subscriber. \
-    //             Their stored contact details are invalid")
-    //         }
-    //     }
-    // }
-    success_message().send();
+    publication_message(scheduled_for).send();
     let response = see_other("/admin/newsletters");
-    let response = save_response(&pool, &idempotency_key, user_id, response, transaction)
+    let response = save_response(&idempotency_key, user_id, response, transaction)
         .await
         .map_err(e500)?;
     Ok(response)
 }
 
-fn success_message() -> FlashMessage {
+pub fn success_message() -> FlashMessage {
     FlashMessage::info("The newsletter issue has been published!")
 }
 
-#[derive(Clone)]
-pub struct ConfirmedSubscriber {
-    email: SubscriberEmail,
+pub fn publication_message(scheduled_for: Option<DateTime<Utc>>) -> FlashMessage {
+    match scheduled_for {
+        Some(scheduled_for) if scheduled_for > Utc::now() => FlashMessage::info(format!(
+            "The newsletter issue has been scheduled for {}.",
+            scheduled_for.to_rfc3339()
+        )),
+        _ => success_message(),
+    }
+}
+
+// Issues with a publication time in the future wait as `scheduled`,
+// everything else goes straight to the delivery worker.
+pub fn publication_status(scheduled_for: Option<DateTime<Utc>>) -> NewsletterIssueStatus {
+    match scheduled_for {
+        Some(scheduled_for) if scheduled_for > Utc::now() => NewsletterIssueStatus::Scheduled,
+        _ => NewsletterIssueStatus::Sending,
+    }
+}
+
+/// Tracking is on unless the `disable_tracking` field is set to anything
+/// other than `false`.
+pub fn tracking_enabled(disable_tracking: Option<&str>) -> bool {
+    !is_checked(disable_tracking)
+}
+
+/// Issues show in the public archive and feeds unless `hide_from_archive`
+/// is set, like `disable_tracking`. Issues sent to a segment never do: they
+/// are only meant for the subscribers in it.
+pub fn in_archive(hide_from_archive: Option<&str>, segment: Option<&Segment>) -> bool {
+    segment.is_none() && !is_checked(hide_from_archive)
 }
 
-#[tracing::instrument(name = "Get confirmed subscribers", skip(pool))]
-pub async fn get_confirmed_subscribers(
+// Unticked checkboxes are left out of forms, JSON clients may send `false`
+fn is_checked(field: Option<&str>) -> bool {
+    match field.map(str::trim) {
+        None | Some("") => false,
+        Some(value) => !value.eq_ignore_ascii_case("false"),
+    }
+}
+
+/// Accepts either an RFC 3339 timestamp or the `YYYY-MM-DDTHH:MM` value
+/// produced by a `datetime-local` input, which is interpreted as UTC.
+/// An empty string means "publish right away".
+pub fn parse_scheduled_for(s: &str) -> Result<Option<DateTime<Utc>>, anyhow::Error> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Ok(None);
+    }
+    if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+        return Ok(Some(t.with_timezone(&Utc)));
+    }
+    let t = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M")
+        .with_context(|| format!("{} is not a valid publication time", s))?;
+    Ok(Some(t.and_utc()))
+}
+
+/// Looks up the lists an issue is published to. Unknown slugs are rejected.
+pub async fn resolve_target_lists(
     pool: &PgPool,
-) -> Result<Vec<Result<ConfirmedSubscriber, anyhow::Error>>, anyhow::Error> {
-    let rows = sqlx::query!(r#"SELECT email FROM subscriptions WHERE status = 'confirmed'"#)
-        .fetch_all(pool)
-        .await?;
-    let subscribers = rows
-        .into_iter()
-        .map(|row| match SubscriberEmail::parse(row.email) {
-            Ok(email) => Ok(ConfirmedSubscriber { email }),
-            Err(error) => Err(anyhow::anyhow!(error)),
-        })
-        .collect();
-    Ok(subscribers)
+    lists: Option<&str>,
+) -> Result<Vec<Uuid>, actix_web::Error> {
+    let mut connection = pool
+        .acquire()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    match resolve_lists(&mut connection, &parse_slugs(lists)).await {
+        Ok(lists) => Ok(lists.into_iter().map(|list| list.list_id).collect()),
+        Err(e @ ResolveListsError::UnknownList(_)) => Err(e400(e)),
+        Err(e) => Err(e500(e)),
+    }
 }
 
 #[tracing::instrument(skip_all)]
@@ -120,6 +204,9 @@ pub async fn insert_newsletter_issue(
     title: &str,
     text_content: &str,
     html_content: &str,
+    scheduled_for: Option<DateTime<Utc>>,
+    tracking: bool,
+    in_archive: bool,
 ) -> Result<Uuid, sqlx::Error> {
     let newsletter_issue_id = Uuid::new_v4();
     sqlx::query!(
@@ -129,38 +216,222 @@ pub async fn insert_newsletter_issue(
           title,
           text_content,
           html_content,
-          published_at
+          published_at,
+          scheduled_for,
+          status,
+          tracking,
+          in_archive
         )
-        VALUES ($1, $2, $3, $4, now())
+        VALUES ($1, $2, $3, $4, COALESCE($5, now()), $5, $6, $7, $8)
         "#,
         newsletter_issue_id,
         title,
         text_content,
-        html_content
+        html_content,
+        scheduled_for,
+        publication_status(scheduled_for).as_str(),
+        tracking,
+        in_archive
     )
     .execute(transaction.deref_mut())
     .await?;
     Ok(newsletter_issue_id)
 }
 
+// The advisory lock serializing slug assignments
+const SLUG_LOCK_KEY: i64 = 0x736c_7567;
+
+/// Gives a published issue the slug its web page is found at, derived from
+/// its title. Issues sharing a title get a numbered suffix.
+#[tracing::instrument(skip(transaction))]
+pub async fn assign_slug(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    title: &str,
+) -> Result<(), sqlx::Error> {
+    // Held until the transaction ends, so that issues published at the same
+    // time can't both pick the same free slug. A single lock for every slug:
+    // `our-issue-2` is both a title of its own and a suffix of `our-issue`.
+    sqlx::query!("SELECT pg_advisory_xact_lock($1)", SLUG_LOCK_KEY)
+        .execute(transaction.deref_mut())
+        .await?;
+    let slug = slugify(title);
+    let taken = sqlx::query_scalar!(
+        r#"
+        SELECT slug AS "slug!" FROM newsletter_issues
+        WHERE slug = $1 OR slug LIKE $1 || '-%'
+        "#,
+        slug
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+    let slug = std::iter::once(slug.clone())
+        .chain((2..).map(|n| format!("{}-{}", slug, n)))
+        .find(|candidate| !taken.contains(candidate))
+        .expect("There is always a free suffix");
+    sqlx::query!(
+        r#"UPDATE newsletter_issues SET slug = $2 WHERE newsletter_issue_id = $1"#,
+        newsletter_issue_id,
+        slug
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    Ok(())
+}
+
+// Lowercase ASCII letters and digits, with a dash for anything in between,
+// e.g. `Our 1st issue!` becomes `our-1st-issue`.
+fn slugify(title: &str) -> String {
+    let slug = title
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_ascii_lowercase)
+        .collect::<Vec<_>>()
+        .join("-");
+    if slug.is_empty() {
+        "issue".into()
+    } else {
+        slug
+    }
+}
+
+/// Stores the lists and segment an issue is published to. Recipients are
+/// only resolved when deliveries are enqueued, see `enqueue_delivery_tasks`.
 #[tracing::instrument(skip_all)]
-async fn enqueue_delivery_tasks(
+pub async fn set_audience(
     transaction: &mut Transaction<'_, Postgres>,
     newsletter_issue_id: Uuid,
+    list_ids: &[Uuid],
+    segment: Option<&Segment>,
 ) -> Result<(), sqlx::Error> {
     sqlx::query!(
         r#"
-        INSERT INTO issue_delivery_queue (
+        INSERT INTO newsletter_issue_lists (newsletter_issue_id, list_id)
+        SELECT $1, list_id FROM UNNEST($2::uuid[]) AS list_id
+        ON CONFLICT DO NOTHING
+        "#,
+        newsletter_issue_id,
+        list_ids
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    sqlx::query!(
+        r#"UPDATE newsletter_issues SET segment = $2 WHERE newsletter_issue_id = $1"#,
+        newsletter_issue_id,
+        segment.map(|segment| segment.to_string())
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    Ok(())
+}
+
+/// Queues a delivery to everyone in the audience of an issue at this point
+/// in time: right away when it is published, or when its schedule fires.
+/// Subscribers on several of the target lists get the issue once.
+#[tracing::instrument(skip(transaction))]
+pub async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    let list_ids = sqlx::query_scalar!(
+        r#"SELECT list_id FROM newsletter_issue_lists WHERE newsletter_issue_id = $1"#,
+        newsletter_issue_id
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+    let segment = sqlx::query_scalar!(
+        r#"SELECT segment FROM newsletter_issues WHERE newsletter_issue_id = $1"#,
+        newsletter_issue_id
+    )
+    .fetch_one(transaction.deref_mut())
+    .await?
+    .map(|segment| Segment::parse(&segment))
+    .transpose()
+    .map_err(anyhow::Error::msg)
+    .context("The stored segment is invalid")?;
+
+    let mut query = QueryBuilder::new(
+        "INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email) SELECT ",
+    );
+    query.push_bind(newsletter_issue_id);
+    query.push(", s.email");
+    push_recipients(&mut query, &list_ids, segment.as_ref());
+    query.build().execute(transaction.deref_mut()).await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_deliveries (
           newsletter_issue_id,
-          subscriber_email
+          subscriber_email,
+          status,
+          created_at,
+          updated_at
         )
-        SELECT $1, email
-        FROM subscriptions
-        WHERE status = 'confirmed'
+        SELECT newsletter_issue_id, subscriber_email, 'pending', now(), now()
+        FROM issue_delivery_queue
+        WHERE newsletter_issue_id = $1
         "#,
         newsletter_issue_id
     )
     .execute(transaction.deref_mut())
     .await?;
+    // Delivered on commit, waking up idle delivery workers
+    sqlx::query!("SELECT pg_notify($1, '')", DELIVERY_QUEUE_CHANNEL)
+        .execute(transaction.deref_mut())
+        .await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{in_archive, parse_scheduled_for, slugify, tracking_enabled};
+    use crate::domain::Segment;
+    use claims::{assert_err, assert_none};
+
+    #[test]
+    fn slugs_only_keep_letters_and_digits() {
+        assert_eq!(slugify("Our 1st issue!"), "our-1st-issue");
+        assert_eq!(slugify("  Hello, {{ name }}  "), "hello-name");
+        assert_eq!(slugify("!!!"), "issue");
+    }
+
+    #[test]
+    fn tracking_is_on_unless_disabled() {
+        assert!(tracking_enabled(None));
+        assert!(tracking_enabled(Some("")));
+        assert!(tracking_enabled(Some("false")));
+        assert!(!tracking_enabled(Some("true")));
+        assert!(!tracking_enabled(Some("on")));
+    }
+
+    #[test]
+    fn issues_sent_to_a_segment_stay_out_of_the_archive() {
+        let segment = Segment::parse("tag:beta").unwrap();
+        assert!(in_archive(None, None));
+        assert!(!in_archive(Some("true"), None));
+        assert!(!in_archive(None, Some(&segment)));
+    }
+
+    #[test]
+    fn an_empty_publication_time_means_right_away() {
+        assert_none!(parse_scheduled_for("  ").unwrap());
+    }
+
+    #[test]
+    fn rfc3339_publication_times_are_accepted() {
+        let t = parse_scheduled_for("2024-01-16T09:00:00+01:00")
+            .unwrap()
+            .unwrap();
+        assert_eq!(t.to_rfc3339(), "2024-01-16T08:00:00+00:00");
+    }
+
+    #[test]
+    fn datetime_local_publication_times_are_read_as_utc() {
+        let t = parse_scheduled_for("2024-01-16T09:00").unwrap().unwrap();
+        assert_eq!(t.to_rfc3339(), "2024-01-16T09:00:00+00:00");
+    }
+
+    #[test]
+    fn invalid_publication_times_are_rejected() {
+        assert_err!(parse_scheduled_for("next tuesday"));
+    }
+}
@@ -0,0 +1,110 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use super::post::resolve_target_lists;
+use crate::domain::Segment;
+use crate::utils::{e400, e500};
+
+/// Reads the segment field of the publish forms. Left empty, the issue goes
+/// to everyone on the target lists.
+pub fn parse_segment(segment: Option<&str>) -> Result<Option<Segment>, String> {
+    match segment.map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(segment) => Segment::parse(segment).map(Some),
+    }
+}
+
+/// Appends the `FROM` and `WHERE` clauses selecting the recipients of an
+/// issue as `s`: confirmed subscribers, confirmed on at least one of
+/// `list_ids` and, if there is one, in `segment`.
+pub fn push_recipients(
+    builder: &mut QueryBuilder<'_, Postgres>,
+    list_ids: &[Uuid],
+    segment: Option<&Segment>,
+) {
+    builder.push(
+        " FROM subscriptions s \
+        WHERE s.status = 'confirmed' \
+        AND EXISTS ( \
+            SELECT 1 FROM list_memberships m \
+            WHERE m.subscriber_id = s.id AND m.status = 'confirmed' AND m.list_id = ANY(",
+    );
+    builder.push_bind(list_ids.to_vec());
+    builder.push(
+        ")) \
+        AND NOT EXISTS ( \
+            SELECT 1 FROM suppressions x \
+            WHERE x.email = lower(s.email) OR x.domain = lower(split_part(s.email, '@', 2)) \
+        )",
+    );
+    if let Some(segment) = segment {
+        builder.push(" AND ");
+        segment.push_sql(builder);
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct RecipientsQuery {
+    #[serde(default)]
+    lists: Option<String>,
+    #[serde(default)]
+    segment: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct RecipientCount {
+    recipients: i64,
+}
+
+/// How many subscribers an issue would reach with the given lists and
+/// segment. The publish forms call it as the admin types.
+#[tracing::instrument(name = "Count newsletter recipients", skip(query, pool))]
+pub async fn count_newsletter_recipients(
+    query: web::Query<RecipientsQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let segment = parse_segment(query.segment.as_deref()).map_err(e400)?;
+    let list_ids = resolve_target_lists(&pool, query.lists.as_deref()).await?;
+
+    let mut builder = QueryBuilder::new("SELECT COUNT(*)");
+    push_recipients(&mut builder, &list_ids, segment.as_ref());
+    let recipients: i64 = builder
+        .build_query_scalar()
+        .fetch_one(pool.get_ref())
+        .await
+        .context("Failed to count the recipients of a newsletter issue.")
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(RecipientCount { recipients }))
+}
+
+/// Keeps the recipient count next to the publish button in sync with the
+/// lists and segment fields of `form_id`.
+pub fn recipient_count_script(form_id: &str) -> String {
+    format!(
+        r#"<p>Recipients: <output id="{form_id}-recipients">-</output></p>
+    <script>
+    (function () {{
+        const form = document.getElementById("{form_id}");
+        const output = document.getElementById("{form_id}-recipients");
+        let timer;
+        async function refresh() {{
+            const params = new URLSearchParams({{
+                lists: form.elements["lists"].value,
+                segment: form.elements["segment"].value,
+            }});
+            const response = await fetch("/admin/newsletters/recipients?" + params);
+            output.textContent = response.ok
+                ? (await response.json()).recipients
+                : await response.text();
+        }}
+        form.addEventListener("input", () => {{
+            clearTimeout(timer);
+            timer = setTimeout(refresh, 300);
+        }});
+        refresh();
+    }})();
+    </script>"#
+    )
+}
@@ -0,0 +1,340 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::{
+    delivery_events::DeliveryEventKind,
+    issue_delivery_worker::DeliveryStatus,
+    utils::{e400, e500},
+};
+
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    status: Option<String>,
+}
+
+pub async fn newsletter_delivery_report(
+    newsletter_issue_id: web::Path<Uuid>,
+    query: web::Query<QueryParams>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let status = match query.0.status {
+        Some(status) => DeliveryStatus::try_from(status).map_err(e400)?,
+        None => DeliveryStatus::Failed,
+    };
+
+    let Some(issue) = get_issue(&pool, newsletter_issue_id).await.map_err(e500)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let totals = get_delivery_totals(&pool, newsletter_issue_id)
+        .await
+        .map_err(e500)?;
+    let event_totals = get_event_totals(&pool, newsletter_issue_id)
+        .await
+        .map_err(e500)?;
+    let engagement = get_engagement(&pool, newsletter_issue_id)
+        .await
+        .map_err(e500)?;
+    let deliveries = get_deliveries(&pool, newsletter_issue_id, status)
+        .await
+        .map_err(e500)?;
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let schedule_html = match issue.scheduled_for {
+        Some(scheduled_for) if scheduled_for > Utc::now() => format!(
+            r#"<p>Scheduled for {scheduled_for}</p>
+    <form action="/admin/newsletters/{newsletter_issue_id}/reschedule" method="post">
+        <label>New publication time (UTC)
+            <input type="datetime-local" name="scheduled_for">
+        </label>
+        <button type="submit">Reschedule</button>
+    </form>
+    <form action="/admin/newsletters/{newsletter_issue_id}/cancel" method="post">
+        <button type="submit">Cancel</button>
+    </form>"#,
+            scheduled_for = scheduled_for.to_rfc3339()
+        ),
+        _ => String::new(),
+    };
+
+    let mut totals_html = String::new();
+    for s in [
+        DeliveryStatus::Sent,
+        DeliveryStatus::Failed,
+        DeliveryStatus::Pending,
+        DeliveryStatus::Skipped,
+    ] {
+        writeln!(
+            totals_html,
+            r#"<li><a href="/admin/newsletters/{newsletter_issue_id}?status={0}">{0}</a>: {1}</li>"#,
+            s.as_str(),
+            totals.get(s)
+        )
+        .unwrap();
+    }
+
+    // As reported by the email provider after sending
+    let mut events_html = String::new();
+    for kind in DeliveryEventKind::ALL {
+        let count = event_totals
+            .iter()
+            .find(|(k, _)| *k == kind)
+            .map_or(0, |(_, count)| *count);
+        writeln!(events_html, "<li>{}: {}</li>", kind.as_str(), count).unwrap();
+    }
+
+    let engagement_html = if issue.tracking {
+        let mut links_html = String::new();
+        for link in engagement.links {
+            writeln!(
+                links_html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                encode_minimal(&link.url),
+                link.unique_clicks,
+                link.total_clicks
+            )
+            .unwrap();
+        }
+        format!(
+            r#"<p>Unique opens: {}</p>
+    <table>
+        <tr><th>Link</th><th>Unique clicks</th><th>Total clicks</th></tr>
+{links_html}    </table>"#,
+            engagement.unique_opens
+        )
+    } else {
+        "<p>Opens and clicks are not tracked for this issue.</p>".to_string()
+    };
+
+    let mut rows_html = String::new();
+    for d in deliveries {
+        writeln!(
+            rows_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            encode_minimal(&d.subscriber_email),
+            d.n_attempts,
+            encode_minimal(d.provider_message_id.as_deref().unwrap_or("")),
+            encode_minimal(d.last_error.as_deref().unwrap_or("")),
+            d.updated_at.to_rfc3339()
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Delivery report</title>
+</head>
+<body>
+    {msg_html}
+    <h1>{title}</h1>
+    {schedule_html}
+    <ul>
+{totals_html}    </ul>
+    <h2>Delivery events</h2>
+    <ul>
+{events_html}    </ul>
+    <h2>Engagement</h2>
+    {engagement_html}
+    <h2>{status} deliveries</h2>
+    <table>
+        <tr><th>Email</th><th>Attempts</th><th>Message id</th><th>Last error</th><th>Updated at</th></tr>
+{rows_html}    </table>
+    <p><a href="/admin/newsletters">&lt;- Back</a></p>
+</body>
+</html>"#,
+            title = encode_minimal(&issue.title),
+            status = status.as_str(),
+        )))
+}
+
+#[derive(Default)]
+struct DeliveryTotals {
+    pending: i64,
+    sent: i64,
+    failed: i64,
+    skipped: i64,
+}
+
+impl DeliveryTotals {
+    fn get(&self, status: DeliveryStatus) -> i64 {
+        match status {
+            DeliveryStatus::Pending => self.pending,
+            DeliveryStatus::Sent => self.sent,
+            DeliveryStatus::Failed => self.failed,
+            DeliveryStatus::Skipped => self.skipped,
+        }
+    }
+}
+
+struct Issue {
+    title: String,
+    scheduled_for: Option<DateTime<Utc>>,
+    tracking: bool,
+}
+
+struct Engagement {
+    unique_opens: i64,
+    links: Vec<LinkClicks>,
+}
+
+struct LinkClicks {
+    url: String,
+    unique_clicks: i64,
+    total_clicks: i64,
+}
+
+struct Delivery {
+    subscriber_email: String,
+    n_attempts: i16,
+    provider_message_id: Option<String>,
+    last_error: Option<String>,
+    updated_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Get newsletter issue", skip(pool))]
+async fn get_issue(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<Issue>, anyhow::Error> {
+    let issue = sqlx::query_as!(
+        Issue,
+        r#"SELECT title, scheduled_for, tracking FROM newsletter_issues WHERE newsletter_issue_id = $1"#,
+        newsletter_issue_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the newsletter issue.")?;
+    Ok(issue)
+}
+
+#[tracing::instrument(name = "Get delivery totals", skip(pool))]
+async fn get_delivery_totals(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<DeliveryTotals, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT status, COUNT(*) AS "count!"
+        FROM issue_deliveries
+        WHERE newsletter_issue_id = $1
+        GROUP BY status
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to count deliveries.")?;
+    let mut totals = DeliveryTotals::default();
+    for row in rows {
+        match DeliveryStatus::try_from(row.status).map_err(anyhow::Error::msg)? {
+            DeliveryStatus::Pending => totals.pending = row.count,
+            DeliveryStatus::Sent => totals.sent = row.count,
+            DeliveryStatus::Failed => totals.failed = row.count,
+            DeliveryStatus::Skipped => totals.skipped = row.count,
+        }
+    }
+    Ok(totals)
+}
+
+#[tracing::instrument(name = "Get delivery event totals", skip(pool))]
+async fn get_event_totals(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Vec<(DeliveryEventKind, i64)>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT kind, COUNT(*) AS "count!"
+        FROM delivery_events
+        WHERE newsletter_issue_id = $1
+        GROUP BY kind
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to count delivery events.")?;
+    rows.into_iter()
+        .map(|row| {
+            let kind = DeliveryEventKind::try_from(row.kind).map_err(anyhow::Error::msg)?;
+            Ok((kind, row.count))
+        })
+        .collect()
+}
+
+#[tracing::instrument(name = "Get opens and clicks", skip(pool))]
+async fn get_engagement(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Engagement, anyhow::Error> {
+    let unique_opens = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(DISTINCT subscriber_id) AS "count!"
+        FROM tracking_events
+        WHERE newsletter_issue_id = $1 AND kind = 'open'
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to count opens.")?;
+    let links = sqlx::query_as!(
+        LinkClicks,
+        r#"
+        SELECT
+            url AS "url!",
+            COUNT(DISTINCT subscriber_id) AS "unique_clicks!",
+            COUNT(*) AS "total_clicks!"
+        FROM tracking_events
+        WHERE newsletter_issue_id = $1 AND kind = 'click'
+        GROUP BY url
+        ORDER BY 2 DESC, url
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to count clicks.")?;
+    Ok(Engagement {
+        unique_opens,
+        links,
+    })
+}
+
+#[tracing::instrument(name = "Get deliveries", skip(pool))]
+async fn get_deliveries(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    status: DeliveryStatus,
+) -> Result<Vec<Delivery>, anyhow::Error> {
+    let deliveries = sqlx::query_as!(
+        Delivery,
+        r#"
+        SELECT subscriber_email, n_attempts, provider_message_id, last_error, updated_at
+        FROM issue_deliveries
+        WHERE newsletter_issue_id = $1 AND status = $2
+        ORDER BY updated_at DESC
+        "#,
+        newsletter_issue_id,
+        status.as_str()
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve deliveries.")?;
+    Ok(deliveries)
+}
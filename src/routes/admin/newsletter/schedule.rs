@@ -0,0 +1,195 @@
+use std::ops::DerefMut;
+
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use super::post::parse_scheduled_for;
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    scheduled_for: String,
+}
+
+#[tracing::instrument(name = "Cancel a scheduled newsletter issue", skip(pool))]
+pub async fn cancel_scheduled_newsletter(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+
+    match lock_issue_schedule(&mut transaction, newsletter_issue_id)
+        .await
+        .map_err(e500)?
+    {
+        Schedule::NotFound => return Ok(HttpResponse::NotFound().finish()),
+        Schedule::Fired => {
+            FlashMessage::error(
+                "The newsletter issue is already being sent and can no longer be cancelled.",
+            )
+            .send();
+            return Ok(see_other(&format!(
+                "/admin/newsletters/{}",
+                newsletter_issue_id
+            )));
+        }
+        Schedule::Pending => {}
+    }
+
+    revert_to_draft(&mut transaction, newsletter_issue_id)
+        .await
+        .context("Failed to move the scheduled newsletter issue back to drafts.")
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to cancel a newsletter issue.")
+        .map_err(e500)?;
+
+    FlashMessage::info(
+        "The scheduled newsletter issue has been cancelled and moved back to drafts.",
+    )
+    .send();
+    Ok(see_other(&format!(
+        "/admin/newsletters/drafts/{}",
+        newsletter_issue_id
+    )))
+}
+
+#[tracing::instrument(name = "Reschedule a newsletter issue", skip(form, pool))]
+pub async fn reschedule_newsletter(
+    newsletter_issue_id: web::Path<Uuid>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let report_url = format!("/admin/newsletters/{}", newsletter_issue_id);
+
+    let scheduled_for = match parse_scheduled_for(&form.0.scheduled_for) {
+        Ok(Some(t)) if t > Utc::now() => t,
+        _ => {
+            FlashMessage::error("The new publication time must be a valid time in the future.")
+                .send();
+            return Ok(see_other(&report_url));
+        }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+
+    match lock_issue_schedule(&mut transaction, newsletter_issue_id)
+        .await
+        .map_err(e500)?
+    {
+        Schedule::NotFound => return Ok(HttpResponse::NotFound().finish()),
+        Schedule::Fired => {
+            FlashMessage::error(
+                "The newsletter issue is already being sent and can no longer be rescheduled.",
+            )
+            .send();
+            return Ok(see_other(&report_url));
+        }
+        Schedule::Pending => {}
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET scheduled_for = $2, published_at = $2, updated_at = now()
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+        scheduled_for
+    )
+    .execute(transaction.deref_mut())
+    .await
+    .context("Failed to reschedule the newsletter issue.")
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to reschedule a newsletter issue.")
+        .map_err(e500)?;
+
+    FlashMessage::info(format!(
+        "The newsletter issue has been rescheduled for {}.",
+        scheduled_for.to_rfc3339()
+    ))
+    .send();
+    Ok(see_other(&report_url))
+}
+
+enum Schedule {
+    NotFound,
+    // The issue has a publication time in the future
+    Pending,
+    // The issue was published right away or its publication time has passed
+    Fired,
+}
+
+// Locks the issue row, so that the schedule cannot change under our feet.
+async fn lock_issue_schedule(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<Schedule, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT status = 'scheduled' AND scheduled_for > now() AS is_pending
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        FOR UPDATE
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_optional(transaction.deref_mut())
+    .await?;
+    Ok(match row {
+        None => Schedule::NotFound,
+        Some(r) if r.is_pending == Some(true) => Schedule::Pending,
+        Some(_) => Schedule::Fired,
+    })
+}
+
+async fn revert_to_draft(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    // Nothing was enqueued yet: recipients are only picked when the schedule
+    // fires. The target lists, segment and slug are picked again when the
+    // draft is published
+    sqlx::query!(
+        r#"DELETE FROM newsletter_issue_lists WHERE newsletter_issue_id = $1"#,
+        newsletter_issue_id
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            status = 'draft',
+            scheduled_for = NULL,
+            published_at = NULL,
+            segment = NULL,
+            slug = NULL,
+            updated_at = now()
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    Ok(())
+}
@@ -31,7 +31,7 @@ pub async fn change_password(
     let username = get_username(*user_id, &pool).await.map_err(e500)?;
 
     let credentials = Credentials {
-        username: username,
+        username,
         password: form.0.current_password,
     };
 
@@ -41,7 +41,7 @@ pub async fn change_password(
                 FlashMessage::error("The current password is invalid.").send();
                 Ok(see_other("/admin/password"))
             }
-            AuthError::UnexpectedError(_) => Err(e500(e).into()),
+            AuthError::UnexpectedError(_) => Err(e500(e)),
         };
     }
     crate::authentication::change_password(*user_id, form.0.new_password, &pool)
@@ -0,0 +1,139 @@
+use actix_web::web::Bytes;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::borrow::Cow;
+use uuid::Uuid;
+
+use crate::domain::SubscriberStatus;
+use crate::utils::e400;
+
+// Rows fetched from the database per chunk of the response
+const EXPORT_PAGE_SIZE: i64 = 1000;
+
+#[derive(serde::Deserialize)]
+pub struct ExportQuery {
+    // Comma-separated statuses, e.g. `confirmed,pending_confirmation`
+    status: Option<String>,
+}
+
+struct ExportedSubscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    confirmed_at: Option<DateTime<Utc>>,
+}
+
+// Where the next page of the export starts
+struct Cursor {
+    after: Option<(DateTime<Utc>, Uuid)>,
+}
+
+/// Streams the subscriber list as CSV, in the format `import_subscribers` reads.
+#[tracing::instrument(name = "Export subscribers", skip(query, pool))]
+pub async fn export_subscribers(
+    query: web::Query<ExportQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let statuses = match &query.status {
+        Some(statuses) => Some(
+            statuses
+                .split(',')
+                .map(|s| SubscriberStatus::try_from(s.trim().to_owned()).map(|s| s.as_str().into()))
+                .collect::<Result<Vec<String>, _>>()
+                .map_err(e400)?,
+        ),
+        None => None,
+    };
+
+    let header = Bytes::from_static(b"email,name,status,subscribed_at,confirmed_at\n");
+    let rows = futures_util::stream::try_unfold(Some(Cursor { after: None }), move |cursor| {
+        let pool = pool.clone();
+        let statuses = statuses.clone();
+        async move {
+            let Some(cursor) = cursor else {
+                return Ok(None);
+            };
+            let page = get_page(&pool, statuses.as_deref(), cursor.after).await?;
+            if page.is_empty() {
+                return Ok(None);
+            }
+            let next = match page.len() as i64 {
+                n if n < EXPORT_PAGE_SIZE => None,
+                _ => page.last().map(|s| Cursor {
+                    after: Some((s.subscribed_at, s.id)),
+                }),
+            };
+            Ok::<_, anyhow::Error>(Some((to_csv(&page)?, next)))
+        }
+    });
+    let body =
+        futures_util::StreamExt::chain(futures_util::stream::once(async { Ok(header) }), rows);
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header((
+            "Content-Disposition",
+            r#"attachment; filename="subscribers.csv""#,
+        ))
+        .streaming(body))
+}
+
+async fn get_page(
+    pool: &PgPool,
+    statuses: Option<&[String]>,
+    after: Option<(DateTime<Utc>, Uuid)>,
+) -> Result<Vec<ExportedSubscriber>, anyhow::Error> {
+    let (after_subscribed_at, after_id) = after.unzip();
+    let page = sqlx::query_as!(
+        ExportedSubscriber,
+        r#"
+        SELECT id, email, name, status, subscribed_at, confirmed_at
+        FROM subscriptions
+        WHERE
+            ($1::text[] IS NULL OR status = ANY($1)) AND
+            ($2::timestamptz IS NULL OR (subscribed_at, id) > ($2, $3))
+        ORDER BY subscribed_at, id
+        LIMIT $4
+        "#,
+        statuses,
+        after_subscribed_at,
+        after_id,
+        EXPORT_PAGE_SIZE
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch subscribers to export.")?;
+    Ok(page)
+}
+
+fn to_csv(page: &[ExportedSubscriber]) -> Result<Bytes, anyhow::Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for s in page {
+        writer.write_record([
+            &escape_formula(&s.email),
+            &escape_formula(&s.name),
+            s.status.as_str(),
+            &s.subscribed_at.to_rfc3339(),
+            &s.confirmed_at.map(|t| t.to_rfc3339()).unwrap_or_default(),
+        ])?;
+    }
+    let csv = writer
+        .into_inner()
+        .context("Failed to write subscribers as CSV.")?;
+    Ok(Bytes::from(csv))
+}
+
+// Spreadsheets evaluate cells starting with one of these as formulas. Names and
+// addresses are typed in by whoever subscribes, so they are prefixed with `'`
+// to be shown as text. The import strips the prefix again.
+fn escape_formula(field: &str) -> Cow<'_, str> {
+    if field.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        Cow::Owned(format!("'{}", field))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
@@ -0,0 +1,89 @@
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::persistence::{
+    count_subscribers, find_subscriber, get_subscribers, Subscriber, SubscriberFilter,
+};
+use crate::domain::SubscriberStatus;
+use crate::utils::{e400, e500};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 100;
+
+#[derive(serde::Deserialize)]
+pub struct ListQuery {
+    status: Option<String>,
+    email: Option<String>,
+    // Pages start at 1
+    page: Option<i64>,
+    page_size: Option<i64>,
+}
+
+#[derive(serde::Serialize)]
+struct SubscriberPage {
+    subscribers: Vec<Subscriber>,
+    page: i64,
+    page_size: i64,
+    total: i64,
+}
+
+#[tracing::instrument(name = "List subscribers", skip(query, pool))]
+pub async fn list_subscribers(
+    query: web::Query<ListQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let ListQuery {
+        status,
+        email,
+        page,
+        page_size,
+    } = query.into_inner();
+    let page = page.unwrap_or(1);
+    let page_size = page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+    if page < 1 {
+        return Err(e400("The page number must be at least 1."));
+    }
+    if !(1..=MAX_PAGE_SIZE).contains(&page_size) {
+        return Err(e400(format!(
+            "The page size must be between 1 and {}.",
+            MAX_PAGE_SIZE
+        )));
+    }
+    let filter = SubscriberFilter {
+        status: status
+            .map(SubscriberStatus::try_from)
+            .transpose()
+            .map_err(e400)?,
+        email: email.filter(|e| !e.is_empty()),
+    };
+
+    let offset = page
+        .checked_sub(1)
+        .and_then(|p| p.checked_mul(page_size))
+        .ok_or_else(|| e400("The page number is too large."))?;
+    let subscribers = get_subscribers(&pool, &filter, page_size, offset)
+        .await
+        .map_err(e500)?;
+    let total = count_subscribers(&pool, &filter).await.map_err(e500)?;
+    Ok(HttpResponse::Ok().json(SubscriberPage {
+        subscribers,
+        page,
+        page_size,
+        total,
+    }))
+}
+
+#[tracing::instrument(name = "Get subscriber", skip(pool))]
+pub async fn get_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match find_subscriber(&pool, subscriber_id.into_inner())
+        .await
+        .map_err(e500)?
+    {
+        Some(subscriber) => Ok(HttpResponse::Ok().json(subscriber)),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
@@ -0,0 +1,513 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use csv_core::ReadRecordResult;
+use futures_util::StreamExt;
+use sqlx::PgPool;
+use std::collections::HashSet;
+use std::ops::DerefMut;
+use uuid::Uuid;
+
+use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName, SubscriberStatus};
+use crate::mailing_lists::{get_list_by_slug, MailingList, DEFAULT_LIST_SLUG};
+use crate::routes::{generate_subscription_token, send_confirmation_email, store_token};
+use crate::startup::ApplicationBaseUrl;
+use crate::suppressions::SuppressionSource;
+use crate::utils::{e400, e500};
+
+// Rows inserted per statement, and per transaction
+const IMPORT_BATCH_SIZE: usize = 500;
+
+#[derive(serde::Deserialize)]
+pub struct ImportQuery {
+    // Send a confirmation email to every imported `pending_confirmation` row
+    #[serde(default)]
+    send_confirmation: bool,
+    // The slug of the list imported subscribers join, the default list if left out
+    #[serde(default)]
+    list: Option<String>,
+}
+
+#[derive(serde::Serialize, Default)]
+struct ImportReport {
+    imported: usize,
+    // Rows whose email is already subscribed, or appears earlier in the file
+    duplicates: Vec<usize>,
+    errors: Vec<RowError>,
+}
+
+#[derive(serde::Serialize)]
+struct RowError {
+    row: usize,
+    error: String,
+}
+
+/// Imports subscribers from a CSV upload (`Content-Type: text/csv`).
+/// The header row names the columns: `email` and `name` are required,
+/// `status` and `subscribed_at` (RFC 3339) are optional.
+/// Rows are numbered like in a spreadsheet, the header being row 1.
+/// New subscribers join the `list` given in the query string with their
+/// imported status.
+#[tracing::instrument(name = "Import subscribers", skip_all)]
+pub async fn import_subscribers(
+    mut payload: web::Payload,
+    query: web::Query<ImportQuery>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let list_slug = query.list.as_deref().unwrap_or(DEFAULT_LIST_SLUG);
+    let mut connection = pool
+        .acquire()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let Some(list) = get_list_by_slug(&mut connection, list_slug)
+        .await
+        .context("Failed to look up the mailing list.")
+        .map_err(e500)?
+    else {
+        return Err(e400(format!(
+            "There is no mailing list called {}",
+            list_slug
+        )));
+    };
+    drop(connection);
+
+    let mut parser = CsvParser::new();
+    let mut importer = Importer::new(&pool, &base_url.0, &list, query.send_confirmation);
+    let mut records = Vec::new();
+    while let Some(chunk) = payload.next().await {
+        parser.feed(&chunk?, &mut records);
+        for record in records.drain(..) {
+            importer.add(record).await?;
+        }
+    }
+    parser.finish(&mut records);
+    for record in records.drain(..) {
+        importer.add(record).await?;
+    }
+    let report = importer.finish().await?;
+    Ok(HttpResponse::Ok().json(report))
+}
+
+struct Columns {
+    email: usize,
+    name: usize,
+    status: Option<usize>,
+    subscribed_at: Option<usize>,
+}
+
+impl Columns {
+    fn parse(header: &[Vec<u8>]) -> Result<Self, String> {
+        let names: Vec<String> = header
+            .iter()
+            .map(|field| {
+                String::from_utf8_lossy(field)
+                    .trim_start_matches('\u{feff}')
+                    .trim()
+                    .to_lowercase()
+            })
+            .collect();
+        let find = |column: &str| names.iter().position(|name| name == column);
+        match (find("email"), find("name")) {
+            (Some(email), Some(name)) => Ok(Self {
+                email,
+                name,
+                status: find("status"),
+                subscribed_at: find("subscribed_at"),
+            }),
+            _ => Err("The CSV header must have an `email` and a `name` column.".into()),
+        }
+    }
+}
+
+struct ImportedSubscriber {
+    row: usize,
+    new_subscriber: NewSubscriber,
+    status: SubscriberStatus,
+    subscribed_at: DateTime<Utc>,
+}
+
+fn parse_row(
+    columns: &Columns,
+    row: usize,
+    record: Vec<Vec<u8>>,
+) -> Result<ImportedSubscriber, String> {
+    let record = record
+        .into_iter()
+        .map(String::from_utf8)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| "The row is not valid UTF-8.".to_string())?;
+    let field = |i: usize| {
+        record
+            .get(i)
+            .map(|s| unescape_formula(s).trim())
+            .unwrap_or_default()
+    };
+    let optional_field = |i: Option<usize>| i.map(field).filter(|s| !s.is_empty());
+
+    let new_subscriber = NewSubscriber {
+        email: SubscriberEmail::parse(field(columns.email).to_owned())?,
+        name: SubscriberName::parse(field(columns.name).to_owned())?,
+    };
+    let status = match optional_field(columns.status) {
+        Some(status) => SubscriberStatus::try_from(status.to_owned())?,
+        None => SubscriberStatus::PendingConfirmation,
+    };
+    let subscribed_at = match optional_field(columns.subscribed_at) {
+        Some(subscribed_at) => DateTime::parse_from_rfc3339(subscribed_at)
+            .map_err(|_| format!("{} is not a valid RFC 3339 timestamp.", subscribed_at))?
+            .with_timezone(&Utc),
+        None => Utc::now(),
+    };
+    Ok(ImportedSubscriber {
+        row,
+        new_subscriber,
+        status,
+        subscribed_at,
+    })
+}
+
+// Undoes the `'` the export puts in front of cells a spreadsheet would take
+// for a formula, so that an exported file imports back as it was.
+fn unescape_formula(field: &str) -> &str {
+    match field.strip_prefix('\'') {
+        Some(rest) if rest.starts_with(['=', '+', '-', '@', '\t', '\r']) => rest,
+        _ => field,
+    }
+}
+
+// Validates rows as they come and inserts them in batches.
+struct Importer<'a> {
+    pool: &'a PgPool,
+    base_url: &'a str,
+    list: &'a MailingList,
+    send_confirmation: bool,
+    columns: Option<Columns>,
+    n_rows: usize,
+    seen_emails: HashSet<String>,
+    batch: Vec<ImportedSubscriber>,
+    report: ImportReport,
+}
+
+impl<'a> Importer<'a> {
+    fn new(
+        pool: &'a PgPool,
+        base_url: &'a str,
+        list: &'a MailingList,
+        send_confirmation: bool,
+    ) -> Self {
+        Self {
+            pool,
+            base_url,
+            list,
+            send_confirmation,
+            columns: None,
+            n_rows: 0,
+            seen_emails: HashSet::new(),
+            batch: Vec::with_capacity(IMPORT_BATCH_SIZE),
+            report: ImportReport::default(),
+        }
+    }
+
+    async fn add(&mut self, record: Vec<Vec<u8>>) -> Result<(), actix_web::Error> {
+        self.n_rows += 1;
+        let Some(columns) = &self.columns else {
+            self.columns = Some(Columns::parse(&record).map_err(e400)?);
+            return Ok(());
+        };
+        // Blank lines are skipped
+        if record
+            .iter()
+            .all(|field| field.iter().all(u8::is_ascii_whitespace))
+        {
+            return Ok(());
+        }
+        match parse_row(columns, self.n_rows, record) {
+            Ok(subscriber) => {
+                if !self
+                    .seen_emails
+                    .insert(subscriber.new_subscriber.email.as_ref().to_owned())
+                {
+                    self.report.duplicates.push(subscriber.row);
+                    return Ok(());
+                }
+                self.batch.push(subscriber);
+                if self.batch.len() >= IMPORT_BATCH_SIZE {
+                    self.flush().await.map_err(e500)?;
+                }
+            }
+            Err(error) => self.report.errors.push(RowError {
+                row: self.n_rows,
+                error,
+            }),
+        }
+        Ok(())
+    }
+
+    async fn finish(mut self) -> Result<ImportReport, actix_web::Error> {
+        if self.columns.is_none() {
+            return Err(e400("The CSV is empty."));
+        }
+        self.flush().await.map_err(e500)?;
+        self.report.duplicates.sort_unstable();
+        Ok(self.report)
+    }
+
+    async fn flush(&mut self) -> Result<(), anyhow::Error> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        let batch = std::mem::take(&mut self.batch);
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool.")?;
+        let ids: Vec<Uuid> = batch.iter().map(|_| Uuid::new_v4()).collect();
+        let emails: Vec<String> = batch
+            .iter()
+            .map(|s| s.new_subscriber.email.as_ref().to_owned())
+            .collect();
+        let names: Vec<String> = batch
+            .iter()
+            .map(|s| s.new_subscriber.name.as_ref().to_owned())
+            .collect();
+        let subscribed_at: Vec<DateTime<Utc>> = batch.iter().map(|s| s.subscribed_at).collect();
+        let statuses: Vec<String> = batch.iter().map(|s| s.status.as_str().into()).collect();
+        // Addresses that are already subscribed are left untouched. Confirmed
+        // subscribers are taken as confirmed when they subscribed.
+        let inserted: HashSet<Uuid> = sqlx::query!(
+            r#"
+            INSERT INTO subscriptions (id, email, name, subscribed_at, status, confirmed_at)
+            SELECT
+                id,
+                email,
+                name,
+                subscribed_at,
+                status,
+                CASE WHEN status = 'confirmed' THEN subscribed_at END
+            FROM UNNEST($1::uuid[], $2::text[], $3::text[], $4::timestamptz[], $5::text[])
+                AS imported (id, email, name, subscribed_at, status)
+            ON CONFLICT (email) DO NOTHING
+            RETURNING id
+            "#,
+            &ids,
+            &emails,
+            &names,
+            &subscribed_at,
+            &statuses
+        )
+        .fetch_all(transaction.deref_mut())
+        .await
+        .context("Failed to insert imported subscribers.")?
+        .into_iter()
+        .map(|r| r.id)
+        .collect();
+        let inserted_ids: Vec<Uuid> = inserted.iter().copied().collect();
+        sqlx::query!(
+            r#"
+            INSERT INTO list_memberships (list_id, subscriber_id, status, subscribed_at, confirmed_at)
+            SELECT
+                $1,
+                id,
+                CASE status
+                    WHEN 'confirmed' THEN 'confirmed'
+                    WHEN 'unsubscribed' THEN 'unsubscribed'
+                    ELSE 'pending_confirmation'
+                END,
+                subscribed_at,
+                confirmed_at
+            FROM subscriptions
+            WHERE id = ANY($2)
+            "#,
+            self.list.list_id,
+            &inserted_ids
+        )
+        .execute(transaction.deref_mut())
+        .await
+        .context("Failed to add imported subscribers to the mailing list.")?;
+        // Addresses another provider gave up on stay suppressed here
+        let (suppressed_emails, suppressed_statuses): (Vec<String>, Vec<String>) = batch
+            .iter()
+            .filter(|s| {
+                matches!(
+                    s.status,
+                    SubscriberStatus::Bounced | SubscriberStatus::Complained
+                )
+            })
+            .map(|s| {
+                (
+                    s.new_subscriber.email.as_ref().to_lowercase(),
+                    s.status.as_str().to_owned(),
+                )
+            })
+            .unzip();
+        sqlx::query!(
+            r#"
+            INSERT INTO suppressions (suppression_id, email, reason, source)
+            SELECT gen_random_uuid(), email, 'Imported as ' || status, $3
+            FROM UNNEST($1::text[], $2::text[]) AS t(email, status)
+            ON CONFLICT DO NOTHING
+            "#,
+            &suppressed_emails,
+            &suppressed_statuses,
+            SuppressionSource::Import.as_str()
+        )
+        .execute(transaction.deref_mut())
+        .await
+        .context("Failed to suppress imported addresses.")?;
+
+        for (subscriber, id) in batch.into_iter().zip(ids) {
+            if !inserted.contains(&id) {
+                self.report.duplicates.push(subscriber.row);
+                continue;
+            }
+            self.report.imported += 1;
+            if self.send_confirmation && subscriber.status == SubscriberStatus::PendingConfirmation
+            {
+                let subscription_token = generate_subscription_token();
+                store_token(
+                    &mut transaction,
+                    &id,
+                    &self.list.list_id,
+                    &subscription_token,
+                )
+                .await
+                .context("Failed to store the confirmation token for an imported subscriber.")?;
+                send_confirmation_email(
+                    &mut transaction,
+                    subscriber.new_subscriber,
+                    &self.list.name,
+                    self.base_url,
+                    &subscription_token,
+                )
+                .await
+                .context("Failed to queue a confirmation email.")?;
+            }
+        }
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to import subscribers.")?;
+        Ok(())
+    }
+}
+
+// Incremental CSV parser, fed the upload one chunk at a time so that large
+// files are never held in memory as a whole.
+struct CsvParser {
+    reader: csv_core::Reader,
+    output: Vec<u8>,
+    ends: Vec<usize>,
+    output_len: usize,
+    ends_len: usize,
+}
+
+impl CsvParser {
+    fn new() -> Self {
+        Self {
+            reader: csv_core::Reader::new(),
+            output: vec![0; 1024],
+            ends: vec![0; 16],
+            output_len: 0,
+            ends_len: 0,
+        }
+    }
+
+    // Parses `chunk`, appending the records it completes to `records`.
+    fn feed(&mut self, chunk: &[u8], records: &mut Vec<Vec<Vec<u8>>>) {
+        // An empty input tells the parser the file is over
+        if !chunk.is_empty() {
+            self.parse(chunk, records);
+        }
+    }
+
+    // Flushes the last record, for files that don't end with a newline.
+    fn finish(&mut self, records: &mut Vec<Vec<Vec<u8>>>) {
+        self.parse(&[], records);
+    }
+
+    fn parse(&mut self, mut input: &[u8], records: &mut Vec<Vec<Vec<u8>>>) {
+        loop {
+            let (result, n_in, n_out, n_ends) = self.reader.read_record(
+                input,
+                &mut self.output[self.output_len..],
+                &mut self.ends[self.ends_len..],
+            );
+            input = &input[n_in..];
+            self.output_len += n_out;
+            self.ends_len += n_ends;
+            match result {
+                ReadRecordResult::InputEmpty | ReadRecordResult::End => return,
+                ReadRecordResult::OutputFull => self.output.resize(self.output.len() * 2, 0),
+                ReadRecordResult::OutputEndsFull => self.ends.resize(self.ends.len() * 2, 0),
+                ReadRecordResult::Record => {
+                    let mut start = 0;
+                    let mut fields = Vec::with_capacity(self.ends_len);
+                    for &end in &self.ends[..self.ends_len] {
+                        fields.push(self.output[start..end].to_vec());
+                        start = end;
+                    }
+                    records.push(fields);
+                    self.output_len = 0;
+                    self.ends_len = 0;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{unescape_formula, CsvParser};
+
+    fn parse_in_chunks(csv: &str, chunk_size: usize) -> Vec<Vec<String>> {
+        let mut parser = CsvParser::new();
+        let mut records = Vec::new();
+        for chunk in csv.as_bytes().chunks(chunk_size) {
+            parser.feed(chunk, &mut records);
+        }
+        parser.finish(&mut records);
+        records
+            .into_iter()
+            .map(|r| {
+                r.into_iter()
+                    .map(|f| String::from_utf8(f).unwrap())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn records_split_across_chunks_are_reassembled() {
+        let csv = "email,name\nursula@gmail.com,\"Le Guin, Ursula\"\nle_guin@gmail.com,Ursula";
+        let expected = vec![
+            vec!["email", "name"],
+            vec!["ursula@gmail.com", "Le Guin, Ursula"],
+            vec!["le_guin@gmail.com", "Ursula"],
+        ];
+
+        for chunk_size in [1, 3, 7, 1024] {
+            assert_eq!(parse_in_chunks(csv, chunk_size), expected);
+        }
+    }
+
+    #[test]
+    fn long_fields_grow_the_buffers() {
+        let name = "a".repeat(5000);
+        let csv = format!("{},{}\n", name, name);
+
+        let records = parse_in_chunks(&csv, 100);
+
+        assert_eq!(records, vec![vec![name.clone(), name]]);
+    }
+
+    #[test]
+    fn only_escaped_formulas_lose_their_quote() {
+        assert_eq!(unescape_formula("'=SUM(A1)"), "=SUM(A1)");
+        assert_eq!(unescape_formula("'-Bob"), "-Bob");
+        assert_eq!(unescape_formula("'Bob'"), "'Bob'");
+        assert_eq!(unescape_formula("O'Brien"), "O'Brien");
+    }
+}
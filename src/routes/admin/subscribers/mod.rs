@@ -0,0 +1,14 @@
+mod delete;
+mod export;
+mod get;
+mod import;
+mod persistence;
+mod tags;
+mod update;
+
+pub use delete::delete_subscriber;
+pub use export::export_subscribers;
+pub use get::{get_subscriber, list_subscribers};
+pub use import::import_subscribers;
+pub use tags::{get_subscriber_tags, set_subscriber_tags};
+pub use update::{change_subscriber_status, update_subscriber_attributes, update_subscriber_name};
@@ -0,0 +1,259 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::ops::DerefMut;
+use uuid::Uuid;
+
+use crate::domain::{SubscriberName, SubscriberStatus, SubscriberTag};
+
+#[derive(serde::Serialize)]
+pub struct Subscriber {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+    pub confirmed_at: Option<DateTime<Utc>>,
+    // Custom fields available to issues as merge tags
+    pub attributes: serde_json::Value,
+}
+
+#[derive(Default)]
+pub struct SubscriberFilter {
+    pub status: Option<SubscriberStatus>,
+    // Case-insensitive substring of the email address
+    pub email: Option<String>,
+}
+
+impl SubscriberFilter {
+    fn status(&self) -> Option<&'static str> {
+        self.status.map(|s| s.as_str())
+    }
+
+    // `email` as an ILIKE pattern, matching its characters literally
+    fn email_pattern(&self) -> Option<String> {
+        self.email.as_ref().map(|email| {
+            let escaped = email
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            format!("%{}%", escaped)
+        })
+    }
+}
+
+#[tracing::instrument(name = "Get subscribers", skip(pool, filter))]
+pub async fn get_subscribers(
+    pool: &PgPool,
+    filter: &SubscriberFilter,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<Subscriber>, sqlx::Error> {
+    sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT id, email, name, status, subscribed_at, confirmed_at, attributes
+        FROM subscriptions
+        WHERE
+            ($1::text IS NULL OR status = $1) AND
+            ($2::text IS NULL OR email ILIKE $2)
+        ORDER BY subscribed_at DESC, id
+        LIMIT $3
+        OFFSET $4
+        "#,
+        filter.status(),
+        filter.email_pattern(),
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Count subscribers", skip(pool, filter))]
+pub async fn count_subscribers(
+    pool: &PgPool,
+    filter: &SubscriberFilter,
+) -> Result<i64, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM subscriptions
+        WHERE
+            ($1::text IS NULL OR status = $1) AND
+            ($2::text IS NULL OR email ILIKE $2)
+        "#,
+        filter.status(),
+        filter.email_pattern()
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row.count)
+}
+
+#[tracing::instrument(name = "Find subscriber", skip(pool))]
+pub async fn find_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<Subscriber>, sqlx::Error> {
+    sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT id, email, name, status, subscribed_at, confirmed_at, attributes
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Update subscriber name", skip(pool, name))]
+pub async fn update_name(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    name: &SubscriberName,
+) -> Result<Option<Subscriber>, sqlx::Error> {
+    sqlx::query_as!(
+        Subscriber,
+        r#"
+        UPDATE subscriptions SET name = $2
+        WHERE id = $1
+        RETURNING id, email, name, status, subscribed_at, confirmed_at, attributes
+        "#,
+        subscriber_id,
+        name.as_ref()
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Update subscriber attributes", skip(pool, attributes))]
+pub async fn update_attributes(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    attributes: &serde_json::Value,
+) -> Result<Option<Subscriber>, sqlx::Error> {
+    sqlx::query_as!(
+        Subscriber,
+        r#"
+        UPDATE subscriptions SET attributes = $2
+        WHERE id = $1
+        RETURNING id, email, name, status, subscribed_at, confirmed_at, attributes
+        "#,
+        subscriber_id,
+        attributes
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Update subscriber status", skip(pool))]
+pub async fn update_status(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    status: SubscriberStatus,
+) -> Result<Option<Subscriber>, sqlx::Error> {
+    // Confirming by hand counts as a confirmation, unless there was one
+    // already, and confirms the lists the subscriber is waiting on too
+    sqlx::query_as!(
+        Subscriber,
+        r#"
+        WITH memberships AS (
+            UPDATE list_memberships
+            SET status = 'confirmed', confirmed_at = now()
+            WHERE subscriber_id = $1 AND $2 = 'confirmed' AND status = 'pending_confirmation'
+        )
+        UPDATE subscriptions
+        SET
+            status = $2,
+            confirmed_at = CASE
+                WHEN $2 = 'confirmed' THEN COALESCE(confirmed_at, now())
+                ELSE confirmed_at
+            END
+        WHERE id = $1
+        RETURNING id, email, name, status, subscribed_at, confirmed_at, attributes
+        "#,
+        subscriber_id,
+        status.as_str()
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+// Returns `false` if there was no such subscriber.
+#[tracing::instrument(name = "Delete subscriber", skip(transaction))]
+pub async fn delete_subscriber_by_id(
+    transaction: &mut Transaction<'static, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    let result = sqlx::query!(r#"DELETE FROM subscriptions WHERE id = $1"#, subscriber_id)
+        .execute(transaction.deref_mut())
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// Returns `None` if there is no such subscriber.
+#[tracing::instrument(name = "Get subscriber tags", skip(pool))]
+pub async fn get_tags(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<Vec<String>>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT ARRAY(
+            SELECT tag FROM subscriber_tags WHERE subscriber_id = s.id ORDER BY tag
+        ) AS "tags!"
+        FROM subscriptions s
+        WHERE s.id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+// Returns `false` if there is no such subscriber.
+#[tracing::instrument(name = "Replace subscriber tags", skip(transaction, tags))]
+pub async fn replace_tags(
+    transaction: &mut Transaction<'static, Postgres>,
+    subscriber_id: Uuid,
+    tags: &[SubscriberTag],
+) -> Result<bool, sqlx::Error> {
+    let exists = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        subscriber_id
+    )
+    .fetch_optional(transaction.deref_mut())
+    .await?
+    .is_some();
+    if !exists {
+        return Ok(false);
+    }
+    let tags: Vec<String> = tags.iter().map(|tag| tag.as_ref().to_owned()).collect();
+    sqlx::query!(
+        r#"DELETE FROM subscriber_tags WHERE subscriber_id = $1 AND tag <> ALL($2)"#,
+        subscriber_id,
+        &tags
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_tags (subscriber_id, tag)
+        SELECT $1, tag FROM UNNEST($2::text[]) AS tag
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id,
+        &tags
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    Ok(true)
+}
@@ -0,0 +1,68 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::persistence::{get_tags, replace_tags};
+use crate::domain::SubscriberTag;
+use crate::utils::{e400, e500};
+
+#[derive(serde::Deserialize, serde::Serialize)]
+pub struct TagsData {
+    tags: Vec<String>,
+}
+
+#[tracing::instrument(name = "Get a subscriber's tags", skip(pool))]
+pub async fn get_subscriber_tags(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match get_tags(&pool, subscriber_id.into_inner())
+        .await
+        .map_err(e500)?
+    {
+        Some(tags) => Ok(HttpResponse::Ok().json(TagsData { tags })),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+// Replaces the whole set of tags, an empty list removes them all.
+#[tracing::instrument(name = "Set a subscriber's tags", skip(body, pool))]
+pub async fn set_subscriber_tags(
+    subscriber_id: web::Path<Uuid>,
+    body: web::Json<TagsData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut tags = body
+        .0
+        .tags
+        .into_iter()
+        .map(SubscriberTag::parse)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(e400)?;
+    tags.sort();
+    tags.dedup();
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let found = replace_tags(&mut transaction, subscriber_id.into_inner(), &tags)
+        .await
+        .map_err(e500)?;
+    if !found {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to set a subscriber's tags.")
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(TagsData {
+        tags: tags
+            .into_iter()
+            .map(|tag| tag.as_ref().to_owned())
+            .collect(),
+    }))
+}
@@ -0,0 +1,85 @@
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::persistence::{update_attributes, update_name, update_status};
+use crate::domain::{SubscriberName, SubscriberStatus};
+use crate::utils::{e400, e500};
+
+#[derive(serde::Deserialize)]
+pub struct NameData {
+    name: String,
+}
+
+#[tracing::instrument(name = "Update a subscriber's name", skip(body, pool))]
+pub async fn update_subscriber_name(
+    subscriber_id: web::Path<Uuid>,
+    body: web::Json<NameData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = SubscriberName::parse(body.0.name).map_err(e400)?;
+    match update_name(&pool, subscriber_id.into_inner(), &name)
+        .await
+        .map_err(e500)?
+    {
+        Some(subscriber) => Ok(HttpResponse::Ok().json(subscriber)),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+// Keeps attributes small, they are loaded for every delivery
+const MAX_ATTRIBUTES_SIZE: usize = 16 * 1024;
+
+// Replaces the subscriber's custom attributes with the JSON object in the body.
+#[tracing::instrument(name = "Update a subscriber's attributes", skip(body, pool))]
+pub async fn update_subscriber_attributes(
+    subscriber_id: web::Path<Uuid>,
+    body: web::Json<serde_json::Value>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let attributes = body.into_inner();
+    if !attributes.is_object() {
+        return Err(e400("Attributes must be a JSON object."));
+    }
+    if attributes.to_string().len() > MAX_ATTRIBUTES_SIZE {
+        return Err(e400(format!(
+            "Attributes can't take more than {} bytes.",
+            MAX_ATTRIBUTES_SIZE
+        )));
+    }
+    match update_attributes(&pool, subscriber_id.into_inner(), &attributes)
+        .await
+        .map_err(e500)?
+    {
+        Some(subscriber) => Ok(HttpResponse::Ok().json(subscriber)),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct StatusData {
+    status: String,
+}
+
+// Admins can confirm, unsubscribe or block a subscriber. Putting someone
+// back to `pending_confirmation` is left to the subscription flow.
+#[tracing::instrument(name = "Change a subscriber's status", skip(body, pool))]
+pub async fn change_subscriber_status(
+    subscriber_id: web::Path<Uuid>,
+    body: web::Json<StatusData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let status = SubscriberStatus::try_from(body.0.status).map_err(e400)?;
+    if status == SubscriberStatus::PendingConfirmation {
+        return Err(e400(
+            "A subscriber can't be put back to pending_confirmation.",
+        ));
+    }
+    match update_status(&pool, subscriber_id.into_inner(), status)
+        .await
+        .map_err(e500)?
+    {
+        Some(subscriber) => Ok(HttpResponse::Ok().json(subscriber)),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
@@ -0,0 +1,106 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::suppressions::{
+    add_suppression, count_suppressions, delete_suppression_by_id, get_suppressions, Suppression,
+    SuppressionSource, SuppressionTarget,
+};
+use crate::utils::{e400, e500};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 100;
+
+#[derive(serde::Deserialize)]
+pub struct SuppressionListQuery {
+    // Pages start at 1
+    page: Option<i64>,
+    page_size: Option<i64>,
+}
+
+#[derive(serde::Serialize)]
+struct SuppressionPage {
+    suppressions: Vec<Suppression>,
+    page: i64,
+    page_size: i64,
+    total: i64,
+}
+
+#[tracing::instrument(name = "List suppressions", skip(query, pool))]
+pub async fn list_suppressions(
+    query: web::Query<SuppressionListQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let page = query.page.unwrap_or(1);
+    let page_size = query.page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+    if page < 1 {
+        return Err(e400("The page number must be at least 1."));
+    }
+    if !(1..=MAX_PAGE_SIZE).contains(&page_size) {
+        return Err(e400(format!(
+            "The page size must be between 1 and {}.",
+            MAX_PAGE_SIZE
+        )));
+    }
+    let offset = page
+        .checked_sub(1)
+        .and_then(|p| p.checked_mul(page_size))
+        .ok_or_else(|| e400("The page number is too large."))?;
+    let suppressions = get_suppressions(&pool, page_size, offset)
+        .await
+        .map_err(e500)?;
+    let total = count_suppressions(&pool).await.map_err(e500)?;
+    Ok(HttpResponse::Ok().json(SuppressionPage {
+        suppressions,
+        page,
+        page_size,
+        total,
+    }))
+}
+
+#[derive(serde::Deserialize)]
+pub struct NewSuppressionData {
+    // An email address or a domain
+    target: String,
+    reason: String,
+}
+
+#[tracing::instrument(name = "Create a suppression", skip(body, pool))]
+pub async fn create_suppression(
+    body: web::Json<NewSuppressionData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let target = SuppressionTarget::parse(&body.target).map_err(e400)?;
+    let reason = body.reason.trim();
+    if reason.is_empty() || reason.chars().count() > 256 {
+        return Err(e400("A reason must be between 1 and 256 characters long."));
+    }
+    let mut connection = pool
+        .acquire()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    match add_suppression(&mut connection, &target, reason, SuppressionSource::Manual)
+        .await
+        .map_err(e500)?
+    {
+        Some(suppression) => Ok(HttpResponse::Created().json(suppression)),
+        None => Ok(HttpResponse::Conflict().body("This address or domain is already suppressed.")),
+    }
+}
+
+#[tracing::instrument(name = "Remove a suppression", skip(pool))]
+pub async fn remove_suppression(
+    suppression_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if delete_suppression_by_id(&pool, suppression_id.into_inner())
+        .await
+        .map_err(e500)?
+    {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Ok(HttpResponse::NotFound().finish())
+    }
+}
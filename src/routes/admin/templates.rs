@@ -0,0 +1,195 @@
+use actix_web::{http::header::ContentType, web, HttpResponse, HttpResponseBuilder};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::email_layout::{get_layout, reset_layout, save_layout, Layout, LayoutName};
+use crate::utils::{e500, see_other};
+
+pub async fn list_layouts(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut connection = pool
+        .acquire()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let mut layouts_html = String::new();
+    for name in LayoutName::ALL {
+        let layout = get_layout(&mut connection, name).await.map_err(e500)?;
+        let state = match layout {
+            Some(Layout {
+                updated_at: Some(updated_at),
+                ..
+            }) => format!("edited {}", updated_at.to_rfc3339()),
+            Some(_) => "default".to_owned(),
+            None => "not sent".to_owned(),
+        };
+        writeln!(
+            layouts_html,
+            r#"<li><a href="/admin/templates/{name}">{name}</a> ({state})</li>"#,
+            name = name.as_str(),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Email layouts</title>
+</head>
+<body>
+    {msg_html}
+    <h1>Email layouts</h1>
+    <ul>
+{layouts_html}    </ul>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#
+        )))
+}
+
+pub async fn edit_layout_form(
+    name: web::Path<String>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Ok(name) = LayoutName::try_from(name.into_inner()) else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let mut connection = pool
+        .acquire()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    // Layouts without a default start out empty
+    let layout = get_layout(&mut connection, name)
+        .await
+        .map_err(e500)?
+        .unwrap_or(Layout {
+            subject: String::new(),
+            html_content: String::new(),
+            text_content: String::new(),
+            updated_at: None,
+        });
+    Ok(layout_form(HttpResponse::Ok(), name, &layout, &msg_html))
+}
+
+#[derive(serde::Deserialize)]
+pub struct LayoutFormData {
+    subject: String,
+    html_content: String,
+    text_content: String,
+}
+
+// Invalid layouts are sent back in the form, so no edit is lost.
+#[tracing::instrument(name = "Save an email layout", skip(form, pool))]
+pub async fn save_layout_form(
+    name: web::Path<String>,
+    form: web::Form<LayoutFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Ok(name) = LayoutName::try_from(name.into_inner()) else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let LayoutFormData {
+        subject,
+        html_content,
+        text_content,
+    } = form.into_inner();
+    let layout = Layout {
+        subject,
+        html_content,
+        text_content,
+        updated_at: None,
+    };
+    if let Err(e) = layout.validate(name) {
+        let msg_html = format!("<p><i>{}</i></p>", encode_minimal(&e.to_string()));
+        return Ok(layout_form(
+            HttpResponse::BadRequest(),
+            name,
+            &layout,
+            &msg_html,
+        ));
+    }
+    save_layout(&pool, name, &layout).await.map_err(e500)?;
+    FlashMessage::info("The layout has been saved.").send();
+    Ok(see_other(&format!("/admin/templates/{}", name.as_str())))
+}
+
+#[tracing::instrument(name = "Reset an email layout", skip(pool))]
+pub async fn reset_layout_form(
+    name: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Ok(name) = LayoutName::try_from(name.into_inner()) else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    reset_layout(&pool, name).await.map_err(e500)?;
+    FlashMessage::info("The layout is back to its default.").send();
+    Ok(see_other(&format!("/admin/templates/{}", name.as_str())))
+}
+
+fn layout_form(
+    mut builder: HttpResponseBuilder,
+    name: LayoutName,
+    layout: &Layout,
+    msg_html: &str,
+) -> HttpResponse {
+    let variables = name
+        .variables()
+        .iter()
+        .map(|variable| format!("<code>{{{{ {} }}}}</code>", variable))
+        .collect::<Vec<_>>()
+        .join(", ");
+    builder.content_type(ContentType::html()).body(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Edit the {name} layout</title>
+</head>
+<body>
+    {msg_html}
+    <h1>The {name} layout</h1>
+    <p>Available variables: {variables}</p>
+    <form action="/admin/templates/{name}" method="post">
+        <label>Subject
+            <input type="text" name="subject" value="{subject}">
+        </label>
+        <label>HtmlContent
+            <textarea name="html_content">{html_content}</textarea>
+        </label>
+        <label>TextContent
+            <textarea name="text_content">{text_content}</textarea>
+        </label>
+        <button type="submit">Save layout</button>
+    </form>
+    <form action="/admin/templates/{name}/reset" method="post">
+        <button type="submit">Reset to default</button>
+    </form>
+    <p><a href="/admin/templates">&lt;- Back</a></p>
+</body>
+</html>"#,
+        name = name.as_str(),
+        subject = encode_attribute(&layout.subject),
+        html_content = encode_minimal(&layout.html_content),
+        text_content = encode_minimal(&layout.text_content),
+    ))
+}
@@ -0,0 +1,330 @@
+use actix_web::http::header::{
+    ETag, EntityTag, HttpDate, IfModifiedSince, IfNoneMatch, LastModified,
+};
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::fmt::Write;
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
+
+use crate::{
+    mailing_lists::{get_list_by_slug, MailingList},
+    routes::render_public_issue,
+    startup::ApplicationBaseUrl,
+    utils::e500,
+};
+
+// The latest issues, as most feed readers only look at the top of a feed
+const FEED_SIZE: i64 = 20;
+
+#[derive(Debug, Copy, Clone)]
+enum FeedFormat {
+    Rss,
+    Atom,
+}
+
+impl FeedFormat {
+    fn file_name(&self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "feed.xml",
+            FeedFormat::Atom => "atom.xml",
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "application/rss+xml; charset=utf-8",
+            FeedFormat::Atom => "application/atom+xml; charset=utf-8",
+        }
+    }
+}
+
+/// RSS 2.0 feed of every published issue.
+pub async fn rss_feed(
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    serve_feed(&request, &pool, &base_url, None, FeedFormat::Rss).await
+}
+
+/// Atom feed of every published issue.
+pub async fn atom_feed(
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    serve_feed(&request, &pool, &base_url, None, FeedFormat::Atom).await
+}
+
+/// RSS 2.0 feed of the issues published to a list.
+pub async fn list_rss_feed(
+    request: HttpRequest,
+    slug: web::Path<String>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    serve_feed(&request, &pool, &base_url, Some(&slug), FeedFormat::Rss).await
+}
+
+/// Atom feed of the issues published to a list.
+pub async fn list_atom_feed(
+    request: HttpRequest,
+    slug: web::Path<String>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    serve_feed(&request, &pool, &base_url, Some(&slug), FeedFormat::Atom).await
+}
+
+// Feed readers poll: they get a 304 without a body when nothing was published
+// since they last fetched the feed.
+#[tracing::instrument(name = "Serve a feed", skip(request, pool, base_url))]
+async fn serve_feed(
+    request: &HttpRequest,
+    pool: &PgPool,
+    base_url: &ApplicationBaseUrl,
+    list_slug: Option<&str>,
+    format: FeedFormat,
+) -> Result<HttpResponse, actix_web::Error> {
+    let list = match list_slug {
+        Some(slug) => {
+            let mut connection = pool
+                .acquire()
+                .await
+                .context("Failed to acquire a Postgres connection from the pool.")
+                .map_err(e500)?;
+            match get_list_by_slug(&mut connection, slug)
+                .await
+                .map_err(e500)?
+            {
+                Some(list) => Some(list),
+                None => return Ok(HttpResponse::NotFound().finish()),
+            }
+        }
+        None => None,
+    };
+    let issues = get_feed_issues(pool, list.as_ref().map(|list| list.list_id))
+        .await
+        .map_err(e500)?;
+    let mut entries = Vec::with_capacity(issues.len());
+    for issue in issues {
+        let rendered = render_public_issue(&issue.title, &issue.html_content, &issue.text_content)
+            .map_err(e500)?;
+        entries.push(FeedEntry {
+            newsletter_issue_id: issue.newsletter_issue_id,
+            title: rendered.subject,
+            url: format!("{}/issues/{}", base_url.0, issue.path),
+            html_content: rendered.html_content,
+            published_at: issue.published_at,
+        });
+    }
+    let feed = Feed {
+        title: list.as_ref().map_or("Newsletter", |list| &list.name),
+        self_url: feed_url(&base_url.0, list.as_ref(), format),
+        archive_url: format!("{}/issues", base_url.0),
+        updated_at: entries.first().map(|entry| entry.published_at),
+        entries,
+    };
+    let body = match format {
+        FeedFormat::Rss => feed.to_rss(),
+        FeedFormat::Atom => feed.to_atom(),
+    };
+
+    let etag = EntityTag::new_strong(format!("{:x}", Sha256::digest(body.as_bytes())));
+    let last_modified = feed.updated_at.map(http_date);
+    let is_fresh = is_fresh(request, &etag, last_modified);
+    let mut response = if is_fresh {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response.insert_header(ETag(etag));
+    if let Some(last_modified) = last_modified {
+        response.insert_header(LastModified(last_modified));
+    }
+    if is_fresh {
+        return Ok(response.finish());
+    }
+    Ok(response.content_type(format.content_type()).body(body))
+}
+
+fn feed_url(base_url: &str, list: Option<&MailingList>, format: FeedFormat) -> String {
+    match list {
+        Some(list) => format!("{}/lists/{}/{}", base_url, list.slug, format.file_name()),
+        None => format!("{}/{}", base_url, format.file_name()),
+    }
+}
+
+// HTTP dates have a precision of one second
+fn http_date(t: DateTime<Utc>) -> HttpDate {
+    let seconds = u64::try_from(t.timestamp()).unwrap_or_default();
+    HttpDate::from(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+// `If-None-Match` takes precedence over `If-Modified-Since` (RFC 9110).
+fn is_fresh(request: &HttpRequest, etag: &EntityTag, last_modified: Option<HttpDate>) -> bool {
+    if let Some(if_none_match) = request.get_header::<IfNoneMatch>() {
+        return match if_none_match {
+            IfNoneMatch::Any => true,
+            IfNoneMatch::Items(tags) => tags.iter().any(|tag| tag.weak_eq(etag)),
+        };
+    }
+    match (request.get_header::<IfModifiedSince>(), last_modified) {
+        (Some(IfModifiedSince(since)), Some(last_modified)) => last_modified <= since,
+        _ => false,
+    }
+}
+
+struct Feed<'a> {
+    title: &'a str,
+    self_url: String,
+    archive_url: String,
+    // When the latest issue was published
+    updated_at: Option<DateTime<Utc>>,
+    entries: Vec<FeedEntry>,
+}
+
+struct FeedEntry {
+    newsletter_issue_id: Uuid,
+    title: String,
+    url: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+}
+
+impl Feed<'_> {
+    fn to_rss(&self) -> String {
+        let mut items = String::new();
+        for entry in &self.entries {
+            write!(
+                items,
+                r#"
+    <item>
+      <title>{title}</title>
+      <link>{url}</link>
+      <guid isPermaLink="true">{url}</guid>
+      <pubDate>{published_at}</pubDate>
+      <description>{content}</description>
+    </item>"#,
+                title = encode_minimal(&entry.title),
+                url = encode_minimal(&entry.url),
+                published_at = entry.published_at.to_rfc2822(),
+                content = encode_minimal(&entry.html_content),
+            )
+            .unwrap();
+        }
+        let last_build_date = self
+            .updated_at
+            .map(|t| format!("\n    <lastBuildDate>{}</lastBuildDate>", t.to_rfc2822()))
+            .unwrap_or_default();
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
+  <channel>
+    <title>{title}</title>
+    <link>{archive_url}</link>
+    <description>Every issue of {title}</description>
+    <atom:link href="{self_url}" rel="self" type="application/rss+xml"/>{last_build_date}{items}
+  </channel>
+</rss>
+"#,
+            title = encode_minimal(self.title),
+            archive_url = encode_minimal(&self.archive_url),
+            self_url = encode_minimal(&self.self_url),
+        )
+    }
+
+    fn to_atom(&self) -> String {
+        let mut entries = String::new();
+        for entry in &self.entries {
+            write!(
+                entries,
+                r#"
+  <entry>
+    <title>{title}</title>
+    <id>urn:uuid:{id}</id>
+    <link rel="alternate" type="text/html" href="{url}"/>
+    <published>{published_at}</published>
+    <updated>{published_at}</updated>
+    <content type="html">{content}</content>
+  </entry>"#,
+                title = encode_minimal(&entry.title),
+                id = entry.newsletter_issue_id,
+                url = encode_minimal(&entry.url),
+                published_at = entry.published_at.to_rfc3339(),
+                content = encode_minimal(&entry.html_content),
+            )
+            .unwrap();
+        }
+        // An empty feed was last updated at the dawn of time
+        let updated_at = self.updated_at.unwrap_or_default().to_rfc3339();
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>{title}</title>
+  <id>{self_url}</id>
+  <link rel="self" type="application/atom+xml" href="{self_url}"/>
+  <link rel="alternate" type="text/html" href="{archive_url}"/>
+  <updated>{updated_at}</updated>
+  <author><name>{title}</name></author>{entries}
+</feed>
+"#,
+            title = encode_minimal(self.title),
+            archive_url = encode_minimal(&self.archive_url),
+            self_url = encode_minimal(&self.self_url),
+        )
+    }
+}
+
+struct FeedIssue {
+    newsletter_issue_id: Uuid,
+    path: String,
+    title: String,
+    text_content: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+}
+
+// Like the archive, feeds leave out drafts, issues scheduled for later and
+// issues kept out of the archive, e.g. because they were sent to a segment
+#[tracing::instrument(skip(pool))]
+async fn get_feed_issues(
+    pool: &PgPool,
+    list_id: Option<Uuid>,
+) -> Result<Vec<FeedIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        FeedIssue,
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            COALESCE(i.slug, i.newsletter_issue_id::text) AS "path!",
+            i.title,
+            i.text_content,
+            i.html_content,
+            i.published_at AS "published_at!"
+        FROM newsletter_issues i
+        WHERE i.status IN ('sending', 'sent')
+            AND i.in_archive
+            AND (
+                $1::uuid IS NULL
+                OR EXISTS (
+                    SELECT 1 FROM newsletter_issue_lists l
+                    WHERE l.newsletter_issue_id = i.newsletter_issue_id AND l.list_id = $1
+                )
+            )
+        ORDER BY i.published_at DESC, i.newsletter_issue_id
+        LIMIT $2
+        "#,
+        list_id,
+        FEED_SIZE
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the issues of a feed.")?;
+    Ok(issues)
+}
@@ -0,0 +1,196 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::{
+    email_template::{RenderedEmail, TemplateError},
+    issue_template::{IssueTemplate, MergeTags},
+    utils::{e400, e500, html_page},
+};
+
+const PAGE_SIZE: i64 = 20;
+
+#[derive(serde::Deserialize)]
+pub struct ArchiveQuery {
+    // Pages start at 1, with the latest issues
+    page: Option<i64>,
+}
+
+/// The public archive: every published issue, latest first.
+/// Drafts, issues scheduled for later and issues kept out of the archive,
+/// e.g. because they were sent to a segment, are left out.
+#[tracing::instrument(name = "List published issues", skip(query, pool))]
+pub async fn list_issues(
+    query: web::Query<ArchiveQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let page = query.page.unwrap_or(1);
+    if page < 1 {
+        return Err(e400("The page number must be at least 1."));
+    }
+    let offset = page
+        .checked_sub(1)
+        .and_then(|p| p.checked_mul(PAGE_SIZE))
+        .ok_or_else(|| e400("The page number is too large."))?;
+    // One more than shown, to know whether there is an older page
+    let mut issues = get_public_issues(&pool, PAGE_SIZE + 1, offset)
+        .await
+        .map_err(e500)?;
+    let has_older = issues.len() as i64 > PAGE_SIZE;
+    issues.truncate(PAGE_SIZE as usize);
+
+    let mut issues_html = String::new();
+    for issue in &issues {
+        writeln!(
+            issues_html,
+            r#"<li><a href="/issues/{}">{}</a> ({})</li>"#,
+            encode_minimal(&issue.path),
+            encode_minimal(&issue.title),
+            issue.published_at.format("%B %-d, %Y")
+        )
+        .unwrap();
+    }
+    if issues.is_empty() {
+        issues_html.push_str("<li>Nothing has been published yet.</li>");
+    }
+    let mut navigation_html = String::new();
+    if page > 1 {
+        write!(
+            navigation_html,
+            r#"<a href="/issues?page={}">Newer issues</a> "#,
+            page - 1
+        )
+        .unwrap();
+    }
+    if has_older {
+        write!(
+            navigation_html,
+            r#"<a href="/issues?page={}">Older issues</a>"#,
+            page + 1
+        )
+        .unwrap();
+    }
+
+    Ok(html_page(
+        HttpResponse::Ok(),
+        "Issues",
+        &format!(
+            r#"<h1>Issues</h1>
+    <ul>
+{issues_html}
+    </ul>
+    <p>{navigation_html}</p>"#
+        ),
+    ))
+}
+
+/// A published issue, found by its id or its slug. Merge tags are rendered
+/// with placeholder values and the HTML is sanitized before being served.
+#[tracing::instrument(name = "Show a published issue", skip(pool))]
+pub async fn show_issue(
+    id_or_slug: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(issue) = get_public_issue(&pool, &id_or_slug).await.map_err(e500)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let rendered = render_public_issue(&issue.title, &issue.html_content, &issue.text_content)
+        .map_err(e500)?;
+    let title = encode_minimal(&rendered.subject);
+    Ok(html_page(
+        HttpResponse::Ok(),
+        &title,
+        &format!(
+            r#"<h1>{title}</h1>
+    <p>Published on {published_at}</p>
+    <article>
+{content}
+    </article>
+    <p><a href="/issues">&lt;- All issues</a></p>"#,
+            published_at = issue.published_at.format("%B %-d, %Y"),
+            content = rendered.html_content,
+        ),
+    ))
+}
+
+/// Renders an issue as anyone may read it: merge tags get placeholder values
+/// and the HTML is sanitized.
+pub fn render_public_issue(
+    title: &str,
+    html_content: &str,
+    text_content: &str,
+) -> Result<RenderedEmail, TemplateError> {
+    let mut rendered =
+        IssueTemplate::compile(title, html_content, text_content)?.render(&MergeTags::public())?;
+    rendered.html_content = ammonia::clean(&rendered.html_content);
+    Ok(rendered)
+}
+
+struct PublicIssueSummary {
+    path: String,
+    title: String,
+    published_at: DateTime<Utc>,
+}
+
+struct PublicIssue {
+    title: String,
+    text_content: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_public_issues(
+    pool: &PgPool,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<PublicIssueSummary>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        PublicIssueSummary,
+        r#"
+        SELECT
+            COALESCE(slug, newsletter_issue_id::text) AS "path!",
+            title,
+            published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE status IN ('sending', 'sent') AND in_archive
+        ORDER BY published_at DESC, newsletter_issue_id
+        LIMIT $1 OFFSET $2
+        "#,
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve published issues.")?;
+    Ok(issues)
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_public_issue(
+    pool: &PgPool,
+    id_or_slug: &str,
+) -> Result<Option<PublicIssue>, anyhow::Error> {
+    let newsletter_issue_id = Uuid::parse_str(id_or_slug).ok();
+    let issue = sqlx::query_as!(
+        PublicIssue,
+        r#"
+        SELECT title, text_content, html_content, published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE
+            (newsletter_issue_id = $1 OR slug = $2)
+            AND status IN ('sending', 'sent')
+            AND in_archive
+        "#,
+        newsletter_issue_id,
+        id_or_slug
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the published issue.")?;
+    Ok(issue)
+}
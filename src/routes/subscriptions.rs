@@ -1,8 +1,14 @@
 use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
-use crate::{email_client::EmailClient, startup::ApplicationBaseUrl};
+use crate::email_layout::{get_layout, LayoutName};
+use crate::mailing_lists::{
+    add_pending_membership, get_list_by_slug, get_membership_status, DEFAULT_LIST_SLUG,
+};
+use crate::suppressions::is_suppressed;
+use crate::{email_outbox::enqueue_email, startup::ApplicationBaseUrl};
 use actix_web::{http::StatusCode, web, HttpResponse, ResponseError};
 use anyhow::Context;
 use chrono::Utc;
+use minijinja::{context, Value};
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use serde::Deserialize;
@@ -15,6 +21,9 @@ use uuid::Uuid;
 pub struct FormData {
     name: String,
     email: String,
+    // The slug of the list to join, the default list if left out
+    #[serde(default)]
+    list: Option<String>,
 }
 
 impl TryFrom<FormData> for NewSubscriber {
@@ -27,7 +36,7 @@ impl TryFrom<FormData> for NewSubscriber {
     }
 }
 
-fn generate_subscription_token() -> String {
+pub fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
@@ -77,50 +86,160 @@ impl ResponseError for SubscribeError {
     }
 }
 
-#[tracing::instrument(name = "Add a new subscriber", skip(form, db_pool, email_server, base_url), fields(
+#[tracing::instrument(name = "Add a new subscriber", skip(form, db_pool, base_url), fields(
     subscriber_email = %form.0.email,
     subscriber_name = %form.0.name,
 ))]
 pub async fn subscribe(
     form: web::Form<FormData>,
     db_pool: web::Data<PgPool>,
-    email_server: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
 ) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = form.0.try_into()?;
+    let mut form = form.into_inner();
+    let list_slug = form
+        .list
+        .take()
+        .filter(|slug| !slug.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_LIST_SLUG.to_owned());
+    let new_subscriber: NewSubscriber = form.try_into()?;
 
     let mut transaction = db_pool
         .begin()
         .await
         .context("Failed to acquire a Postgres connection from the pool.")?;
 
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
+    let list = get_list_by_slug(&mut transaction, list_slug.trim())
         .await
-        .context("Failed to insert new subscriber in the database.")?;
+        .context("Failed to look up the mailing list in the database.")?
+        .ok_or_else(|| {
+            SubscribeError::ValidationError(format!(
+                "There is no mailing list called {}",
+                list_slug.trim()
+            ))
+        })?;
 
-    let subscription_token = generate_subscription_token();
-
-    store_token(&mut transaction, &subscriber_id, &subscription_token)
+    // Inserted first, so that concurrent submissions for a new address wait for
+    // each other on the unique email rather than both trying to insert it
+    let subscriber = match insert_subscriber(&mut transaction, &new_subscriber)
         .await
-        .context("Failed to store the confirmation token for a new subscriber.")?;
+        .context("Failed to insert new subscriber in the database.")?
+    {
+        Some(id) => ExistingSubscriber {
+            id,
+            status: "pending_confirmation".into(),
+        },
+        None => get_subscriber_by_email(&mut transaction, &new_subscriber.email)
+            .await
+            .context("Failed to look up the subscriber in the database.")?
+            .context("The subscriber was not found after a conflicting insert.")?,
+    };
+    let subscriber_id = match subscriber.status.as_str() {
+        "pending_confirmation" => subscriber.id,
+        // Confirmed subscribers confirm each new list they join on its own
+        "confirmed" => {
+            let membership = get_membership_status(&mut transaction, list.list_id, subscriber.id)
+                .await
+                .context("Failed to look up the list membership in the database.")?;
+            if membership.as_deref() == Some("confirmed") {
+                return Ok(HttpResponse::Ok().finish());
+            }
+            subscriber.id
+        }
+        // Coming back after unsubscribing: they start over, on the list they
+        // ask for only
+        "unsubscribed" => {
+            resubscribe(&mut transaction, subscriber.id)
+                .await
+                .context("Failed to resubscribe a subscriber.")?;
+            subscriber.id
+        }
+        // Blocked or undeliverable. Answer exactly as for a new subscriber,
+        // so the form can't be used to find out who is subscribed
+        _ => return Ok(HttpResponse::Ok().finish()),
+    };
 
-    transaction
-        .commit()
+    add_pending_membership(&mut transaction, list.list_id, subscriber_id)
         .await
-        .context("Failed to commit SQL transaction to store a new subscriber.")?;
+        .context("Failed to add the subscriber to the mailing list.")?;
 
+    let subscription_token = generate_subscription_token();
+
+    store_token(
+        &mut transaction,
+        &subscriber_id,
+        &list.list_id,
+        &subscription_token,
+    )
+    .await
+    .context("Failed to store the confirmation token for a new subscriber.")?;
+
+    // Sent by the background workers once the subscriber is committed, so an
+    // email provider outage doesn't fail the signup
     send_confirmation_email(
-        &email_server,
+        &mut transaction,
         new_subscriber,
+        &list.name,
         &base_url.0,
         &subscription_token,
     )
     .await
-    .context("Failed to ")?;
+    .context("Failed to queue a confirmation email.")?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a new subscriber.")?;
 
     return Ok(HttpResponse::Ok().finish());
 }
 
+pub struct ExistingSubscriber {
+    pub id: Uuid,
+    pub status: String,
+}
+
+#[tracing::instrument(name = "Get subscriber by email", skip(transaction, email))]
+pub async fn get_subscriber_by_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+) -> Result<Option<ExistingSubscriber>, sqlx::Error> {
+    // Locked so that concurrent submissions for the same address line up
+    sqlx::query_as!(
+        ExistingSubscriber,
+        r#"SELECT id, status FROM subscriptions WHERE email = $1 FOR UPDATE"#,
+        email.as_ref()
+    )
+    .fetch_optional(transaction.deref_mut())
+    .await
+}
+
+#[tracing::instrument(name = "Resubscribe a subscriber", skip(transaction))]
+async fn resubscribe(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = 'pending_confirmation', confirmed_at = NULL
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    // Unsubscribing left every list
+    sqlx::query!(
+        r#"
+        UPDATE list_memberships SET status = 'unsubscribed', confirmed_at = NULL
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    Ok(())
+}
+
 pub struct StoreTokenError(sqlx::Error);
 
 impl std::fmt::Display for StoreTokenError {
@@ -146,17 +265,23 @@ impl std::error::Error for StoreTokenError {
 
 #[tracing::instrument(
     name = "Store subscription token in the database",
-    skip(transaction, subscriber_id, subscription_token)
+    skip(transaction, subscriber_id, list_id, subscription_token)
 )]
 pub async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: &Uuid,
+    list_id: &Uuid,
     subscription_token: &str,
 ) -> Result<(), StoreTokenError> {
+    // A token confirms the subscriber on the list they asked to join
     sqlx::query!(
-        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id) VALUES ($1, $2)"#,
+        r#"
+        INSERT INTO subscription_tokens (subscription_token, subscriber_id, list_id)
+        VALUES ($1, $2, $3)
+        "#,
         subscription_token,
-        subscriber_id
+        subscriber_id,
+        list_id
     )
     .execute(transaction.deref_mut())
     .await
@@ -166,53 +291,71 @@ pub async fn store_token(
 
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
-    skip(email_client, new_subscriber)
+    skip(transaction, new_subscriber)
 )]
 pub async fn send_confirmation_email(
-    email_client: &EmailClient,
+    transaction: &mut Transaction<'static, Postgres>,
     new_subscriber: NewSubscriber,
+    list_name: &str,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), anyhow::Error> {
+    // Answer as usual, so the form can't be used to find out who is suppressed
+    if is_suppressed(transaction, new_subscriber.email.as_ref()).await? {
+        tracing::info!(
+            subscriber_email = %new_subscriber.email,
+            "Not sending a confirmation email to a suppressed address."
+        );
+        return Ok(());
+    }
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url, subscription_token
     );
-    let plain_body = format!(
-        "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
-        confirmation_link
-    );
-    let html_body = format!(
-        "Welcome to our newsletter!<br />\
-        Click <a href=\"{}\">here</a> to confirm your subscription.",
-        confirmation_link
-    );
-    email_client
-        .send_email(&new_subscriber.email, "Welcome!", &html_body, &plain_body)
-        .await
+    let layout = get_layout(transaction, LayoutName::Confirmation)
+        .await?
+        .context("There is no confirmation email layout.")?;
+    let email = layout.render(
+        LayoutName::Confirmation,
+        &context! {
+            name => new_subscriber.name.as_ref(),
+            list_name => list_name,
+            confirmation_link => Value::from_safe_string(confirmation_link),
+        },
+    )?;
+    enqueue_email(
+        transaction,
+        &new_subscriber.email,
+        &email.subject,
+        &email.html_content,
+        &email.text_content,
+    )
+    .await?;
+    Ok(())
 }
 
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
     skip(transaction, new_subscriber)
 )]
+// Returns `None` if the address is already stored.
 pub async fn insert_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
     new_subscriber: &NewSubscriber,
-) -> Result<Uuid, sqlx::Error> {
-    let subscriber_id = Uuid::new_v4();
-    sqlx::query!(
+) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!(
         r#"
           INSERT INTO subscriptions (id, email, name, subscribed_at, status)
           VALUES ($1, $2, $3, $4, 'pending_confirmation')
+          ON CONFLICT (email) DO NOTHING
+          RETURNING id
         "#,
-        subscriber_id,
+        Uuid::new_v4(),
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
         Utc::now()
     )
     // https://github.com/launchbadge/sqlx/issues/2672#issuecomment-1660867114
-    .execute(transaction.deref_mut())
-    .await?;
-    Ok(subscriber_id)
+    .fetch_optional(transaction.deref_mut())
+    .await
 }
@@ -1,5 +1,15 @@
+use crate::domain::SubscriberEmail;
+use crate::email_layout::{get_layout, LayoutName};
+use crate::email_outbox::enqueue_email;
+use crate::mailing_lists::confirm_membership;
+use crate::startup::SubscriptionTokenTtl;
+use crate::utils::{e500, html_page};
 use actix_web::{web, HttpResponse};
-use sqlx::PgPool;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use minijinja::context;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::ops::DerefMut;
 use uuid::Uuid;
 
 #[derive(serde::Deserialize)]
@@ -7,59 +17,190 @@ pub struct Parameters {
     subscription_token: String,
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, db_pool))]
+// Following the link twice, or following an older link once a newer one was
+// used, lands on the "already confirmed" page rather than on an error.
+// Each link confirms the subscriber on the list they asked to join.
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(parameters, db_pool, token_ttl)
+)]
 pub async fn confirm(
     parameters: web::Query<Parameters>,
     db_pool: web::Data<PgPool>,
-) -> HttpResponse {
-    let subscription_token = parameters.0.subscription_token;
-    let id = match get_subscriber_id_from_token(&subscription_token, &db_pool).await {
-        Ok(id) => id,
-        Err(_) => return HttpResponse::InternalServerError().finish(),
+    token_ttl: web::Data<SubscriptionTokenTtl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let Some(token) = get_token(&mut transaction, &parameters.subscription_token)
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(invalid_token_page());
     };
-    match id {
-        None => return HttpResponse::Unauthorized().finish(),
-        Some(subscriber_id) => {
-            if confirm_subscriber(&subscriber_id, &db_pool).await.is_err() {
-                return HttpResponse::InternalServerError().finish();
-            }
-            HttpResponse::Ok().finish()
-        }
+    if token.subscriber_status == "confirmed"
+        && token.membership_status.as_deref() == Some("confirmed")
+    {
+        return Ok(html_page(
+            HttpResponse::Ok(),
+            "Subscription confirmed",
+            "<p>Your subscription is already confirmed, there is nothing else to do.</p>",
+        ));
     }
+    if token.consumed_at.is_some()
+        || token.created_at + token_ttl.0 <= Utc::now()
+        || !matches!(
+            token.subscriber_status.as_str(),
+            "pending_confirmation" | "confirmed"
+        )
+    {
+        return Ok(invalid_token_page());
+    }
+
+    confirm_subscriber(
+        &mut transaction,
+        &parameters.subscription_token,
+        &token.subscriber_id,
+        &token.list_id,
+    )
+    .await
+    .map_err(e500)?;
+    send_welcome_email(&mut transaction, &token)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to confirm a subscriber.")
+        .map_err(e500)?;
+
+    Ok(html_page(
+        HttpResponse::Ok(),
+        "Subscription confirmed",
+        "<p>Thanks for confirming your subscription! You will receive our next issue.</p>",
+    ))
 }
 
-#[tracing::instrument(name = "Mark subscriber as confirmed", skip(subscriber_id, db_pool))]
-pub async fn confirm_subscriber(subscriber_id: &Uuid, db_pool: &PgPool) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
-        subscriber_id
+struct StoredToken {
+    subscriber_id: Uuid,
+    list_id: Uuid,
+    created_at: DateTime<Utc>,
+    consumed_at: Option<DateTime<Utc>>,
+    subscriber_status: String,
+    membership_status: Option<String>,
+    email: String,
+    name: String,
+    list_name: String,
+}
+
+#[tracing::instrument(name = "Get subscription token", skip(transaction, subscription_token))]
+async fn get_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscription_token: &str,
+) -> Result<Option<StoredToken>, anyhow::Error> {
+    // Locks the subscriber, so concurrent clicks on the same link confirm it once
+    let token = sqlx::query_as!(
+        StoredToken,
+        r#"
+        SELECT
+            t.subscriber_id,
+            t.list_id,
+            t.created_at,
+            t.consumed_at,
+            s.status AS subscriber_status,
+            m.status AS "membership_status?",
+            s.email,
+            s.name,
+            l.name AS list_name
+        FROM subscription_tokens t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        JOIN lists l ON l.list_id = t.list_id
+        LEFT JOIN list_memberships m
+            ON m.subscriber_id = t.subscriber_id AND m.list_id = t.list_id
+        WHERE t.subscription_token = $1
+        FOR UPDATE OF t, s
+        "#,
+        subscription_token
     )
-    .execute(db_pool)
+    .fetch_optional(transaction.deref_mut())
     .await
-    .map_err(|e| {
-        tracing::error!("Failed to execute query: {}", e);
-        e
-    })?;
-    Ok(())
+    .context("Failed to retrieve the subscription token.")?;
+    Ok(token)
 }
 
 #[tracing::instrument(
-    name = "Get subscriber_id from token",
-    skip(subscription_token, db_pool)
+    name = "Mark subscriber as confirmed",
+    skip(transaction, subscription_token)
 )]
-pub async fn get_subscriber_id_from_token(
+async fn confirm_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
     subscription_token: &str,
-    db_pool: &PgPool,
-) -> Result<Option<Uuid>, sqlx::Error> {
-    let result = sqlx::query!(
-        r#"SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1"#,
+    subscriber_id: &Uuid,
+    list_id: &Uuid,
+) -> Result<(), anyhow::Error> {
+    // Subscribers already confirmed on another list keep their confirmation time
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = 'confirmed', confirmed_at = now()
+        WHERE id = $1 AND status = 'pending_confirmation'
+        "#,
+        subscriber_id
+    )
+    .execute(transaction.deref_mut())
+    .await
+    .context("Failed to mark the subscriber as confirmed.")?;
+    confirm_membership(transaction, *list_id, *subscriber_id)
+        .await
+        .context("Failed to confirm the list membership.")?;
+    sqlx::query!(
+        r#"
+        UPDATE subscription_tokens SET consumed_at = now()
+        WHERE subscription_token = $1
+        "#,
         subscription_token
     )
-    .fetch_optional(db_pool)
+    .execute(transaction.deref_mut())
+    .await
+    .context("Failed to consume the subscription token.")?;
+    Ok(())
+}
+
+// Only sent once an admin has written a welcome layout.
+#[tracing::instrument(name = "Send a welcome email", skip_all)]
+async fn send_welcome_email(
+    transaction: &mut Transaction<'static, Postgres>,
+    token: &StoredToken,
+) -> Result<(), anyhow::Error> {
+    let Some(layout) = get_layout(transaction, LayoutName::Welcome).await? else {
+        return Ok(());
+    };
+    let recipient = SubscriberEmail::parse(token.email.clone()).map_err(anyhow::Error::msg)?;
+    let email = layout.render(
+        LayoutName::Welcome,
+        &context! {
+            name => &token.name,
+            list_name => &token.list_name,
+        },
+    )?;
+    enqueue_email(
+        transaction,
+        &recipient,
+        &email.subject,
+        &email.html_content,
+        &email.text_content,
+    )
     .await
-    .map_err(|e| {
-        tracing::error!("Failed to execute query: {}", e);
-        e
-    })?;
-    Ok(result.map(|r| r.subscriber_id))
+    .context("Failed to queue the welcome email.")?;
+    Ok(())
+}
+
+fn invalid_token_page() -> HttpResponse {
+    html_page(
+        HttpResponse::Unauthorized(),
+        "Confirm your subscription",
+        "<p>This confirmation link is invalid or has expired. \
+        Subscribe again to receive a new one.</p>",
+    )
 }
@@ -0,0 +1,91 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use htmlescape::encode_attribute;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    domain::UnsubscribeToken,
+    startup::HmacSecret,
+    utils::{e500, html_page},
+};
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    token: String,
+}
+
+// Following RFC 8058, a GET never unsubscribes on its own: link scanners
+// and previews follow links in emails. It asks the reader to confirm instead.
+#[tracing::instrument(name = "Show the unsubscribe page", skip(parameters, hmac_secret))]
+pub async fn unsubscribe_form(
+    parameters: web::Query<UnsubscribeParameters>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+    if UnsubscribeToken::verify(&parameters.token, &hmac_secret.0).is_err() {
+        return invalid_token_page();
+    }
+    html_page(
+        HttpResponse::Ok(),
+        "Unsubscribe",
+        &format!(
+            r#"<p>Do you want to stop receiving this newsletter?</p>
+    <form action="/subscriptions/unsubscribe?token={}" method="post">
+        <button type="submit">Unsubscribe</button>
+    </form>"#,
+            encode_attribute(&parameters.token)
+        ),
+    )
+}
+
+// Handles both the confirmation form and one-click unsubscribe requests
+// sent by mailbox providers (`List-Unsubscribe=One-Click` in the body).
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber",
+    skip(parameters, db_pool, hmac_secret)
+)]
+pub async fn unsubscribe(
+    parameters: web::Query<UnsubscribeParameters>,
+    db_pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Ok(subscriber_id) = UnsubscribeToken::verify(&parameters.token, &hmac_secret.0) else {
+        return Ok(invalid_token_page());
+    };
+    mark_subscriber_as_unsubscribed(&subscriber_id, &db_pool)
+        .await
+        .map_err(e500)?;
+    Ok(html_page(
+        HttpResponse::Ok(),
+        "Unsubscribe",
+        "<p>You have been unsubscribed. You will not receive any more issues.</p>",
+    ))
+}
+
+#[tracing::instrument(name = "Mark subscriber as unsubscribed", skip(db_pool))]
+pub async fn mark_subscriber_as_unsubscribed(
+    subscriber_id: &Uuid,
+    db_pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    // Blocked, bounced and complained addresses stay as they are: marking
+    // them unsubscribed would let them sign up again
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = 'unsubscribed'
+        WHERE id = $1 AND status IN ('pending_confirmation', 'confirmed')
+        "#,
+        subscriber_id
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to mark the subscriber as unsubscribed.")?;
+    Ok(())
+}
+
+fn invalid_token_page() -> HttpResponse {
+    html_page(
+        HttpResponse::BadRequest(),
+        "Unsubscribe",
+        "<p>This unsubscribe link is invalid.</p>",
+    )
+}
@@ -0,0 +1,65 @@
+use actix_web::http::header::{self, CacheControl, CacheDirective};
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+use crate::{
+    domain::{TrackedEvent, TrackingToken},
+    startup::HmacSecret,
+    tracking::record_tracking_event,
+};
+
+// The smallest transparent GIF: 1x1 pixel
+const PIXEL: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+/// Records a click and sends the reader on to the link.
+/// Only links signed into the token are followed, so this can't be used as an
+/// open redirect.
+#[tracing::instrument(name = "Follow a tracked link", skip_all)]
+pub async fn track_click(
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+    let Ok(event) = TrackingToken::verify(&token, &hmac_secret.0) else {
+        return HttpResponse::NotFound().finish();
+    };
+    let TrackedEvent::Click { url, .. } = &event else {
+        return HttpResponse::NotFound().finish();
+    };
+    if !(url.starts_with("https://") || url.starts_with("http://")) {
+        return HttpResponse::NotFound().finish();
+    }
+    // Losing a click is better than losing the reader
+    if let Err(e) = record_tracking_event(&pool, &event).await {
+        tracing::error!(error.cause_chain = ?e, "Failed to record a click.");
+    }
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, url.as_str()))
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .finish()
+}
+
+/// Records an open and serves the tracking pixel.
+#[tracing::instrument(name = "Serve the tracking pixel", skip_all)]
+pub async fn track_open(
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+    match TrackingToken::verify(&token, &hmac_secret.0) {
+        Ok(event @ TrackedEvent::Open { .. }) => {
+            if let Err(e) = record_tracking_event(&pool, &event).await {
+                tracing::error!(error.cause_chain = ?e, "Failed to record an open.");
+            }
+        }
+        _ => return HttpResponse::NotFound().finish(),
+    }
+    HttpResponse::Ok()
+        .content_type("image/gif")
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(PIXEL)
+}
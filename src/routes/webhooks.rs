@@ -0,0 +1,202 @@
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+use crate::authentication::{basic_authentication, Credentials};
+use crate::delivery_events::{mark_undeliverable, record_event, DeliveryEvent, DeliveryEventKind};
+use crate::routes::error_fmt_chain;
+use crate::suppressions::{add_suppression, SuppressionTarget};
+
+/// The credentials the email provider must send to call our webhook.
+#[derive(Clone)]
+pub struct WebhookCredentials {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+impl WebhookCredentials {
+    fn accepts(&self, credentials: &Credentials) -> bool {
+        // Hashed first so that comparing doesn't take longer the closer a
+        // guess is to the password
+        let digest = |s: &str| Sha256::digest(s.as_bytes());
+        credentials.username == self.username
+            && digest(credentials.password.expose_secret()) == digest(self.password.expose_secret())
+    }
+}
+
+// The notifications we act on, as sent by Postmark: any other record type is
+// acknowledged and ignored.
+#[derive(serde::Deserialize, Debug)]
+#[serde(tag = "RecordType")]
+pub enum PostmarkEvent {
+    Delivery(PostmarkDelivery),
+    Bounce(PostmarkBounce),
+    SpamComplaint(PostmarkBounce),
+    #[serde(other)]
+    Other,
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct PostmarkDelivery {
+    #[serde(rename = "MessageID")]
+    message_id: String,
+    recipient: String,
+    delivered_at: DateTime<Utc>,
+    #[serde(default)]
+    details: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct PostmarkBounce {
+    #[serde(rename = "MessageID")]
+    message_id: String,
+    email: String,
+    // e.g. `HardBounce` or `SoftBounce`
+    r#type: String,
+    bounced_at: DateTime<Utc>,
+    #[serde(default)]
+    description: Option<String>,
+}
+
+// Bounce types that mean the address will never accept our emails
+const HARD_BOUNCE_TYPES: &[&str] = &["HardBounce", "BadEmailAddress", "ManuallyDeactivated"];
+
+impl PostmarkEvent {
+    fn into_delivery_event(self) -> Option<DeliveryEvent> {
+        match self {
+            PostmarkEvent::Delivery(d) => Some(DeliveryEvent {
+                kind: DeliveryEventKind::Delivered,
+                provider_message_id: d.message_id,
+                recipient: d.recipient,
+                details: d.details,
+                occurred_at: d.delivered_at,
+            }),
+            PostmarkEvent::Bounce(b) => Some(DeliveryEvent {
+                kind: if HARD_BOUNCE_TYPES.contains(&b.r#type.as_str()) {
+                    DeliveryEventKind::HardBounced
+                } else {
+                    DeliveryEventKind::SoftBounced
+                },
+                details: Some(match b.description {
+                    Some(description) => format!("{}: {}", b.r#type, description),
+                    None => b.r#type,
+                }),
+                provider_message_id: b.message_id,
+                recipient: b.email,
+                occurred_at: b.bounced_at,
+            }),
+            PostmarkEvent::SpamComplaint(b) => Some(DeliveryEvent {
+                kind: DeliveryEventKind::Complained,
+                provider_message_id: b.message_id,
+                recipient: b.email,
+                details: b.description,
+                occurred_at: b.bounced_at,
+            }),
+            PostmarkEvent::Other => None,
+        }
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum WebhookError {
+    #[error("Authentication failed.")]
+    AuthError(#[source] anyhow::Error),
+    #[error("The payload is not a valid webhook event.")]
+    InvalidPayload(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_fmt_chain(self, f)
+    }
+}
+
+impl ResponseError for WebhookError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            Self::AuthError(_) => {
+                let mut response = HttpResponse::new(StatusCode::UNAUTHORIZED);
+                let header_value = HeaderValue::from_str(r#"Basic realm="webhooks""#).unwrap();
+                response
+                    .headers_mut()
+                    .insert(header::WWW_AUTHENTICATE, header_value);
+                response
+            }
+            Self::InvalidPayload(_) => HttpResponse::new(StatusCode::BAD_REQUEST),
+            Self::UnexpectedError(_) => HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    }
+}
+
+/// Receives delivery, bounce and spam complaint notifications from Postmark.
+/// Hard bounces and complaints stop any further email to the address.
+/// The body is only parsed once the caller is authenticated.
+#[tracing::instrument(name = "Receive a Postmark webhook", skip_all, fields(event))]
+pub async fn postmark_webhook(
+    request: HttpRequest,
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+    webhook_credentials: web::Data<WebhookCredentials>,
+) -> Result<HttpResponse, WebhookError> {
+    let credentials = basic_authentication(request.headers()).map_err(WebhookError::AuthError)?;
+    if !webhook_credentials.accepts(&credentials) {
+        return Err(WebhookError::AuthError(anyhow::anyhow!(
+            "Invalid webhook credentials."
+        )));
+    }
+    let event: PostmarkEvent = serde_json::from_slice(&body)
+        .context("Failed to parse the webhook payload.")
+        .map_err(WebhookError::InvalidPayload)?;
+    tracing::Span::current().record("event", tracing::field::debug(&event));
+
+    let Some(event) = event.into_delivery_event() else {
+        return Ok(HttpResponse::Ok().finish());
+    };
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let recorded = record_event(&mut transaction, &event)
+        .await
+        .context("Failed to record the delivery event.")?;
+    if !recorded {
+        // Sent again by the provider: it was acted on the first time, and an
+        // admin may have lifted the suppression since
+        return Ok(HttpResponse::Ok().finish());
+    }
+    if let Some(status) = event.kind.subscriber_status() {
+        mark_undeliverable(&mut transaction, &event.recipient, status)
+            .await
+            .context("Failed to update the status of an undeliverable subscriber.")?;
+    }
+    if let Some(source) = event.kind.suppression_source() {
+        // Also covers addresses that aren't, or are no longer, subscribers
+        match SuppressionTarget::parse(&event.recipient) {
+            Ok(target) => {
+                let reason = event.details.as_deref().unwrap_or(event.kind.as_str());
+                add_suppression(&mut transaction, &target, reason, source)
+                    .await
+                    .context("Failed to suppress an undeliverable address.")?;
+            }
+            Err(e) => tracing::warn!(
+                recipient = %event.recipient,
+                error = %e,
+                "Not suppressing an invalid recipient address."
+            ),
+        }
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the delivery event.")?;
+    Ok(HttpResponse::Ok().finish())
+}
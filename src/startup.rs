@@ -1,10 +1,10 @@
 use crate::authentication::reject_anonymous_users;
 use crate::configuration::Settings;
-use crate::email_client::EmailClient;
+use crate::email_client::EmailSender;
 use actix_session::storage::RedisSessionStore;
 use actix_session::SessionMiddleware;
 use actix_web::cookie::Key;
-use actix_web::dev::Server;
+use actix_web::dev::{Server, ServerHandle};
 use actix_web::{web, App, HttpServer};
 use actix_web_flash_messages::storage::CookieMessageStore;
 use actix_web_flash_messages::FlashMessagesFramework;
@@ -12,11 +12,22 @@ use actix_web_lab::middleware::from_fn;
 use secrecy::{ExposeSecret, Secret};
 use sqlx::PgPool;
 use std::net::TcpListener;
+use std::sync::Arc;
 use tracing_actix_web::TracingLogger;
 
 use crate::routes::{
-    admin_dashboard, change_password, change_password_form, confirm, health_check, home, log_out,
-    login, login_form, publish_newsletter, publish_newsletter_form, subscribe,
+    account_email_form, admin_dashboard, atom_feed, cancel_scheduled_newsletter,
+    change_account_email, change_password, change_password_form, change_subscriber_status, confirm,
+    count_newsletter_recipients, create_draft, create_mailing_list, create_suppression,
+    delete_subscriber, edit_draft, edit_draft_form, edit_layout_form, export_subscribers,
+    get_subscriber, get_subscriber_tags, health_check, home, import_subscribers, list_atom_feed,
+    list_drafts, list_issues, list_layouts, list_mailing_lists, list_rss_feed, list_subscribers,
+    list_suppressions, log_out, login, login_form, newsletter_delivery_report, postmark_webhook,
+    preview_draft, publish_draft, publish_newsletter, publish_newsletter_form, remove_draft,
+    remove_suppression, reschedule_newsletter, reset_layout_form, rss_feed, save_layout_form,
+    send_test_draft, set_subscriber_tags, show_issue, subscribe, track_click, track_open,
+    unsubscribe, unsubscribe_form, update_subscriber_attributes, update_subscriber_name,
+    WebhookCredentials,
 };
 
 pub struct Application {
@@ -24,13 +35,20 @@ pub struct Application {
     server: Server,
 }
 
+#[derive(Clone)]
 pub struct ApplicationBaseUrl(pub String);
 
 impl Application {
     pub async fn build(configuration: Settings) -> Result<Self, anyhow::Error> {
         let db_pool = get_database_pool(&configuration);
 
+        let webhook_credentials = WebhookCredentials {
+            username: configuration.email_client.webhook_username.clone(),
+            password: configuration.email_client.webhook_password.clone(),
+        };
         let email_client = configuration.email_client.client();
+        let subscription_token_ttl =
+            SubscriptionTokenTtl(configuration.application.subscription_token_ttl()?);
 
         let address = format!(
             "{}:{}",
@@ -43,8 +61,10 @@ impl Application {
             listener,
             db_pool,
             email_client,
-            configuration.application.base_url,
-            HmacSecret(configuration.application.hmac_secret),
+            configuration.application.base_url.clone(),
+            HmacSecret(configuration.application.hmac_secret.clone()),
+            subscription_token_ttl,
+            webhook_credentials,
             configuration.redis_uri,
         )
         .await?;
@@ -55,6 +75,11 @@ impl Application {
         self.port
     }
 
+    /// Lets the caller stop the server gracefully, e.g. on SIGTERM.
+    pub fn handle(&self) -> ServerHandle {
+        self.server.handle()
+    }
+
     // this function only returns when the application is stopped
     pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
         self.server.await
@@ -64,12 +89,20 @@ impl Application {
 #[derive(Clone)]
 pub struct HmacSecret(pub Secret<String>);
 
+/// How long a subscription token can be used to confirm a subscription,
+/// checked to be in range when the application is built.
+#[derive(Clone, Copy)]
+pub struct SubscriptionTokenTtl(pub chrono::Duration);
+
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
     listener: TcpListener,
     db_pool: PgPool,
-    email_client: EmailClient,
+    email_client: Arc<dyn EmailSender>,
     base_url: String,
     hmac_secret: HmacSecret,
+    subscription_token_ttl: SubscriptionTokenTtl,
+    webhook_credentials: WebhookCredentials,
     redis_uri: Secret<String>,
 ) -> Result<Server, anyhow::Error> {
     let secret = Key::from(hmac_secret.0.expose_secret().as_bytes());
@@ -78,9 +111,11 @@ pub async fn run(
     let redis_store = RedisSessionStore::new(redis_uri.expose_secret()).await?;
 
     let db_pool = web::Data::new(db_pool);
-    let email_client = web::Data::new(email_client);
+    let email_client: web::Data<dyn EmailSender> = web::Data::from(email_client);
     let base_url = web::Data::new(ApplicationBaseUrl(base_url));
     let hmac_secret = web::Data::new(hmac_secret);
+    let subscription_token_ttl = web::Data::new(subscription_token_ttl);
+    let webhook_credentials = web::Data::new(webhook_credentials);
 
     let server = HttpServer::new(move || {
         App::new()
@@ -93,28 +128,136 @@ pub async fn run(
             .route("/health_check", web::get().to(health_check))
             .route("/subscriptions", web::post().to(subscribe))
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route(
+                "/subscriptions/unsubscribe",
+                web::get().to(unsubscribe_form),
+            )
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
             .route("/newsletter", web::post().to(publish_newsletter))
+            .route("/webhooks/postmark", web::post().to(postmark_webhook))
+            .route("/issues", web::get().to(list_issues))
+            .route("/issues/{id_or_slug}", web::get().to(show_issue))
+            .route("/feed.xml", web::get().to(rss_feed))
+            .route("/atom.xml", web::get().to(atom_feed))
+            .route("/lists/{slug}/feed.xml", web::get().to(list_rss_feed))
+            .route("/lists/{slug}/atom.xml", web::get().to(list_atom_feed))
+            .route("/t/c/{token}", web::get().to(track_click))
+            .route("/t/o/{token}.gif", web::get().to(track_open))
             .service(
                 web::scope("/admin")
                     .wrap(from_fn(reject_anonymous_users))
                     .route("/dashboard", web::get().to(admin_dashboard))
                     .route("/password", web::get().to(change_password_form))
                     .route("/password", web::post().to(change_password))
+                    .route("/email", web::get().to(account_email_form))
+                    .route("/email", web::post().to(change_account_email))
                     .route("/logout", web::post().to(log_out)) // .route("/newsletter", web::post().to()),
+                    .route("/templates", web::get().to(list_layouts))
+                    .route("/templates/{name}", web::get().to(edit_layout_form))
+                    .route("/templates/{name}", web::post().to(save_layout_form))
+                    .route("/templates/{name}/reset", web::post().to(reset_layout_form))
                     .route("/newsletters", web::post().to(publish_newsletter))
-                    .route("/newsletters", web::get().to(publish_newsletter_form)),
+                    .route("/newsletters", web::get().to(publish_newsletter_form))
+                    .route(
+                        "/newsletters/recipients",
+                        web::get().to(count_newsletter_recipients),
+                    )
+                    .route("/newsletters/drafts", web::get().to(list_drafts))
+                    .route("/newsletters/drafts", web::post().to(create_draft))
+                    .route(
+                        "/newsletters/drafts/{newsletter_issue_id}",
+                        web::get().to(edit_draft_form),
+                    )
+                    .route(
+                        "/newsletters/drafts/{newsletter_issue_id}",
+                        web::post().to(edit_draft),
+                    )
+                    .route(
+                        "/newsletters/drafts/{newsletter_issue_id}/delete",
+                        web::post().to(remove_draft),
+                    )
+                    .route(
+                        "/newsletters/drafts/{newsletter_issue_id}/preview",
+                        web::get().to(preview_draft),
+                    )
+                    .route(
+                        "/newsletters/drafts/{newsletter_issue_id}/test",
+                        web::post().to(send_test_draft),
+                    )
+                    .route(
+                        "/newsletters/drafts/{newsletter_issue_id}/publish",
+                        web::post().to(publish_draft),
+                    )
+                    .route(
+                        "/newsletters/{newsletter_issue_id}",
+                        web::get().to(newsletter_delivery_report),
+                    )
+                    .route(
+                        "/newsletters/{newsletter_issue_id}/cancel",
+                        web::post().to(cancel_scheduled_newsletter),
+                    )
+                    .route(
+                        "/newsletters/{newsletter_issue_id}/reschedule",
+                        web::post().to(reschedule_newsletter),
+                    )
+                    .route("/api/lists", web::get().to(list_mailing_lists))
+                    .route("/api/lists", web::post().to(create_mailing_list))
+                    .route("/api/suppressions", web::get().to(list_suppressions))
+                    .route("/api/suppressions", web::post().to(create_suppression))
+                    .route(
+                        "/api/suppressions/{suppression_id}",
+                        web::delete().to(remove_suppression),
+                    )
+                    .route("/api/subscribers", web::get().to(list_subscribers))
+                    .route(
+                        "/api/subscribers/import",
+                        web::post().to(import_subscribers),
+                    )
+                    .route("/api/subscribers/export", web::get().to(export_subscribers))
+                    .route(
+                        "/api/subscribers/{subscriber_id}",
+                        web::get().to(get_subscriber),
+                    )
+                    .route(
+                        "/api/subscribers/{subscriber_id}",
+                        web::patch().to(update_subscriber_name),
+                    )
+                    .route(
+                        "/api/subscribers/{subscriber_id}",
+                        web::delete().to(delete_subscriber),
+                    )
+                    .route(
+                        "/api/subscribers/{subscriber_id}/attributes",
+                        web::put().to(update_subscriber_attributes),
+                    )
+                    .route(
+                        "/api/subscribers/{subscriber_id}/tags",
+                        web::get().to(get_subscriber_tags),
+                    )
+                    .route(
+                        "/api/subscribers/{subscriber_id}/tags",
+                        web::put().to(set_subscriber_tags),
+                    )
+                    .route(
+                        "/api/subscribers/{subscriber_id}/status",
+                        web::put().to(change_subscriber_status),
+                    ),
             )
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
             .app_data(hmac_secret.clone())
+            .app_data(subscription_token_ttl.clone())
+            .app_data(webhook_credentials.clone())
     })
     .listen(listener)?
+    // Shutdown is coordinated by `main`, together with the delivery workers
+    .disable_signals()
     .run();
     Ok(server)
 }
 
 pub fn get_database_pool(configuration: &Settings) -> PgPool {
-    PgPool::connect_lazy(&configuration.database.connection_string().expose_secret())
+    PgPool::connect_lazy(configuration.database.connection_string().expose_secret())
         .expect("Failed to read configuration.")
 }
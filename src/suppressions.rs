@@ -0,0 +1,219 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::domain::SubscriberEmail;
+
+/// How an address ended up suppressed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SuppressionSource {
+    // Added by an admin
+    Manual,
+    // Reported by the email provider
+    Bounce,
+    Complaint,
+    // Carried over with a subscriber import
+    Import,
+}
+
+impl SuppressionSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SuppressionSource::Manual => "manual",
+            SuppressionSource::Bounce => "bounce",
+            SuppressionSource::Complaint => "complaint",
+            SuppressionSource::Import => "import",
+        }
+    }
+}
+
+impl TryFrom<String> for SuppressionSource {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "manual" => Ok(SuppressionSource::Manual),
+            "bounce" => Ok(SuppressionSource::Bounce),
+            "complaint" => Ok(SuppressionSource::Complaint),
+            "import" => Ok(SuppressionSource::Import),
+            other => Err(format!("{} is not a valid suppression source.", other)),
+        }
+    }
+}
+
+/// What a suppression covers: a single address, or every address at a domain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SuppressionTarget {
+    Email(String),
+    Domain(String),
+}
+
+impl SuppressionTarget {
+    /// Anything with an `@` is an address, anything else a domain. Both are
+    /// matched regardless of case.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let s = s.trim().to_lowercase();
+        if s.contains('@') {
+            return SubscriberEmail::parse(s).map(|email| Self::Email(email.as_ref().to_owned()));
+        }
+        let is_valid = s.len() <= 253
+            && s.contains('.')
+            && s.split('.').all(|label| {
+                !label.is_empty()
+                    && label.len() <= 63
+                    && !label.starts_with('-')
+                    && !label.ends_with('-')
+                    && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            });
+        if is_valid {
+            Ok(Self::Domain(s))
+        } else {
+            Err(format!("{} is neither an email address nor a domain.", s))
+        }
+    }
+
+    fn email(&self) -> Option<&str> {
+        match self {
+            Self::Email(email) => Some(email),
+            Self::Domain(_) => None,
+        }
+    }
+
+    fn domain(&self) -> Option<&str> {
+        match self {
+            Self::Email(_) => None,
+            Self::Domain(domain) => Some(domain),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Suppression {
+    pub suppression_id: Uuid,
+    pub email: Option<String>,
+    pub domain: Option<String>,
+    pub reason: String,
+    pub source: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Whether `email` must not receive anything from us.
+#[tracing::instrument(name = "Check the suppression list", skip(connection))]
+pub async fn is_suppressed(
+    connection: &mut PgConnection,
+    email: &str,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM suppressions
+            WHERE email = lower($1) OR domain = lower(split_part($1, '@', 2))
+        ) AS "suppressed!"
+        "#,
+        email
+    )
+    .fetch_one(connection)
+    .await
+}
+
+/// Returns `None` if the target was already suppressed.
+#[tracing::instrument(name = "Add a suppression", skip(connection))]
+pub async fn add_suppression(
+    connection: &mut PgConnection,
+    target: &SuppressionTarget,
+    reason: &str,
+    source: SuppressionSource,
+) -> Result<Option<Suppression>, sqlx::Error> {
+    sqlx::query_as!(
+        Suppression,
+        r#"
+        INSERT INTO suppressions (suppression_id, email, domain, reason, source)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT DO NOTHING
+        RETURNING suppression_id, email, domain, reason, source, created_at
+        "#,
+        Uuid::new_v4(),
+        target.email(),
+        target.domain(),
+        reason,
+        source.as_str()
+    )
+    .fetch_optional(connection)
+    .await
+}
+
+#[tracing::instrument(name = "Get suppressions", skip(pool))]
+pub async fn get_suppressions(
+    pool: &PgPool,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<Suppression>, sqlx::Error> {
+    sqlx::query_as!(
+        Suppression,
+        r#"
+        SELECT suppression_id, email, domain, reason, source, created_at
+        FROM suppressions
+        ORDER BY created_at DESC, suppression_id
+        LIMIT $1
+        OFFSET $2
+        "#,
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Count suppressions", skip(pool))]
+pub async fn count_suppressions(pool: &PgPool) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM suppressions"#)
+        .fetch_one(pool)
+        .await
+}
+
+// Returns `false` if there was no such suppression.
+#[tracing::instrument(name = "Delete a suppression", skip(pool))]
+pub async fn delete_suppression_by_id(
+    pool: &PgPool,
+    suppression_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"DELETE FROM suppressions WHERE suppression_id = $1"#,
+        suppression_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SuppressionTarget;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn addresses_and_domains_are_told_apart_and_lowercased() {
+        assert_ok_eq!(
+            SuppressionTarget::parse(" Ursula@Gmail.com "),
+            SuppressionTarget::Email("ursula@gmail.com".into())
+        );
+        assert_ok_eq!(
+            SuppressionTarget::parse("Mailinator.COM"),
+            SuppressionTarget::Domain("mailinator.com".into())
+        );
+    }
+
+    #[test]
+    fn invalid_targets_are_rejected() {
+        for target in [
+            "",
+            "localhost",
+            "ursula@",
+            "-bad.com",
+            "bad..com",
+            "a b.com",
+        ] {
+            assert_err!(SuppressionTarget::parse(target), "{}", target);
+        }
+    }
+}
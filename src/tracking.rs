@@ -0,0 +1,255 @@
+use htmlescape::decode_html;
+use secrecy::Secret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::{TrackedEvent, TrackingToken};
+
+/// Routes every web link of an issue's HTML body through the click tracking
+/// redirect, except those in `untracked`, and adds the open tracking pixel.
+pub fn add_tracking(
+    html: &str,
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+    untracked: &[&str],
+) -> String {
+    let html = rewrite_links(html, |url| {
+        let is_web_link = url.starts_with("https://") || url.starts_with("http://");
+        if !is_web_link || untracked.contains(&url) {
+            return None;
+        }
+        let token = TrackingToken::click(newsletter_issue_id, subscriber_id, url, hmac_secret);
+        Some(format!("{}/t/c/{}", base_url, token.as_ref()))
+    });
+    let token = TrackingToken::open(newsletter_issue_id, subscriber_id, hmac_secret);
+    let pixel = format!(
+        r#"<img src="{}/t/o/{}.gif" width="1" height="1" alt="" style="border:0">"#,
+        base_url,
+        token.as_ref()
+    );
+    match find_ignore_case(&html, "</body") {
+        Some(i) => format!("{}{}{}", &html[..i], pixel, &html[i..]),
+        None => html + &pixel,
+    }
+}
+
+/// Stores an open or a click verified from a tracking token.
+#[tracing::instrument(skip(pool))]
+pub async fn record_tracking_event(pool: &PgPool, event: &TrackedEvent) -> Result<(), sqlx::Error> {
+    let (kind, newsletter_issue_id, subscriber_id, url) = match event {
+        TrackedEvent::Open {
+            newsletter_issue_id,
+            subscriber_id,
+        } => ("open", newsletter_issue_id, subscriber_id, None),
+        TrackedEvent::Click {
+            newsletter_issue_id,
+            subscriber_id,
+            url,
+        } => ("click", newsletter_issue_id, subscriber_id, Some(url)),
+    };
+    sqlx::query!(
+        r#"
+        INSERT INTO tracking_events (
+            tracking_event_id, newsletter_issue_id, subscriber_id, kind, url
+        )
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        Uuid::new_v4(),
+        newsletter_issue_id,
+        subscriber_id,
+        kind,
+        url
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// Calls `rewrite` with the unescaped `href` of every `<a>` tag and replaces it
+// with what it returns, if anything. The rest of the HTML is left as is.
+fn rewrite_links(html: &str, rewrite: impl Fn(&str) -> Option<String>) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = find_anchor(rest) {
+        let Some(tag_len) = tag_len(&rest[start..]) else {
+            break;
+        };
+        output.push_str(&rest[..start]);
+        let tag = &rest[start..start + tag_len];
+        match href_span(tag) {
+            Some((value_start, value_end)) => {
+                let raw = tag[value_start..value_end].trim_matches(|c| c == '"' || c == '\'');
+                let url = decode_html(raw).unwrap_or_else(|_| raw.to_owned());
+                match rewrite(&url) {
+                    Some(new_url) => {
+                        output.push_str(&tag[..value_start]);
+                        output.push('"');
+                        output.push_str(&new_url.replace('&', "&amp;").replace('"', "&quot;"));
+                        output.push('"');
+                        output.push_str(&tag[value_end..]);
+                    }
+                    None => output.push_str(tag),
+                }
+            }
+            None => output.push_str(tag),
+        }
+        rest = &rest[start + tag_len..];
+    }
+    output.push_str(rest);
+    output
+}
+
+// The start of the next `<a ...>` tag
+fn find_anchor(html: &str) -> Option<usize> {
+    let bytes = html.as_bytes();
+    (0..bytes.len().saturating_sub(2)).find(|&i| {
+        bytes[i] == b'<'
+            && bytes[i + 1].eq_ignore_ascii_case(&b'a')
+            && bytes[i + 2].is_ascii_whitespace()
+    })
+}
+
+// The length of the tag `html` starts with, up to and including its `>`
+fn tag_len(html: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in html.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '>') => return Some(i + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+// Where the value of the `href` attribute of `tag` is, quotes included
+fn href_span(tag: &str) -> Option<(usize, usize)> {
+    let bytes = tag.as_bytes();
+    // Skips `<a`
+    let mut i = 2;
+    loop {
+        while i < bytes.len() && (bytes[i].is_ascii_whitespace() || bytes[i] == b'/') {
+            i += 1;
+        }
+        if i >= bytes.len() || bytes[i] == b'>' {
+            return None;
+        }
+        let name_start = i;
+        while i < bytes.len() && !matches!(bytes[i], b'=' | b'>' | b'/') {
+            if bytes[i].is_ascii_whitespace() {
+                break;
+            }
+            i += 1;
+        }
+        let name = &tag[name_start..i];
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i >= bytes.len() || bytes[i] != b'=' {
+            // An attribute without a value
+            continue;
+        }
+        i += 1;
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        let value_start = i;
+        match bytes.get(i) {
+            Some(&quote @ (b'"' | b'\'')) => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote {
+                    i += 1;
+                }
+                i = (i + 1).min(bytes.len());
+            }
+            _ => {
+                while i < bytes.len() && !bytes[i].is_ascii_whitespace() && bytes[i] != b'>' {
+                    i += 1;
+                }
+            }
+        }
+        if name.eq_ignore_ascii_case("href") {
+            return Some((value_start, i));
+        }
+    }
+}
+
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .to_ascii_lowercase()
+        .rfind(&needle.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add_tracking, rewrite_links};
+    use crate::domain::{TrackedEvent, TrackingToken};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn upper(url: &str) -> Option<String> {
+        Some(url.to_uppercase())
+    }
+
+    #[test]
+    fn only_the_href_of_anchors_is_rewritten() {
+        let html = r#"<p><a class="x" href="https://a.com/?a=1&amp;b=2">A</a> <abbr title="y">B</abbr> <A HREF='http://b.com' target=_blank>C</A> <a name=top>D</a> <img src="https://c.com"></p>"#;
+
+        assert_eq!(
+            rewrite_links(html, upper),
+            r#"<p><a class="x" href="HTTPS://A.COM/?A=1&amp;B=2">A</a> <abbr title="y">B</abbr> <A HREF="HTTP://B.COM" target=_blank>C</A> <a name=top>D</a> <img src="https://c.com"></p>"#
+        );
+    }
+
+    #[test]
+    fn quotes_and_unquoted_values_are_handled() {
+        let html = r#"<a title="a > b" href=https://a.com>A</a><a data-x='"' href = "https://b.com">B</a>"#;
+
+        assert_eq!(
+            rewrite_links(html, upper),
+            r#"<a title="a > b" href="HTTPS://A.COM">A</a><a data-x='"' href = "HTTPS://B.COM">B</a>"#
+        );
+    }
+
+    #[test]
+    fn unfinished_tags_are_left_alone() {
+        assert_eq!(rewrite_links("<a href=", upper), "<a href=");
+        assert_eq!(rewrite_links("<a", upper), "<a");
+        assert_eq!(rewrite_links("x <a href", upper), "x <a href");
+    }
+
+    #[test]
+    fn web_links_are_tracked_and_a_pixel_is_added() {
+        let secret = Secret::new("super-secret".to_string());
+        let (issue_id, subscriber_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let html = r#"<body><a href="https://a.com">A</a><a href="mailto:x@y.com">M</a><a href="https://u.com">U</a></body>"#;
+
+        let tracked = add_tracking(
+            html,
+            issue_id,
+            subscriber_id,
+            "http://127.0.0.1",
+            &secret,
+            &["https://u.com"],
+        );
+
+        let token = tracked
+            .split("http://127.0.0.1/t/c/")
+            .nth(1)
+            .and_then(|s| s.split('"').next())
+            .unwrap();
+        assert_eq!(
+            TrackingToken::verify(token, &secret).unwrap(),
+            TrackedEvent::Click {
+                newsletter_issue_id: issue_id,
+                subscriber_id,
+                url: "https://a.com".into()
+            }
+        );
+        assert!(tracked.contains(r#"<a href="mailto:x@y.com">M</a><a href="https://u.com">U</a><img src="http://127.0.0.1/t/o/"#));
+        assert!(tracked.ends_with(r#".gif" width="1" height="1" alt="" style="border:0"></body>"#));
+    }
+}
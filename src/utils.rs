@@ -1,4 +1,5 @@
-use actix_web::HttpResponse;
+use actix_web::http::header::ContentType;
+use actix_web::{HttpResponse, HttpResponseBuilder};
 use reqwest::header::LOCATION;
 
 pub fn e500<T>(e: T) -> actix_web::Error
@@ -22,3 +23,20 @@ pub fn see_other(location: &str) -> HttpResponse {
         .insert_header((LOCATION, location))
         .finish()
 }
+
+// Wraps `body` in the minimal page shown to subscribers following a link
+// from one of our emails.
+pub fn html_page(mut builder: HttpResponseBuilder, title: &str, body: &str) -> HttpResponse {
+    builder.content_type(ContentType::html()).body(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{title}</title>
+</head>
+<body>
+    {body}
+</body>
+</html>"#
+    ))
+}
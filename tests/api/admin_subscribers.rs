@@ -0,0 +1,438 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use reqwest::Method;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn insert_subscriber(app: &TestApp, email: &str, status: &str) -> Uuid {
+    let id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, 'Ursula', now(), $3)
+        "#,
+        id,
+        email,
+        status
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    id
+}
+
+async fn get_json(app: &TestApp, path: &str) -> serde_json::Value {
+    let response = app.admin_api(Method::GET, path).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    response.json().await.unwrap()
+}
+
+fn emails(page: &serde_json::Value) -> Vec<&str> {
+    let mut emails: Vec<&str> = page["subscribers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| s["email"].as_str().unwrap())
+        .collect();
+    emails.sort();
+    emails
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_use_the_subscribers_api() {
+    let app = spawn_app().await;
+
+    let response = app
+        .admin_api(Method::GET, "/subscribers")
+        .send()
+        .await
+        .unwrap();
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_can_be_filtered_by_status_and_email() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    insert_subscriber(&app, "ursula@gmail.com", "confirmed").await;
+    insert_subscriber(&app, "le_guin@gmail.com", "pending_confirmation").await;
+    insert_subscriber(&app, "ursula@example.com", "confirmed").await;
+
+    let confirmed = get_json(&app, "/subscribers?status=confirmed").await;
+    assert_eq!(
+        emails(&confirmed),
+        vec!["ursula@example.com", "ursula@gmail.com"]
+    );
+    assert_eq!(confirmed["total"], 2);
+
+    let gmail = get_json(&app, "/subscribers?status=confirmed&email=GMAIL").await;
+    assert_eq!(emails(&gmail), vec!["ursula@gmail.com"]);
+
+    // `_` is matched literally, not as a wildcard
+    let underscore = get_json(&app, "/subscribers?email=_").await;
+    assert_eq!(emails(&underscore), vec!["le_guin@gmail.com"]);
+}
+
+#[tokio::test]
+async fn subscribers_are_paginated() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    for i in 0..5 {
+        insert_subscriber(&app, &format!("user{}@gmail.com", i), "confirmed").await;
+    }
+
+    let first = get_json(&app, "/subscribers?page=1&page_size=2").await;
+    let third = get_json(&app, "/subscribers?page=3&page_size=2").await;
+
+    assert_eq!(first["subscribers"].as_array().unwrap().len(), 2);
+    assert_eq!(third["subscribers"].as_array().unwrap().len(), 1);
+    assert_eq!(first["total"], 5);
+
+    for query in [
+        "page=0",
+        "page=9223372036854775807",
+        "page_size=0",
+        "page_size=1000",
+        "status=unknown",
+    ] {
+        let response = app
+            .admin_api(Method::GET, &format!("/subscribers?{}", query))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 400, "{}", query);
+    }
+}
+
+#[tokio::test]
+async fn a_single_subscriber_can_be_fetched() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let id = insert_subscriber(&app, "ursula@gmail.com", "confirmed").await;
+
+    let subscriber = get_json(&app, &format!("/subscribers/{}", id)).await;
+    let missing = app
+        .admin_api(Method::GET, &format!("/subscribers/{}", Uuid::new_v4()))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(subscriber["email"], "ursula@gmail.com");
+    assert_eq!(subscriber["status"], "confirmed");
+    assert_eq!(missing.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn names_are_validated_when_updated() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let id = insert_subscriber(&app, "ursula@gmail.com", "confirmed").await;
+    let path = format!("/subscribers/{}", id);
+
+    let invalid = app
+        .admin_api(Method::PATCH, &path)
+        .json(&serde_json::json!({ "name": "<script>" }))
+        .send()
+        .await
+        .unwrap();
+    let valid = app
+        .admin_api(Method::PATCH, &path)
+        .json(&serde_json::json!({ "name": "Ursula K. Le Guin" }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(invalid.status().as_u16(), 400);
+    assert_eq!(valid.status().as_u16(), 200);
+    let subscriber = get_json(&app, &path).await;
+    assert_eq!(subscriber["name"], "Ursula K. Le Guin");
+}
+
+#[tokio::test]
+async fn admins_can_confirm_unsubscribe_and_block_subscribers() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let id = insert_subscriber(&app, "ursula@gmail.com", "pending_confirmation").await;
+    let path = format!("/subscribers/{}/status", id);
+
+    for status in ["confirmed", "unsubscribed", "blocked"] {
+        let response = app
+            .admin_api(Method::PUT, &path)
+            .json(&serde_json::json!({ "status": status }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+        let subscriber: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(subscriber["status"], status);
+        // Confirmation time is kept once recorded
+        assert!(!subscriber["confirmed_at"].is_null());
+    }
+
+    for status in ["pending_confirmation", "deleted"] {
+        let response = app
+            .admin_api(Method::PUT, &path)
+            .json(&serde_json::json!({ "status": status }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 400);
+    }
+}
+
+#[tokio::test]
+async fn deleted_subscribers_are_gone() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let id = insert_subscriber(&app, "ursula@gmail.com", "pending_confirmation").await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_tokens (subscription_token, subscriber_id, list_id)
+        SELECT 'token', $1, list_id FROM lists WHERE slug = 'newsletter'
+        "#,
+        id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let path = format!("/subscribers/{}", id);
+
+    let first = app.admin_api(Method::DELETE, &path).send().await.unwrap();
+    let second = app.admin_api(Method::DELETE, &path).send().await.unwrap();
+
+    assert_eq!(first.status().as_u16(), 204);
+    assert_eq!(second.status().as_u16(), 404);
+    let get = app.admin_api(Method::GET, &path).send().await.unwrap();
+    assert_eq!(get.status().as_u16(), 404);
+}
+
+pub async fn import_csv(app: &TestApp, query: &str, csv: &str) -> reqwest::Response {
+    app.admin_api(Method::POST, &format!("/subscribers/import{}", query))
+        .header("Content-Type", "text/csv")
+        .body(csv.to_owned())
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn imported_rows_are_validated_and_deduplicated() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    insert_subscriber(&app, "already@gmail.com", "confirmed").await;
+    let csv = "\
+Email,Name,Status,Subscribed_At
+ursula@gmail.com,\"Le Guin, Ursula\",confirmed,2020-01-01T10:00:00Z
+not-an-email,Someone,,
+le_guin@gmail.com,Ursula,,
+already@gmail.com,Someone,,
+ursula@gmail.com,Ursula again,,
+someone@gmail.com,Someone,deleted,
+";
+
+    let response = import_csv(&app, "", csv).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["imported"], 2);
+    assert_eq!(report["duplicates"], serde_json::json!([5, 6]));
+    let error_rows: Vec<_> = report["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["row"].as_u64().unwrap())
+        .collect();
+    assert_eq!(error_rows, vec![3, 7]);
+
+    let imported = sqlx::query!(
+        r#"
+        SELECT s.name, s.status, s.subscribed_at, s.confirmed_at, m.confirmed_at AS membership_confirmed_at
+        FROM subscriptions s JOIN list_memberships m ON m.subscriber_id = s.id
+        WHERE s.email = 'ursula@gmail.com'
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(imported.name, "Le Guin, Ursula");
+    assert_eq!(imported.status, "confirmed");
+    assert_eq!(
+        imported.subscribed_at.to_rfc3339(),
+        "2020-01-01T10:00:00+00:00"
+    );
+    // Confirmed rows count as confirmed when they subscribed
+    assert_eq!(imported.confirmed_at, Some(imported.subscribed_at));
+    assert_eq!(
+        imported.membership_confirmed_at,
+        Some(imported.subscribed_at)
+    );
+    let pending = get_json(&app, "/subscribers?status=pending_confirmation").await;
+    assert_eq!(emails(&pending), vec!["le_guin@gmail.com"]);
+}
+
+#[tokio::test]
+async fn imported_pending_rows_can_be_sent_a_confirmation_email() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let csv = "email,name,status\n\
+        ursula@gmail.com,Ursula,\n\
+        le_guin@gmail.com,Ursula,confirmed\n";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = import_csv(&app, "?send_confirmation=true", csv).await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "ursula@gmail.com");
+    let confirmation_link = app.get_confirmation_links(email_request).html;
+    reqwest::get(confirmation_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let confirmed = get_json(&app, "/subscribers?status=confirmed").await;
+    assert_eq!(
+        emails(&confirmed),
+        vec!["le_guin@gmail.com", "ursula@gmail.com"]
+    );
+}
+
+#[tokio::test]
+async fn imports_without_email_and_name_columns_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    for csv in ["", "address,name\nursula@gmail.com,Ursula\n"] {
+        let response = import_csv(&app, "", csv).await;
+        assert_eq!(response.status().as_u16(), 400, "{:?}", csv);
+    }
+}
+
+#[tokio::test]
+async fn the_export_streams_every_subscriber_matching_the_filter() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    // More than a page, with identical timestamps across the page boundary
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        SELECT gen_random_uuid(), 'user' || i || '@gmail.com', 'User', '2024-01-01T00:00:00Z',
+            CASE WHEN i % 2 = 0 THEN 'confirmed' ELSE 'unsubscribed' END
+        FROM generate_series(1, 2500) AS i
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let confirmed = app
+        .admin_api(Method::GET, "/subscribers/export?status=confirmed")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(confirmed.status().as_u16(), 200);
+    assert!(confirmed.headers()["Content-Type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/csv"));
+    let csv = confirmed.text().await.unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines[0], "email,name,status,subscribed_at,confirmed_at");
+    assert_eq!(lines.len(), 1 + 1250);
+    assert!(lines[1..].iter().all(|l| l.contains(",confirmed,")));
+    let unique: std::collections::HashSet<_> = lines.iter().collect();
+    assert_eq!(unique.len(), lines.len());
+
+    let everyone = app
+        .admin_api(
+            Method::GET,
+            "/subscribers/export?status=confirmed,unsubscribed",
+        )
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(everyone.lines().count(), 1 + 2500);
+
+    let invalid = app
+        .admin_api(Method::GET, "/subscribers/export?status=deleted")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(invalid.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn exported_csv_can_be_imported_back() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let id = insert_subscriber(&app, "ursula@gmail.com", "confirmed").await;
+    // Escaped as a formula in the export
+    sqlx::query!(
+        "UPDATE subscriptions SET name = '-Ursula' WHERE id = $1",
+        id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let csv = app
+        .admin_api(Method::GET, "/subscribers/export")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    sqlx::query!("DELETE FROM subscriptions")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let report: serde_json::Value = import_csv(&app, "", &csv).await.json().await.unwrap();
+
+    assert_eq!(report["imported"], 1);
+    let subscriber = get_json(&app, "/subscribers?status=confirmed").await;
+    assert_eq!(emails(&subscriber), vec!["ursula@gmail.com"]);
+    assert_eq!(subscriber["subscribers"][0]["name"], "-Ursula");
+}
+
+#[tokio::test]
+async fn exported_cells_are_never_spreadsheet_formulas() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let id = insert_subscriber(&app, "ursula@gmail.com", "confirmed").await;
+    sqlx::query!(
+        r#"UPDATE subscriptions SET name = '=HYPERLINK("http://evil.example")' WHERE id = $1"#,
+        id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let csv = app
+        .admin_api(Method::GET, "/subscribers/export")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    let row = csv.lines().nth(1).unwrap();
+    assert!(
+        row.starts_with(r#"ursula@gmail.com,"'=HYPERLINK(""http://evil.example"")",confirmed,"#),
+        "{}",
+        row
+    );
+}
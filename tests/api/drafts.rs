@@ -0,0 +1,243 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::newsletter::create_confirmed_subscriber;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn draft_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Draft title",
+        "text_content": "Draft body as plain text.",
+        "html_content": "<p>Draft body as HTML.</p>"
+    })
+}
+
+async fn create_draft(app: &TestApp) -> uuid::Uuid {
+    let response = app
+        .post_admin_form("/admin/newsletters/drafts", &draft_request_body())
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+    let location = response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    location
+        .strip_prefix("/admin/newsletters/drafts/")
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_save_a_draft() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_admin_form("/admin/newsletters/drafts", &draft_request_body())
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn drafts_are_not_delivered() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let draft_id = create_draft(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let html_page = app.get_admin_html("/admin/newsletters/drafts").await;
+    assert!(html_page.contains(&format!("/admin/newsletters/drafts/{}", draft_id)));
+
+    let issue = sqlx::query!("SELECT status FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.status, "draft");
+}
+
+#[tokio::test]
+async fn drafts_can_be_edited() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app).await;
+    let draft_path = format!("/admin/newsletters/drafts/{}", draft_id);
+
+    let response = app
+        .post_admin_form(
+            &draft_path,
+            &serde_json::json!({
+                "title": "Edited title",
+                "text_content": "Edited body.",
+                "html_content": "<p>Edited body.</p>"
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, &draft_path);
+
+    let html_page = app.get_admin_html(&draft_path).await;
+    assert!(html_page.contains(r#"value="Edited&#x20;title""#));
+    assert!(html_page.contains("&lt;p&gt;Edited body.&lt;/p&gt;"));
+}
+
+#[tokio::test]
+async fn drafts_can_be_deleted() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app).await;
+
+    let response = app
+        .post_admin_form(
+            &format!("/admin/newsletters/drafts/{}/delete", draft_id),
+            &serde_json::json!({}),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters/drafts");
+
+    let issues = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.count, 0);
+}
+
+#[tokio::test]
+async fn preview_renders_the_html_content_in_a_sandbox() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app).await;
+
+    let html_page = app
+        .get_admin_html(&format!("/admin/newsletters/drafts/{}/preview", draft_id))
+        .await;
+
+    assert!(html_page.contains(r#"<iframe sandbox srcdoc="&lt;p&gt;Draft&#x20;body"#));
+    assert!(html_page.contains("Draft body as plain text."));
+}
+
+#[tokio::test]
+async fn test_sends_only_go_to_the_logged_in_admin() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    app.post_admin_form(
+        "/admin/email",
+        &serde_json::json!({ "email": "admin@example.com" }),
+    )
+    .await;
+    let draft_id = create_draft(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let draft_path = format!("/admin/newsletters/drafts/{}", draft_id);
+    let response = app
+        .post_admin_form(&format!("{}/test", draft_path), &serde_json::json!({}))
+        .await;
+    assert_is_redirect_to(&response, &draft_path);
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "admin@example.com");
+    assert_eq!(body["Subject"], "[Test] Draft title");
+    // Laid out as subscribers will get it
+    for part in ["HtmlBody", "TextBody"] {
+        assert!(body[part]
+            .as_str()
+            .unwrap()
+            .contains("/subscriptions/unsubscribe?token="));
+    }
+    let headers = body["Headers"].as_array().unwrap();
+    assert_eq!(headers[0]["Name"], "List-Unsubscribe");
+    assert_eq!(headers[1]["Name"], "List-Unsubscribe-Post");
+}
+
+#[tokio::test]
+async fn test_sends_require_an_admin_email_address() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let draft_path = format!("/admin/newsletters/drafts/{}", draft_id);
+    app.post_admin_form(&format!("{}/test", draft_path), &serde_json::json!({}))
+        .await;
+
+    let html_page = app.get_admin_html(&draft_path).await;
+    assert!(html_page
+        .contains("<p><i>Set an email address for your account before sending a test.</i></p>"));
+}
+
+#[tokio::test]
+async fn published_drafts_are_delivered_to_confirmed_subscribers() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let draft_id = create_draft(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_admin_form(
+            &format!("/admin/newsletters/drafts/{}/publish", draft_id),
+            &serde_json::json!({
+                "idempotency_key": uuid::Uuid::new_v4().to_string(),
+                "scheduled_for": ""
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, &format!("/admin/newsletters/{}", draft_id));
+
+    let issue = sqlx::query!("SELECT status FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.status, "sending");
+
+    app.dispatch_all_pending_emails().await;
+
+    let issue = sqlx::query!("SELECT status FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.status, "sent");
+
+    // Published issues can no longer be edited as drafts
+    let response = app
+        .post_admin_form(
+            &format!("/admin/newsletters/drafts/{}", draft_id),
+            &draft_request_body(),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -0,0 +1,167 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::lists::{create_list, publish_to};
+use uuid::Uuid;
+
+async fn publish(app: &TestApp, title: &str, lists: &str, scheduled_for: Option<&str>) {
+    let mut body = serde_json::json!({
+        "title": title,
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Hello {{ name }} & welcome</p><script>steal()</script>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+        "lists": lists,
+    });
+    if let Some(scheduled_for) = scheduled_for {
+        body["scheduled_for"] = scheduled_for.into();
+    }
+    let response = app.post_publish_newsletter(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+async fn get_feed(app: &TestApp, path: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}{}", app.address, path))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn get_feed_xml(app: &TestApp, path: &str) -> String {
+    let response = get_feed(app, path).await;
+    assert_eq!(response.status().as_u16(), 200);
+    response.text().await.unwrap()
+}
+
+#[tokio::test]
+async fn feeds_list_published_issues() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish(&app, "Fish & chips", "", None).await;
+    let tomorrow = (chrono::Utc::now() + chrono::Duration::days(1)).to_rfc3339();
+    publish(&app, "Not yet", "", Some(&tomorrow)).await;
+    let published_at: chrono::DateTime<chrono::Utc> = sqlx::query_scalar!(
+        r#"SELECT published_at AS "published_at!" FROM newsletter_issues WHERE slug = 'fish-chips'"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    let url = format!("{}/issues/fish-chips", app.base_url.0);
+
+    let response = get_feed(&app, "/feed.xml").await;
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "application/rss+xml; charset=utf-8"
+    );
+    let rss = response.text().await.unwrap();
+    assert!(rss.contains(r#"<rss version="2.0""#));
+    assert_eq!(rss.matches("<item>").count(), 1);
+    assert!(rss.contains("<title>Fish &amp; chips</title>"));
+    assert!(rss.contains(&format!("<link>{}</link>", url)));
+    assert!(rss.contains(&format!("<pubDate>{}</pubDate>", published_at.to_rfc2822())));
+    assert!(rss.contains("&lt;p&gt;Hello reader &amp;amp; welcome&lt;/p&gt;"));
+    assert!(!rss.contains("steal"));
+
+    let atom = get_feed_xml(&app, "/atom.xml").await;
+    assert!(atom.contains(r#"<feed xmlns="http://www.w3.org/2005/Atom">"#));
+    assert_eq!(atom.matches("<entry>").count(), 1);
+    assert!(atom.contains(&format!(r#"href="{}""#, url)));
+    assert!(atom.contains(&format!(
+        "<published>{}</published>",
+        published_at.to_rfc3339()
+    )));
+    assert!(!atom.contains("Not yet"));
+}
+
+#[tokio::test]
+async fn feeds_support_conditional_requests() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish(&app, "First issue", "", None).await;
+
+    let response = get_feed(&app, "/feed.xml").await;
+    let etag = response.headers().get("ETag").unwrap().clone();
+    let last_modified = response.headers().get("Last-Modified").unwrap().clone();
+    let conditional_get = |name: &'static str, value: reqwest::header::HeaderValue| {
+        let app = &app;
+        async move {
+            app.api_client
+                .get(format!("{}/feed.xml", app.address))
+                .header(name, value)
+                .send()
+                .await
+                .unwrap()
+        }
+    };
+
+    let response = conditional_get("If-None-Match", etag.clone()).await;
+    assert_eq!(response.status().as_u16(), 304);
+    assert_eq!(response.headers().get("ETag").unwrap(), &etag);
+    assert!(response.text().await.unwrap().is_empty());
+    let response = conditional_get("If-Modified-Since", last_modified.clone()).await;
+    assert_eq!(response.status().as_u16(), 304);
+
+    // Last-Modified only changes once the next issue is published a second later
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    publish(&app, "Second issue", "", None).await;
+    let response = conditional_get("If-None-Match", etag).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("Second issue"));
+    let response = conditional_get("If-Modified-Since", last_modified).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn lists_have_their_own_feeds() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_list(&app, "product-updates").await;
+    create_list(&app, "events").await;
+    publish(&app, "New feature", "product-updates", None).await;
+    publish(&app, "Everyone is invited", "product-updates,events", None).await;
+
+    let rss = get_feed_xml(&app, "/lists/product-updates/feed.xml").await;
+    assert!(rss.contains("<title>PRODUCT-UPDATES</title>"));
+    assert_eq!(rss.matches("<item>").count(), 2);
+    let atom = get_feed_xml(&app, "/lists/events/atom.xml").await;
+    assert_eq!(atom.matches("<entry>").count(), 1);
+    assert!(atom.contains("Everyone is invited"));
+    assert!(atom.contains(&format!(
+        r#"<link rel="self" type="application/atom+xml" href="{}/lists/events/atom.xml"/>"#,
+        app.base_url.0
+    )));
+    assert_eq!(
+        get_feed(&app, "/lists/unknown/feed.xml")
+            .await
+            .status()
+            .as_u16(),
+        404
+    );
+}
+
+#[tokio::test]
+async fn issues_kept_out_of_the_archive_are_left_out_of_feeds() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_list(&app, "beta").await;
+    let response = publish_to(&app, "beta", "tag:early-access").await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Members only",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "lists": "beta",
+            "hide_from_archive": "true",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    for path in ["/feed.xml", "/lists/beta/feed.xml"] {
+        let rss = get_feed_xml(&app, path).await;
+        assert_eq!(rss.matches("<item>").count(), 0, "{}", path);
+    }
+    for path in ["/atom.xml", "/lists/beta/atom.xml"] {
+        let atom = get_feed_xml(&app, path).await;
+        assert_eq!(atom.matches("<entry>").count(), 0, "{}", path);
+    }
+}
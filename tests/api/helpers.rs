@@ -3,14 +3,20 @@ use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
 use once_cell::sync::Lazy;
 use reqwest::{Client, Proxy};
 use secrecy::ExposeSecret;
-use server_scaffold::email_client::EmailClient;
+use server_scaffold::email_client::{EmailClientKind, EmailSender};
+use server_scaffold::email_outbox::try_dispatch_email;
 use server_scaffold::{
-    configuration::{get_configuration, DatabaseSettings},
-    issue_delivery_worker::{try_execute_task, ExecutionOutcome},
-    startup::{get_database_pool, Application},
+    configuration::{get_configuration, DatabaseSettings, WorkerSettings},
+    issue_delivery_worker::{run_workers, try_execute_task, ExecutionOutcome},
+    rate_limiter::SendRateLimiter,
+    startup::{get_database_pool, Application, ApplicationBaseUrl, HmacSecret},
     telemetry::{get_subscriber, init_subscriber},
 };
 use sqlx::{Connection, Executor, PgConnection, PgPool};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 use wiremock::MockServer;
 
@@ -35,7 +41,35 @@ pub struct TestApp {
     pub port: u16,
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
-    pub email_client: EmailClient,
+    pub email_client: Arc<dyn EmailSender>,
+    pub base_url: ApplicationBaseUrl,
+    pub hmac_secret: HmacSecret,
+    pub rate_limiter: Arc<SendRateLimiter>,
+}
+
+pub struct BackgroundWorkers {
+    pub shutdown: CancellationToken,
+    pub handle: JoinHandle<Result<(), anyhow::Error>>,
+}
+
+impl BackgroundWorkers {
+    /// Asks the workers to stop and waits for them to exit, at most for `deadline`.
+    pub async fn stop(self, deadline: Duration) -> Result<(), anyhow::Error> {
+        self.shutdown.cancel();
+        tokio::time::timeout(deadline, self.handle)
+            .await
+            .map_err(|_| anyhow::anyhow!("The workers did not stop within {:?}", deadline))??
+    }
+}
+
+/// Workers that only find new tasks through notifications, or once a minute.
+pub fn slow_polling_worker_settings() -> WorkerSettings {
+    WorkerSettings {
+        concurrency: 2,
+        empty_queue_poll_interval_milliseconds: 60_000,
+        error_poll_interval_milliseconds: 1_000,
+        rate_limit: Default::default(),
+    }
 }
 
 pub struct ConfirmationLinks {
@@ -44,18 +78,56 @@ pub struct ConfirmationLinks {
 }
 
 impl TestApp {
+    /// Sends everything waiting in the email outbox and the delivery queue.
     pub async fn dispatch_all_pending_emails(&self) {
+        while let ExecutionOutcome::TaskCompleted = try_dispatch_email(
+            &self.db_pool,
+            self.email_client.as_ref(),
+            &self.rate_limiter,
+        )
+        .await
+        .unwrap()
+        {}
         loop {
-            if let ExecutionOutcome::EmptyQueue =
-                try_execute_task(&self.db_pool, &self.email_client)
-                    .await
-                    .unwrap()
+            if let ExecutionOutcome::EmptyQueue = try_execute_task(
+                &self.db_pool,
+                self.email_client.as_ref(),
+                &self.base_url,
+                &self.hmac_secret,
+                &self.rate_limiter,
+                &CancellationToken::new(),
+            )
+            .await
+            .unwrap()
             {
                 break;
             }
         }
     }
 
+    /// Runs a pool of delivery workers in the background until the token is cancelled.
+    pub fn spawn_workers(&self, settings: WorkerSettings) -> BackgroundWorkers {
+        self.spawn_workers_with(self.email_client.clone(), settings)
+    }
+
+    /// Like `spawn_workers`, sending emails through `email_client`.
+    pub fn spawn_workers_with(
+        &self,
+        email_client: Arc<dyn EmailSender>,
+        settings: WorkerSettings,
+    ) -> BackgroundWorkers {
+        let shutdown = CancellationToken::new();
+        let handle = tokio::spawn(run_workers(
+            self.db_pool.clone(),
+            email_client,
+            self.base_url.clone(),
+            self.hmac_secret.clone(),
+            settings,
+            shutdown.clone(),
+        ));
+        BackgroundWorkers { shutdown, handle }
+    }
+
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         self.api_client
             .post(format!("{}/subscriptions", &self.address))
@@ -159,6 +231,77 @@ impl TestApp {
             .expect("Failed to execute request")
     }
 
+    pub async fn post_publish_newsletter_json(
+        &self,
+        body: &serde_json::Value,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/newsletters", self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_cancel_newsletter(&self, newsletter_issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/{}/cancel",
+                self.address, newsletter_issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_reschedule_newsletter<Body>(
+        &self,
+        newsletter_issue_id: Uuid,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/{}/reschedule",
+                self.address, newsletter_issue_id
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_admin_form<Body>(&self, path: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}{}", self.address, path))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Starts a request to the admin JSON API, e.g. `/subscribers`.
+    pub fn admin_api(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        self.api_client
+            .request(method, format!("{}/admin/api{}", self.address, path))
+    }
+
+    pub async fn get_admin_html(&self, path: &str) -> String {
+        self.api_client
+            .get(format!("{}{}", self.address, path))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .expect("Failed to execute request")
+    }
+
     pub async fn get_publish_newsletter(&self) -> reqwest::Response {
         self.api_client
             .get(format!("{}/admin/newsletters", self.address))
@@ -175,6 +318,20 @@ impl TestApp {
             .expect("Failed to execute request")
     }
 
+    pub async fn get_newsletter_report_html(&self, newsletter_issue_id: Uuid) -> String {
+        self.api_client
+            .get(format!(
+                "{}/admin/newsletters/{}",
+                self.address, newsletter_issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .expect("Failed to execute request")
+    }
+
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
         let get_link = |s: &str| {
@@ -190,8 +347,8 @@ impl TestApp {
             confirmation_link
         };
 
-        let html = get_link(&body["HtmlBody"].as_str().unwrap());
-        let plain_text = get_link(&body["TextBody"].as_str().unwrap());
+        let html = get_link(body["HtmlBody"].as_str().unwrap());
+        let plain_text = get_link(body["TextBody"].as_str().unwrap());
         ConfirmationLinks { html, plain_text }
     }
 }
@@ -251,6 +408,7 @@ pub async fn spawn_app() -> TestApp {
         let mut c = get_configuration().expect("Failed to read configuration");
         c.database.database_name = uuid::Uuid::new_v4().to_string();
         c.application.port = 0;
+        c.email_client.kind = EmailClientKind::Postmark;
         c.email_client.base_url = email_server.uri();
         c
     };
@@ -261,7 +419,7 @@ pub async fn spawn_app() -> TestApp {
         .await
         .expect("Failed to build application.");
     let application_port = application.port();
-    let _ = tokio::spawn(application.run_until_stopped());
+    tokio::spawn(application.run_until_stopped());
 
     let client = reqwest::Client::builder()
         .redirect(reqwest::redirect::Policy::none())
@@ -277,6 +435,9 @@ pub async fn spawn_app() -> TestApp {
         test_user: TestUser::generate(),
         api_client: client,
         email_client: configuration.email_client.client(),
+        base_url: ApplicationBaseUrl(configuration.application.base_url),
+        hmac_secret: HmacSecret(configuration.application.hmac_secret),
+        rate_limiter: Arc::new(SendRateLimiter::new(&configuration.worker.rate_limit)),
     };
 
     test_app.test_user.store(&test_app.db_pool).await;
@@ -286,7 +447,7 @@ pub async fn spawn_app() -> TestApp {
 
 async fn configure_database(config: &DatabaseSettings) -> PgPool {
     let mut connection =
-        PgConnection::connect(&config.connection_string_without_dbname().expose_secret())
+        PgConnection::connect(config.connection_string_without_dbname().expose_secret())
             .await
             .expect("Failed to connect to database.");
 
@@ -295,7 +456,7 @@ async fn configure_database(config: &DatabaseSettings) -> PgPool {
         .await
         .expect("Failed to create database");
 
-    let db_pool = PgPool::connect(&config.connection_string().expose_secret())
+    let db_pool = PgPool::connect(config.connection_string().expose_secret())
         .await
         .expect("Failed to connect to Postgres");
 
@@ -0,0 +1,251 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::lists::{publish_to, subscribe_and_confirm};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn publish(app: &TestApp, title: &str, html_content: &str, scheduled_for: Option<&str>) {
+    let mut body = serde_json::json!({
+        "title": title,
+        "text_content": "Newsletter body as plain text",
+        "html_content": html_content,
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    if let Some(scheduled_for) = scheduled_for {
+        body["scheduled_for"] = scheduled_for.into();
+    }
+    let response = app.post_publish_newsletter(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+async fn get_page(app: &TestApp, path: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}{}", app.address, path))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn get_html(app: &TestApp, path: &str) -> String {
+    let response = get_page(app, path).await;
+    assert_eq!(response.status().as_u16(), 200);
+    response.text().await.unwrap()
+}
+
+#[tokio::test]
+async fn published_issues_can_be_read_on_the_web() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish(
+        &app,
+        "Our 1st issue",
+        r#"<p onclick="steal()">Hello {{ name }}</p><script>steal()</script>"#,
+        None,
+    )
+    .await;
+    publish(&app, "Our 1st issue", "<p>Same title</p>", None).await;
+    let id: Uuid = sqlx::query_scalar!(
+        "SELECT newsletter_issue_id FROM newsletter_issues WHERE slug = 'our-1st-issue-2'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+
+    let archive = get_html(&app, "/issues").await;
+    assert!(archive.contains(r#"<a href="/issues/our-1st-issue">Our 1st issue</a>"#));
+    assert!(archive.contains(r#"<a href="/issues/our-1st-issue-2">Our 1st issue</a>"#));
+
+    let issue = get_html(&app, "/issues/our-1st-issue").await;
+    assert!(issue.contains("<p>Hello reader</p>"));
+    assert!(!issue.contains("script"));
+    assert!(!issue.contains("onclick"));
+    let issue = get_html(&app, &format!("/issues/{}", id)).await;
+    assert!(issue.contains("<p>Same title</p>"));
+}
+
+#[tokio::test]
+async fn issues_published_at_the_same_time_get_distinct_slugs() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let issues = (0..4).map(|_| publish(&app, "Same title", "<p>Body</p>", None));
+    futures_util::future::join_all(issues).await;
+
+    let mut slugs: Vec<String> = sqlx::query_scalar!(
+        r#"SELECT slug AS "slug!" FROM newsletter_issues WHERE slug IS NOT NULL"#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    slugs.sort();
+    assert_eq!(
+        slugs,
+        ["same-title", "same-title-2", "same-title-3", "same-title-4"]
+    );
+}
+
+#[tokio::test]
+async fn drafts_and_scheduled_issues_stay_hidden() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let response = app
+        .post_admin_form(
+            "/admin/newsletters/drafts",
+            &serde_json::json!({
+                "title": "Draft title",
+                "text_content": "Draft body as plain text.",
+                "html_content": "<p>Draft body as HTML.</p>"
+            }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+    let tomorrow = (chrono::Utc::now() + chrono::Duration::days(1)).to_rfc3339();
+    publish(&app, "Scheduled title", "<p>Not yet</p>", Some(&tomorrow)).await;
+
+    let archive = get_html(&app, "/issues").await;
+    assert!(archive.contains("Nothing has been published yet."));
+    let ids = sqlx::query_scalar!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(ids.len(), 2);
+    for path in [
+        "/issues/scheduled-title".to_string(),
+        format!("/issues/{}", ids[0]),
+        format!("/issues/{}", ids[1]),
+    ] {
+        assert_eq!(get_page(&app, &path).await.status().as_u16(), 404);
+    }
+}
+
+#[tokio::test]
+async fn issues_sent_to_a_segment_or_kept_out_of_the_archive_stay_hidden() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let response = publish_to(&app, "", "tag:beta").await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Members only",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "hide_from_archive": "true",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let archive = get_html(&app, "/issues").await;
+    assert!(archive.contains("Nothing has been published yet."));
+    let id: Uuid = sqlx::query_scalar!(
+        "SELECT newsletter_issue_id FROM newsletter_issues WHERE segment IS NOT NULL"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    for path in [
+        "/issues/newsletter-title".to_string(),
+        format!("/issues/{}", id),
+        "/issues/members-only".to_string(),
+    ] {
+        assert_eq!(get_page(&app, &path).await.status().as_u16(), 404);
+    }
+}
+
+#[tokio::test]
+async fn the_archive_is_paginated() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    for n in 1..=21 {
+        publish(&app, &format!("Issue {}", n), "<p>Body</p>", None).await;
+    }
+
+    let first_page = get_html(&app, "/issues").await;
+    assert_eq!(first_page.matches("<li>").count(), 20);
+    assert!(first_page.contains(r#"<a href="/issues?page=2">Older issues</a>"#));
+    assert!(!first_page.contains("Newer issues"));
+
+    let second_page = get_html(&app, "/issues?page=2").await;
+    assert_eq!(second_page.matches("<li>").count(), 1);
+    assert!(second_page.contains(r#"<a href="/issues?page=1">Newer issues</a>"#));
+    assert!(!second_page.contains("Older issues"));
+
+    for page in ["0", "9223372036854775807"] {
+        let response = get_page(&app, &format!("/issues?page={}", page)).await;
+        assert_eq!(response.status().as_u16(), 400, "{}", page);
+    }
+}
+
+#[tokio::test]
+async fn sent_issues_link_to_their_web_version() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    subscribe_and_confirm(&app, "ursula@gmail.com", None).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    publish(&app, "Big news", "<p>Newsletter body as HTML</p>", None).await;
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let web_url = format!("{}/issues/big-news", app.base_url.0);
+    assert!(body[0]["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains(&format!("View in your browser: {}", web_url)));
+    // Links in the HTML body go through click tracking
+    assert!(body[0]["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains("View in your browser</a>"));
+}
+
+#[tokio::test]
+async fn issues_kept_out_of_the_archive_do_not_link_to_a_web_version() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    subscribe_and_confirm(&app, "ursula@gmail.com", None).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Members only",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "hide_from_archive": "true",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    for part in ["TextBody", "HtmlBody"] {
+        let content = body[0][part].as_str().unwrap();
+        assert!(content.contains("Unsubscribe"), "{}", part);
+        assert!(!content.contains("View in your browser"), "{}", part);
+    }
+}
@@ -0,0 +1,229 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, ConfirmationLinks, TestApp};
+use reqwest::Method;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+pub async fn create_list(app: &TestApp, slug: &str) {
+    let response = app
+        .admin_api(Method::POST, "/lists")
+        .json(&serde_json::json!({ "slug": slug, "name": slug.to_uppercase() }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+async fn subscribe(app: &TestApp, email: &str, list: Option<&str>) -> ConfirmationLinks {
+    let mut body = serde_json::json!({ "name": "Ursula", "email": email });
+    if let Some(list) = list {
+        body["list"] = list.into();
+    }
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions(serde_urlencoded::to_string(body).unwrap())
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(&email_request)
+}
+
+pub async fn subscribe_and_confirm(app: &TestApp, email: &str, list: Option<&str>) {
+    let links = subscribe(app, email, list).await;
+    reqwest::get(links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn membership_status(app: &TestApp, email: &str, slug: &str) -> Option<String> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT m.status
+        FROM list_memberships m
+        JOIN lists l ON l.list_id = m.list_id
+        JOIN subscriptions s ON s.id = m.subscriber_id
+        WHERE s.email = $1 AND l.slug = $2
+        "#,
+        email,
+        slug
+    )
+    .fetch_optional(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+async fn publish(app: &TestApp, lists: &str) -> reqwest::Response {
+    publish_to(app, lists, "").await
+}
+
+pub async fn publish_to(app: &TestApp, lists: &str, segment: &str) -> reqwest::Response {
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "lists": lists,
+        "segment": segment,
+    }))
+    .await
+}
+
+pub async fn recipients(app: &TestApp) -> Vec<String> {
+    sqlx::query_scalar!(
+        r#"SELECT subscriber_email FROM issue_deliveries ORDER BY subscriber_email"#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn admins_can_create_and_list_mailing_lists() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    create_list(&app, "product-updates").await;
+    let duplicate = app
+        .admin_api(Method::POST, "/lists")
+        .json(&serde_json::json!({ "slug": "product-updates", "name": "Again" }))
+        .send()
+        .await
+        .unwrap();
+    let invalid = app
+        .admin_api(Method::POST, "/lists")
+        .json(&serde_json::json!({ "slug": "Product Updates", "name": "Product" }))
+        .send()
+        .await
+        .unwrap();
+    let lists: serde_json::Value = app
+        .admin_api(Method::GET, "/lists")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(duplicate.status().as_u16(), 409);
+    assert_eq!(invalid.status().as_u16(), 400);
+    let slugs: Vec<&str> = lists
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|l| l["slug"].as_str().unwrap())
+        .collect();
+    assert_eq!(slugs, vec!["newsletter", "product-updates"]);
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_list_is_rejected_with_a_400() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_subscriptions("name=Ursula&email=ursula%40gmail.com&list=nope".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let saved = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+}
+
+#[tokio::test]
+async fn each_list_is_confirmed_on_its_own() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_list(&app, "product-updates").await;
+    subscribe_and_confirm(&app, "ursula@gmail.com", None).await;
+
+    // Already confirmed subscribers still confirm the new list
+    let links = subscribe(&app, "ursula@gmail.com", Some("product-updates")).await;
+    assert_eq!(
+        membership_status(&app, "ursula@gmail.com", "product-updates")
+            .await
+            .as_deref(),
+        Some("pending_confirmation")
+    );
+
+    let response = reqwest::get(links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        membership_status(&app, "ursula@gmail.com", "product-updates")
+            .await
+            .as_deref(),
+        Some("confirmed")
+    );
+    assert_eq!(
+        membership_status(&app, "ursula@gmail.com", "newsletter")
+            .await
+            .as_deref(),
+        Some("confirmed")
+    );
+}
+
+#[tokio::test]
+async fn issues_only_reach_confirmed_members_of_the_target_lists() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_list(&app, "product-updates").await;
+    subscribe_and_confirm(&app, "everyone@gmail.com", None).await;
+    subscribe_and_confirm(&app, "product@gmail.com", Some("product-updates")).await;
+    // Confirmed on the default list, still pending on the product list
+    subscribe_and_confirm(&app, "pending@gmail.com", None).await;
+    subscribe(&app, "pending@gmail.com", Some("product-updates")).await;
+
+    let response = publish(&app, "product-updates").await;
+
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    assert_eq!(recipients(&app).await, vec!["product@gmail.com"]);
+}
+
+#[tokio::test]
+async fn publishing_to_several_lists_sends_one_email_per_subscriber() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_list(&app, "product-updates").await;
+    subscribe_and_confirm(&app, "both@gmail.com", None).await;
+    subscribe_and_confirm(&app, "both@gmail.com", Some("product-updates")).await;
+    subscribe_and_confirm(&app, "product@gmail.com", Some("product-updates")).await;
+
+    let response = publish(&app, "newsletter, product-updates").await;
+
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    assert_eq!(
+        recipients(&app).await,
+        vec!["both@gmail.com", "product@gmail.com"]
+    );
+}
+
+#[tokio::test]
+async fn publishing_to_an_unknown_list_is_rejected_with_a_400() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = publish(&app, "newsletter, nope").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let issues = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(issues.is_empty());
+}
@@ -1,8 +1,21 @@
 mod admin_dashboard;
+mod admin_subscribers;
 mod change_password;
+mod drafts;
+mod feeds;
 mod health_check;
 mod helpers;
+mod issues;
+mod lists;
 mod login;
+mod merge_tags;
 mod newsletter;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
+mod suppressions;
+mod tags;
+mod templates;
+mod tracking;
+mod webhooks;
+mod worker_shutdown;
@@ -0,0 +1,118 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::lists::subscribe_and_confirm;
+use reqwest::Method;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn set_attributes(
+    app: &TestApp,
+    email: &str,
+    attributes: serde_json::Value,
+) -> reqwest::Response {
+    let id: Uuid = sqlx::query_scalar!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    app.admin_api(Method::PUT, &format!("/subscribers/{}/attributes", id))
+        .json(&attributes)
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn publish(
+    app: &TestApp,
+    title: &str,
+    html_content: &str,
+    text_content: &str,
+) -> reqwest::Response {
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": title,
+        "text_content": text_content,
+        "html_content": html_content,
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn merge_tags_are_rendered_for_each_subscriber() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    subscribe_and_confirm(&app, "ursula@gmail.com", None).await;
+    let response = set_attributes(
+        &app,
+        "ursula@gmail.com",
+        serde_json::json!({ "company": "Earthsea & co" }),
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = publish(
+        &app,
+        "News for {{ name }}",
+        "<p>Hello {{ name }} at {{ attributes.company }}</p>",
+        "Hello {{ attributes.company }}. Leave: {{ unsubscribe_url }}",
+    )
+    .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let body = &body[0];
+    assert_eq!(body["Subject"], "News for Ursula");
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("<p>Hello Ursula at Earthsea &amp; co</p>"));
+    assert!(body["TextBody"].as_str().unwrap().starts_with(
+        "Hello Earthsea & co. Leave: http://127.0.0.1/subscriptions/unsubscribe?token="
+    ));
+}
+
+#[tokio::test]
+async fn issues_with_invalid_templates_are_rejected_at_publish_time() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    for (title, html_content, text_content) in [
+        ("Title", "<p>Hello {{ nmae }}</p>", "Hello"),
+        ("Title", "<p>Hello</p>", "Hello {{ name "),
+        ("{{ name | shout }}", "<p>Hello</p>", "Hello"),
+    ] {
+        let response = publish(&app, title, html_content, text_content).await;
+        assert_eq!(response.status().as_u16(), 400, "{}", html_content);
+    }
+
+    let issues = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(issues.is_empty());
+}
+
+#[tokio::test]
+async fn attributes_must_be_a_json_object() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    subscribe_and_confirm(&app, "ursula@gmail.com", None).await;
+
+    let response = set_attributes(&app, "ursula@gmail.com", serde_json::json!(["company"])).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
@@ -1,13 +1,15 @@
 use std::time::Duration;
 
-use crate::helpers::{assert_is_redirect_to, spawn_app, ConfirmationLinks, TestApp};
+use crate::helpers::{
+    assert_is_redirect_to, slow_polling_worker_settings, spawn_app, ConfirmationLinks, TestApp,
+};
 use fake::faker::internet::en::SafeEmail;
 use fake::faker::name::en::Name;
 use fake::Fake;
 use wiremock::matchers::{any, method, path};
 use wiremock::{Mock, ResponseTemplate};
 
-async fn create_confirmed_subscriber(app: &TestApp) {
+pub async fn create_confirmed_subscriber(app: &TestApp) {
     let confirmation_links = create_unconfirmed_subscriber(app).await;
 
     reqwest::get(confirmation_links.html)
@@ -31,10 +33,11 @@ async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
         .mount_as_scoped(&app.email_server)
         .await;
 
-    app.post_subscriptions(body.into())
+    app.post_subscriptions(body)
         .await
         .error_for_status()
         .unwrap();
+    app.dispatch_all_pending_emails().await;
 
     let email_request = app
         .email_server
@@ -75,7 +78,7 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     create_confirmed_subscriber(&app).await;
     app.test_user.login(&app).await;
 
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
         .respond_with(ResponseTemplate::new(200))
         .expect(1)
@@ -92,6 +95,12 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     app.post_publish_newsletter(&newsletter_request_body).await;
 
     app.dispatch_all_pending_emails().await;
+
+    let issue = sqlx::query!("SELECT status FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.status, "sent");
 }
 
 #[tokio::test]
@@ -115,7 +124,7 @@ async fn newsletter_creation_is_idempotent() {
     create_confirmed_subscriber(&app).await;
     app.test_user.login(&app).await;
 
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
         .respond_with(ResponseTemplate::new(200))
         .expect(1)
@@ -150,7 +159,7 @@ async fn concurrent_form_submission_is_handled_gracefully() {
     create_confirmed_subscriber(&app).await;
     app.test_user.login(&app).await;
 
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
         .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(2)))
         .expect(1)
@@ -176,3 +185,614 @@ async fn concurrent_form_submission_is_handled_gracefully() {
 
     app.dispatch_all_pending_emails().await;
 }
+
+#[tokio::test]
+async fn transient_delivery_failures_are_retried_later() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text.",
+        "html_content": "<p>Newsletter body as HTML.</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+
+    app.dispatch_all_pending_emails().await;
+
+    let task = sqlx::query!(
+        "SELECT n_retries, execute_after > now() AS \"is_delayed!\" FROM issue_delivery_queue"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("The failed task should still be queued");
+    assert_eq!(task.n_retries, 1);
+    assert!(task.is_delayed);
+}
+
+#[tokio::test]
+async fn tasks_are_moved_to_the_dead_letter_table_after_running_out_of_retries() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text.",
+        "html_content": "<p>Newsletter body as HTML.</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+
+    for _ in 0..20 {
+        app.dispatch_all_pending_emails().await;
+        // Skip the backoff so the next attempt happens right away
+        let rescheduled = sqlx::query!("UPDATE issue_delivery_queue SET execute_after = now()")
+            .execute(&app.db_pool)
+            .await
+            .unwrap()
+            .rows_affected();
+        if rescheduled == 0 {
+            break;
+        }
+    }
+
+    let failure = sqlx::query!("SELECT n_retries, last_error FROM issue_delivery_failures")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The failed task should be in the dead-letter table");
+    assert!(failure.n_retries > 1);
+    assert!(!failure.last_error.is_empty());
+
+    let delivery = sqlx::query!("SELECT status, n_attempts FROM issue_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(delivery.status, "failed");
+    assert_eq!(delivery.n_attempts, failure.n_retries);
+
+    let queued = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.count, 0);
+}
+
+#[tokio::test]
+async fn delivery_report_shows_the_outcome_of_each_delivery() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!([{
+                "ErrorCode": 0,
+                "Message": "OK",
+                "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817"
+            }])),
+        )
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text.",
+        "html_content": "<p>Newsletter body as HTML.</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+
+    let issue = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let html_page = app
+        .get_newsletter_report_html(issue.newsletter_issue_id)
+        .await;
+    assert!(html_page.contains("pending</a>: 1"));
+
+    app.dispatch_all_pending_emails().await;
+
+    let delivery =
+        sqlx::query!("SELECT status, n_attempts, provider_message_id FROM issue_deliveries")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(delivery.status, "sent");
+    assert_eq!(delivery.n_attempts, 1);
+    assert_eq!(
+        delivery.provider_message_id.as_deref(),
+        Some("b7bc2f4a-e38e-4336-af7d-e6c392c2f817")
+    );
+
+    let html_page = app
+        .get_newsletter_report_html(issue.newsletter_issue_id)
+        .await;
+    assert!(html_page.contains("sent</a>: 1"));
+    assert!(html_page.contains("pending</a>: 0"));
+}
+
+#[tokio::test]
+async fn deliveries_to_many_subscribers_are_sent_in_a_single_batch() {
+    let app = spawn_app().await;
+    for _ in 0..3 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text.",
+        "html_content": "<p>Newsletter body as HTML.</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body.as_array().unwrap().len(), 3);
+    let sent =
+        sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_deliveries WHERE status = 'sent'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(sent.count, 3);
+}
+
+#[tokio::test]
+async fn rejected_recipients_in_a_batch_are_retried_on_their_own() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+            { "ErrorCode": 0, "Message": "OK", "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817" },
+            { "ErrorCode": 406, "Message": "You tried to send to a recipient that has been marked as inactive." }
+        ])))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text.",
+        "html_content": "<p>Newsletter body as HTML.</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let rejected_email = body[1]["To"].as_str().unwrap();
+
+    let queued = sqlx::query!("SELECT subscriber_email, n_retries FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.len(), 1);
+    assert_eq!(queued[0].subscriber_email, rejected_email);
+    assert_eq!(queued[0].n_retries, 1);
+
+    let deliveries = sqlx::query!(
+        "SELECT subscriber_email, status, last_error FROM issue_deliveries ORDER BY status"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(deliveries[0].status, "pending");
+    assert_eq!(deliveries[0].subscriber_email, rejected_email);
+    assert!(deliveries[0]
+        .last_error
+        .as_deref()
+        .unwrap()
+        .contains("marked as inactive"));
+    assert_eq!(deliveries[1].status, "sent");
+}
+
+#[tokio::test]
+async fn deliveries_are_paused_when_the_provider_rate_limits_us() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "60"))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text.",
+        "html_content": "<p>Newsletter body as HTML.</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert!(app.rate_limiter.is_paused());
+    // Throttling doesn't use up one of the task's retries
+    let task = sqlx::query!(
+        "SELECT n_retries, execute_after > now() + interval '50 seconds' AS \"is_delayed!\" \
+        FROM issue_delivery_queue"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("The throttled task should still be queued");
+    assert_eq!(task.n_retries, 0);
+    assert!(task.is_delayed);
+    let delivery = sqlx::query!("SELECT status, n_attempts FROM issue_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(delivery.status, "pending");
+    assert_eq!(delivery.n_attempts, 0);
+}
+
+#[tokio::test]
+async fn idle_workers_are_woken_up_as_soon_as_an_issue_is_published() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Polling is slow enough that only a notification explains a prompt delivery
+    let workers = app.spawn_workers(slow_polling_worker_settings());
+    // Let the workers find the queue empty and go idle
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text.",
+        "html_content": "<p>Newsletter body as HTML.</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+
+    let mut status = String::new();
+    for _ in 0..50 {
+        status = sqlx::query!("SELECT status FROM newsletter_issues")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .status;
+        if status == "sent" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(status, "sent");
+    workers.stop(Duration::from_secs(2)).await.unwrap();
+}
+
+fn scheduled_newsletter_request_body(
+    scheduled_for: chrono::DateTime<chrono::Utc>,
+) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text.",
+        "html_content": "<p>Newsletter body as HTML.</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "scheduled_for": scheduled_for.to_rfc3339()
+    })
+}
+
+async fn get_newsletter_issue_id(app: &TestApp) -> uuid::Uuid {
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+#[tokio::test]
+async fn scheduled_newsletters_are_not_delivered_before_their_publication_time() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let scheduled_for = chrono::Utc::now() + chrono::Duration::days(1);
+    let response = app
+        .post_publish_newsletter_json(&scheduled_newsletter_request_body(scheduled_for))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("The newsletter issue has been scheduled for"));
+
+    app.dispatch_all_pending_emails().await;
+
+    // Recipients are only picked once the publication time has passed
+    let queued = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.count, 0);
+}
+
+#[tokio::test]
+async fn scheduled_newsletters_are_delivered_once_their_publication_time_has_passed() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let scheduled_for = chrono::Utc::now() + chrono::Duration::days(1);
+    app.post_publish_newsletter_json(&scheduled_newsletter_request_body(scheduled_for))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    sqlx::query!("UPDATE newsletter_issues SET scheduled_for = now() - interval '1 second'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn scheduled_newsletters_reach_whoever_is_subscribed_when_they_are_sent() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let scheduled_for = chrono::Utc::now() + chrono::Duration::days(1);
+    app.post_publish_newsletter_json(&scheduled_newsletter_request_body(scheduled_for))
+        .await;
+    // Confirms after the issue was scheduled
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    sqlx::query!("UPDATE newsletter_issues SET scheduled_for = now() - interval '1 second'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let issue = sqlx::query!("SELECT status FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.status, "sent");
+}
+
+#[tokio::test]
+async fn scheduled_newsletters_can_be_cancelled() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let scheduled_for = chrono::Utc::now() + chrono::Duration::days(1);
+    app.post_publish_newsletter_json(&scheduled_newsletter_request_body(scheduled_for))
+        .await;
+    let newsletter_issue_id = get_newsletter_issue_id(&app).await;
+
+    let response = app.post_cancel_newsletter(newsletter_issue_id).await;
+    let draft_path = format!("/admin/newsletters/drafts/{}", newsletter_issue_id);
+    assert_is_redirect_to(&response, &draft_path);
+
+    let html_page = app.get_admin_html(&draft_path).await;
+    assert!(html_page.contains(
+        "<p><i>The scheduled newsletter issue has been cancelled and moved back to drafts.</i></p>"
+    ));
+
+    let issue = sqlx::query!("SELECT status, scheduled_for FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.status, "draft");
+    assert!(issue.scheduled_for.is_none());
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn cancelled_newsletters_get_their_slug_back_when_published_again() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let scheduled_for = chrono::Utc::now() + chrono::Duration::days(1);
+    app.post_publish_newsletter_json(&scheduled_newsletter_request_body(scheduled_for))
+        .await;
+    let newsletter_issue_id = get_newsletter_issue_id(&app).await;
+    app.post_cancel_newsletter(newsletter_issue_id).await;
+
+    let issue = sqlx::query!("SELECT slug FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(issue.slug.is_none());
+
+    let response = app
+        .post_admin_form(
+            &format!("/admin/newsletters/drafts/{}/publish", newsletter_issue_id),
+            &serde_json::json!({
+                "idempotency_key": uuid::Uuid::new_v4().to_string(),
+                "scheduled_for": ""
+            }),
+        )
+        .await;
+    assert_is_redirect_to(
+        &response,
+        &format!("/admin/newsletters/{}", newsletter_issue_id),
+    );
+    let issue = sqlx::query!("SELECT slug FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.slug.as_deref(), Some("newsletter-title"));
+}
+
+#[tokio::test]
+async fn newsletters_that_already_went_out_cannot_be_cancelled() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text.",
+        "html_content": "<p>Newsletter body as HTML.</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    let newsletter_issue_id = get_newsletter_issue_id(&app).await;
+
+    let response = app.post_cancel_newsletter(newsletter_issue_id).await;
+    assert_is_redirect_to(
+        &response,
+        &format!("/admin/newsletters/{}", newsletter_issue_id),
+    );
+
+    let issues = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.count, 1);
+}
+
+#[tokio::test]
+async fn scheduled_newsletters_can_be_rescheduled() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let scheduled_for = chrono::Utc::now() + chrono::Duration::days(1);
+    app.post_publish_newsletter_json(&scheduled_newsletter_request_body(scheduled_for))
+        .await;
+    let newsletter_issue_id = get_newsletter_issue_id(&app).await;
+
+    let response = app
+        .post_reschedule_newsletter(
+            newsletter_issue_id,
+            &serde_json::json!({ "scheduled_for": "2999-01-01T09:00" }),
+        )
+        .await;
+    assert_is_redirect_to(
+        &response,
+        &format!("/admin/newsletters/{}", newsletter_issue_id),
+    );
+
+    let issue = sqlx::query!("SELECT scheduled_for FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        issue.scheduled_for.unwrap().to_rfc3339(),
+        "2999-01-01T09:00:00+00:00"
+    );
+}
+
+#[tokio::test]
+async fn tasks_claimed_by_a_worker_are_not_sent_by_another() {
+    let app = spawn_app().await;
+    for _ in 0..3 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(1)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text.",
+        "html_content": "<p>Newsletter body as HTML.</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    }))
+    .await;
+
+    let workers = app.spawn_workers(slow_polling_worker_settings());
+    while !app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .any(|request| request.url.path() == "/email/batch")
+    {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    // The batch is in flight, its tasks are no longer due
+    app.dispatch_all_pending_emails().await;
+    workers.stop(Duration::from_secs(5)).await.unwrap();
+
+    let sent =
+        sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_deliveries WHERE status = 'sent'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(sent.count, 3);
+}
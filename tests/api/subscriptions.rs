@@ -1,4 +1,6 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{slow_polling_worker_settings, spawn_app};
+use server_scaffold::domain::UnsubscribeToken;
+use std::time::Duration;
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
 
@@ -100,6 +102,7 @@ async fn subscribe_sends_a_confirmation_email_for_valid_data() {
         .await;
 
     let response = app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
     assert_eq!(200, response.status().as_u16());
 }
 
@@ -116,8 +119,9 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
         .await;
 
     app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = app.get_confirmation_links(&email_request);
+    let confirmation_links = app.get_confirmation_links(email_request);
     assert_eq!(confirmation_links.html, confirmation_links.plain_text);
 }
 
@@ -134,3 +138,235 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
     let response = app.post_subscriptions(body.into()).await;
     assert_eq!(response.status().as_u16(), 500);
 }
+
+#[tokio::test]
+async fn subscribing_twice_while_pending_sends_a_fresh_confirmation_link() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let first_response = app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+    let second_response = app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(first_response.status().as_u16(), 200);
+    assert_eq!(second_response.status().as_u16(), 200);
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let first_link = app.get_confirmation_links(&email_requests[0]).html;
+    let second_link = app.get_confirmation_links(&email_requests[1]).html;
+    assert_ne!(first_link, second_link);
+
+    let subscribers = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscribers.len(), 1);
+    assert_eq!(subscribers[0].status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn subscribing_an_already_confirmed_address_returns_a_200_without_sending_an_email() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = app.get_confirmation_links(email_request).html;
+    reqwest::get(confirmation_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let response = app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    // Mock verifies on Drop that no second confirmation email went out
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_can_subscribe_again() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    reqwest::get(app.get_confirmation_links(email_request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let subscriber_id = sqlx::query_scalar!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let token = UnsubscribeToken::generate(subscriber_id, &app.hmac_secret.0);
+    app.api_client
+        .post(format!(
+            "{}/subscriptions/unsubscribe?token={}",
+            app.address,
+            token.as_ref()
+        ))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let response = app.post_subscriptions(body.into()).await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    reqwest::get(app.get_confirmation_links(email_request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let status = sqlx::query_scalar!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(status, "confirmed");
+}
+
+#[tokio::test]
+async fn concurrent_subscriptions_for_a_new_address_both_succeed() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    let (first, second) = tokio::join!(
+        app.post_subscriptions(body.into()),
+        app.post_subscriptions(body.into())
+    );
+
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 200);
+    let n_subscribers = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_subscribers, 1);
+}
+
+#[tokio::test]
+async fn subscribe_succeeds_even_if_the_email_provider_is_down() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(body.into()).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.dispatch_all_pending_emails().await;
+
+    // The confirmation email stays in the outbox, to be retried later
+    let outbox = sqlx::query!("SELECT recipient, n_retries FROM email_outbox")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(outbox.recipient, "ursula_le_guin@gmail.com");
+    assert_eq!(outbox.n_retries, 1);
+}
+
+#[tokio::test]
+async fn confirmation_emails_that_keep_failing_are_kept_as_failures() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+    // The last attempt before giving up
+    sqlx::query!("UPDATE email_outbox SET n_retries = 7")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let n_queued = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM email_outbox"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 0);
+    let failure =
+        sqlx::query!("SELECT recipient, n_retries, last_error FROM email_outbox_failures")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(failure.recipient, "ursula_le_guin@gmail.com");
+    assert_eq!(failure.n_retries, 8);
+    assert!(!failure.last_error.is_empty());
+}
+
+#[tokio::test]
+async fn the_confirmation_email_is_sent_by_the_background_workers() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let workers = app.spawn_workers(slow_polling_worker_settings());
+    // Let the workers find the outbox empty and go idle
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    app.post_subscriptions(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let mut n_queued = 1;
+    for _ in 0..50 {
+        n_queued = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM email_outbox"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .count;
+        if n_queued == 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(n_queued, 0);
+    workers.stop(Duration::from_secs(2)).await.unwrap();
+}
@@ -0,0 +1,193 @@
+use crate::helpers::{spawn_app, TestApp};
+use crate::newsletter::create_confirmed_subscriber;
+use server_scaffold::domain::UnsubscribeToken;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn unsubscribe_link(app: &TestApp) -> reqwest::Url {
+    let subscriber = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let token = UnsubscribeToken::generate(subscriber.id, &app.hmac_secret.0);
+    reqwest::Url::parse(&format!(
+        "{}/subscriptions/unsubscribe?token={}",
+        app.address,
+        token.as_ref()
+    ))
+    .unwrap()
+}
+
+async fn publish_newsletter(app: &TestApp) {
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text.",
+        "html_content": "<p>Newsletter body as HTML.</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    }))
+    .await;
+}
+
+async fn subscriber_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status
+}
+
+#[tokio::test]
+async fn issues_carry_an_unsubscribe_footer_and_list_unsubscribe_headers() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let body = &body[0];
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains("/subscriptions/unsubscribe?token="));
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("/subscriptions/unsubscribe?token="));
+    let headers = body["Headers"].as_array().unwrap();
+    assert_eq!(headers[0]["Name"], "List-Unsubscribe");
+    assert!(headers[0]["Value"]
+        .as_str()
+        .unwrap()
+        .starts_with("<http://127.0.0.1/subscriptions/unsubscribe?token="));
+    assert_eq!(headers[1]["Name"], "List-Unsubscribe-Post");
+    assert_eq!(headers[1]["Value"], "List-Unsubscribe=One-Click");
+}
+
+#[tokio::test]
+async fn one_click_unsubscribe_marks_the_subscriber_as_unsubscribed() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let link = unsubscribe_link(&app).await;
+
+    let response = app
+        .api_client
+        .post(link)
+        .form(&[("List-Unsubscribe", "One-Click")])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "unsubscribed");
+}
+
+#[tokio::test]
+async fn opening_the_unsubscribe_link_asks_for_confirmation() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let link = unsubscribe_link(&app).await;
+
+    let response = app.api_client.get(link).send().await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("<form"));
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+}
+
+#[tokio::test]
+async fn unsubscribe_rejects_invalid_tokens() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let response = app
+        .api_client
+        .post(format!(
+            "{}/subscriptions/unsubscribe?token=forged",
+            app.address
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+}
+
+#[tokio::test]
+async fn queued_issues_are_not_delivered_after_unsubscribing() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    publish_newsletter(&app).await;
+    let link = unsubscribe_link(&app).await;
+    app.api_client.post(link).send().await.unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let delivery = sqlx::query!("SELECT status, n_attempts FROM issue_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(delivery.status, "skipped");
+    assert_eq!(delivery.n_attempts, 0);
+}
+
+#[tokio::test]
+async fn blocked_subscribers_stay_blocked_after_unsubscribing_and_subscribing_again() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'blocked'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let link = unsubscribe_link(&app).await;
+    let response = app.api_client.post(link).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "blocked");
+
+    let body = serde_urlencoded::to_string(serde_json::json!({
+        "name": "le guin",
+        "email": email
+    }))
+    .unwrap();
+    let response = app.post_subscriptions(body).await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "blocked");
+}
@@ -0,0 +1,222 @@
+use crate::admin_subscribers::import_csv;
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::lists::{publish_to, recipients, subscribe_and_confirm};
+use reqwest::Method;
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn suppress(app: &TestApp, target: &str) -> reqwest::Response {
+    app.admin_api(Method::POST, "/suppressions")
+        .json(&serde_json::json!({ "target": target, "reason": "Asked by phone" }))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn suppressions(app: &TestApp) -> serde_json::Value {
+    app.admin_api(Method::GET, "/suppressions")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_suppressions() {
+    let app = spawn_app().await;
+
+    let response = suppress(&app, "ursula@gmail.com").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn admins_can_add_list_and_remove_suppressions() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = suppress(&app, "Ursula@Gmail.com").await;
+    assert_eq!(response.status().as_u16(), 201);
+    let created: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(created["email"], "ursula@gmail.com");
+    assert_eq!(created["source"], "manual");
+    assert_eq!(
+        suppress(&app, "ursula@gmail.com").await.status().as_u16(),
+        409
+    );
+    assert_eq!(
+        suppress(&app, "mailinator.com").await.status().as_u16(),
+        201
+    );
+    for target in ["ursula@", "localhost", ""] {
+        assert_eq!(suppress(&app, target).await.status().as_u16(), 400);
+    }
+
+    let page = suppressions(&app).await;
+    assert_eq!(page["total"], 2);
+    assert_eq!(page["suppressions"][0]["domain"], "mailinator.com");
+
+    let id = created["suppression_id"].as_str().unwrap();
+    let delete = || async {
+        app.admin_api(Method::DELETE, &format!("/suppressions/{}", id))
+            .send()
+            .await
+            .unwrap()
+    };
+    assert_eq!(delete().await.status().as_u16(), 204);
+    assert_eq!(delete().await.status().as_u16(), 404);
+    assert_eq!(suppressions(&app).await["total"], 1);
+}
+
+#[tokio::test]
+async fn listing_suppressions_rejects_invalid_pages() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    for query in [
+        "page=0",
+        "page=9223372036854775807",
+        "page_size=0",
+        "page_size=1000",
+    ] {
+        let response = app
+            .admin_api(Method::GET, &format!("/suppressions?{}", query))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 400, "{}", query);
+    }
+}
+
+#[tokio::test]
+async fn suppressed_addresses_get_no_confirmation_email() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    suppress(&app, "gmail.com").await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40GMAIL.com".into())
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn queued_emails_to_addresses_suppressed_since_are_dropped() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    suppress(&app, "ursula_le_guin@gmail.com").await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    let outbox = sqlx::query!("SELECT recipient FROM email_outbox")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(outbox.is_empty());
+}
+
+#[tokio::test]
+async fn suppressed_subscribers_are_left_out_of_issues() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    subscribe_and_confirm(&app, "ursula@gmail.com", None).await;
+    subscribe_and_confirm(&app, "ged@earthsea.org", None).await;
+    subscribe_and_confirm(&app, "tenar@earthsea.org", None).await;
+
+    suppress(&app, "ursula@gmail.com").await;
+    publish_to(&app, "", "").await;
+    assert_eq!(
+        recipients(&app).await,
+        vec!["ged@earthsea.org", "tenar@earthsea.org"]
+    );
+
+    // Suppressed after the issue was published, before it was sent
+    suppress(&app, "earthsea.org").await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    let statuses = sqlx::query_scalar!("SELECT status FROM issue_deliveries")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(statuses, vec!["skipped", "skipped"]);
+}
+
+#[tokio::test]
+async fn suppressions_outlive_the_subscriber() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    subscribe_and_confirm(&app, "ursula@gmail.com", None).await;
+    suppress(&app, "ursula@gmail.com").await;
+    let id: Uuid = sqlx::query_scalar!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app
+        .admin_api(Method::DELETE, &format!("/subscribers/{}", id))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 204);
+    assert_eq!(suppressions(&app).await["total"], 1);
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=Ursula&email=ursula%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn imported_bounced_and_complained_rows_are_suppressed() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let csv = "\
+email,name,status
+ursula@gmail.com,Ursula,bounced
+ged@earthsea.org,Ged,complained
+tenar@earthsea.org,Tenar,confirmed
+";
+
+    let response = import_csv(&app, "", csv).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let imported = sqlx::query!("SELECT email, reason, source FROM suppressions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(imported.len(), 2);
+    assert_eq!(imported[0].email.as_deref(), Some("ged@earthsea.org"));
+    assert_eq!(imported[0].reason, "Imported as complained");
+    assert_eq!(imported[1].source, "import");
+}
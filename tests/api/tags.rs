@@ -0,0 +1,132 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::lists::{publish_to, recipients, subscribe_and_confirm};
+use reqwest::Method;
+use uuid::Uuid;
+
+async fn subscriber_id(app: &TestApp, email: &str) -> Uuid {
+    sqlx::query_scalar!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+async fn set_tags(app: &TestApp, email: &str, tags: &[&str]) -> reqwest::Response {
+    let path = format!("/subscribers/{}/tags", subscriber_id(app, email).await);
+    app.admin_api(Method::PUT, &path)
+        .json(&serde_json::json!({ "tags": tags }))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn count_recipients(app: &TestApp, segment: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}/admin/newsletters/recipients", app.address))
+        .query(&[("segment", segment)])
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn admins_can_replace_a_subscribers_tags() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    subscribe_and_confirm(&app, "ursula@gmail.com", None).await;
+
+    set_tags(&app, "ursula@gmail.com", &["beta", "eu"]).await;
+    let response = set_tags(&app, "ursula@gmail.com", &["paying", "beta", "beta"]).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let path = format!(
+        "/subscribers/{}/tags",
+        subscriber_id(&app, "ursula@gmail.com").await
+    );
+    let tags: serde_json::Value = app
+        .admin_api(Method::GET, &path)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(tags, serde_json::json!({ "tags": ["beta", "paying"] }));
+}
+
+#[tokio::test]
+async fn invalid_tags_and_unknown_subscribers_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    subscribe_and_confirm(&app, "ursula@gmail.com", None).await;
+
+    let invalid = set_tags(&app, "ursula@gmail.com", &["Beta Testers"]).await;
+    let unknown = app
+        .admin_api(
+            Method::PUT,
+            &format!("/subscribers/{}/tags", Uuid::new_v4()),
+        )
+        .json(&serde_json::json!({ "tags": ["beta"] }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(invalid.status().as_u16(), 400);
+    assert_eq!(unknown.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn issues_published_to_a_segment_only_reach_matching_subscribers() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    for (email, tags) in [
+        ("beta@gmail.com", vec!["beta"]),
+        ("churned@gmail.com", vec!["beta", "churned"]),
+        ("plain@gmail.com", vec![]),
+    ] {
+        subscribe_and_confirm(&app, email, None).await;
+        set_tags(&app, email, &tags).await;
+    }
+
+    let response = publish_to(&app, "", "tag:beta AND NOT tag:churned").await;
+
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    assert_eq!(recipients(&app).await, vec!["beta@gmail.com"]);
+    let segment = sqlx::query_scalar!("SELECT segment FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(segment.as_deref(), Some("tag:beta AND NOT tag:churned"));
+}
+
+#[tokio::test]
+async fn the_recipient_count_follows_the_segment() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    for (email, tags) in [
+        ("beta@gmail.com", vec!["beta"]),
+        ("eu@gmail.com", vec!["eu"]),
+        ("plain@gmail.com", vec![]),
+    ] {
+        subscribe_and_confirm(&app, email, None).await;
+        set_tags(&app, email, &tags).await;
+    }
+
+    for (segment, expected) in [("", 3), ("tag:beta OR tag:eu", 2), ("NOT tag:beta", 2)] {
+        let response = count_recipients(&app, segment).await;
+        assert_eq!(response.status().as_u16(), 200);
+        let count: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(count["recipients"], expected, "{}", segment);
+    }
+}
+
+#[tokio::test]
+async fn invalid_segments_are_rejected_with_a_400() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let count = count_recipients(&app, "tag:beta AND").await;
+    let publish = publish_to(&app, "", "beta").await;
+
+    assert_eq!(count.status().as_u16(), 400);
+    assert_eq!(publish.status().as_u16(), 400);
+}
@@ -0,0 +1,256 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::lists::subscribe_and_confirm;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn save_layout(
+    app: &TestApp,
+    name: &str,
+    subject: &str,
+    html_content: &str,
+    text_content: &str,
+) -> reqwest::Response {
+    app.post_admin_form(
+        &format!("/admin/templates/{}", name),
+        &serde_json::json!({
+            "subject": subject,
+            "html_content": html_content,
+            "text_content": text_content,
+        }),
+    )
+    .await
+}
+
+async fn last_email(app: &TestApp) -> serde_json::Value {
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    serde_json::from_slice(&email_request.body).unwrap()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_layouts() {
+    let app = spawn_app().await;
+
+    let response = save_layout(&app, "confirmation", "Hi", "Hi", "Hi").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_saved_confirmation_layout_is_used_when_subscribing() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let response = save_layout(
+        &app,
+        "confirmation",
+        "Confirm your {{ list_name }} subscription",
+        "<p>Hi {{ name }}, <a href=\"{{ confirmation_link }}\">confirm</a></p>",
+        "Hi {{ name }}, confirm at {{ confirmation_link }}",
+    )
+    .await;
+    assert_is_redirect_to(&response, "/admin/templates/confirmation");
+    let html_page = app.get_admin_html("/admin/templates/confirmation").await;
+    assert!(html_page.contains("<p><i>The layout has been saved.</i></p>"));
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let body = last_email(&app).await;
+    assert_eq!(body["Subject"], "Confirm your Newsletter subscription");
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("<p>Hi le guin, <a href=\"http://127.0.0.1/subscriptions/confirm?"));
+    let links = app.get_confirmation_links(&app.email_server.received_requests().await.unwrap()[0]);
+    assert_eq!(links.html, links.plain_text);
+}
+
+#[tokio::test]
+async fn invalid_layouts_are_rejected_and_kept_in_the_form() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    for (html_content, error) in [
+        ("{{ confirmation_link ", "not a valid template"),
+        (
+            "{{ unsubscribe_url }}",
+            "`unsubscribe_url`, which is not a merge tag",
+        ),
+    ] {
+        let response = save_layout(&app, "confirmation", "Hi", html_content, "Hi").await;
+
+        assert_eq!(response.status().as_u16(), 400);
+        let html_page = response.text().await.unwrap();
+        assert!(html_page.contains(error), "{}", html_page);
+        assert!(html_page.contains(&htmlescape::encode_minimal(html_content)));
+    }
+    let saved = sqlx::query!("SELECT name FROM templates")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_empty());
+}
+
+#[tokio::test]
+async fn layouts_without_their_required_link_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    for (name, html_content, text_content, error) in [
+        (
+            "issue",
+            "{{ content }}",
+            "{{ content }}",
+            "The HTML content must use `{{ unsubscribe_url }}`.",
+        ),
+        (
+            "confirmation",
+            r#"<a href="{{ confirmation_link }}">Confirm</a>"#,
+            "Welcome!",
+            "The text content must use `{{ confirmation_link }}`.",
+        ),
+    ] {
+        let response = save_layout(&app, name, "Hi", html_content, text_content).await;
+
+        assert_eq!(response.status().as_u16(), 400);
+        let html_page = response.text().await.unwrap();
+        assert!(html_page.contains(error), "{}", html_page);
+    }
+    let saved = sqlx::query!("SELECT name FROM templates")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_empty());
+}
+
+#[tokio::test]
+async fn unknown_layouts_are_not_found() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = save_layout(&app, "goodbye", "Hi", "Hi", "Hi").await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn a_welcome_email_is_sent_after_confirming_once_there_is_a_layout() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    // No welcome email without a layout
+    subscribe_and_confirm(&app, "ursula@gmail.com", None).await;
+    app.dispatch_all_pending_emails().await;
+    assert_eq!(app.email_server.received_requests().await.unwrap().len(), 1);
+
+    let response = save_layout(
+        &app,
+        "welcome",
+        "Welcome to {{ list_name }}",
+        "<p>Glad to have you, {{ name }}!</p>",
+        "Glad to have you, {{ name }}!",
+    )
+    .await;
+    assert_is_redirect_to(&response, "/admin/templates/welcome");
+    subscribe_and_confirm(&app, "ged@gmail.com", None).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    let body = last_email(&app).await;
+    assert_eq!(body["To"], "ged@gmail.com");
+    assert_eq!(body["Subject"], "Welcome to Newsletter");
+    assert_eq!(body["HtmlBody"], "<p>Glad to have you, Ursula!</p>");
+}
+
+#[tokio::test]
+async fn the_issue_layout_wraps_every_delivery_until_it_is_reset() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    subscribe_and_confirm(&app, "ursula@gmail.com", None).await;
+    let response = save_layout(
+        &app,
+        "issue",
+        "[Weekly] {{ title }}",
+        "<main>{{ content }}</main><a href=\"{{ unsubscribe_url }}\">Leave</a>",
+        "{{ content }}\n--\nLeave: {{ unsubscribe_url }}",
+    )
+    .await;
+    assert_is_redirect_to(&response, "/admin/templates/issue");
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    let publish = || async {
+        app.post_publish_newsletter(&serde_json::json!({
+            "title": "Hello {{ name }}",
+            "text_content": "Plain & simple",
+            "html_content": "<p>Plain &amp; simple</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await
+    };
+    assert_is_redirect_to(&publish().await, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    let body = &last_email(&app).await[0];
+    assert_eq!(body["Subject"], "[Weekly] Hello Ursula");
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.starts_with(
+        "<main><p>Plain &amp; simple</p></main><a href=\"http://127.0.0.1/subscriptions/unsubscribe?"
+    ));
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("Plain & simple\n--\nLeave: http://127.0.0.1/subscriptions/unsubscribe?"));
+
+    let response = app
+        .post_admin_form("/admin/templates/issue/reset", &serde_json::json!({}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/templates/issue");
+    assert_is_redirect_to(&publish().await, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    let body = &last_email(&app).await[0];
+    assert_eq!(body["Subject"], "Hello Ursula");
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("<p>Plain &amp; simple</p><p><a href="));
+}
+
+#[tokio::test]
+async fn the_layouts_page_lists_every_layout() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let html_page = app.get_admin_html("/admin/templates").await;
+
+    assert!(
+        html_page.contains(r#"<a href="/admin/templates/confirmation">confirmation</a> (default)"#)
+    );
+    assert!(html_page.contains(r#"<a href="/admin/templates/welcome">welcome</a> (not sent)"#));
+    assert!(html_page.contains(r#"<a href="/admin/templates/issue">issue</a> (default)"#));
+}
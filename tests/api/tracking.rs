@@ -0,0 +1,137 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::lists::subscribe_and_confirm;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const HTML_CONTENT: &str =
+    r#"<p>Read <a href="https://example.com/post?a=1&amp;b=2">the post</a></p>"#;
+
+async fn publish(app: &TestApp, disable_tracking: Option<&str>) -> Uuid {
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let mut body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": HTML_CONTENT,
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    if let Some(disable_tracking) = disable_tracking {
+        body["disable_tracking"] = disable_tracking.into();
+    }
+    let response = app.post_publish_newsletter(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+    sqlx::query_scalar!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+async fn sent_html(app: &TestApp) -> String {
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    body[0]["HtmlBody"].as_str().unwrap().to_string()
+}
+
+// The first tracking URL in the HTML under `prefix`, pointed at the test server
+fn tracking_url(app: &TestApp, html: &str, prefix: &str) -> String {
+    let start = html.find(prefix).expect("No tracking URL in the email");
+    let end = start + html[start..].find('"').unwrap();
+    format!("{}{}", app.address, &html[start..end])
+}
+
+#[tokio::test]
+async fn links_in_issues_are_tracked_and_redirect_to_their_target() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    subscribe_and_confirm(&app, "ursula@gmail.com", None).await;
+    let issue_id = publish(&app, None).await;
+
+    let html = sent_html(&app).await;
+    assert!(!html.contains(r#"href="https://example.com/post"#));
+    let click_url = tracking_url(&app, &html, "/t/c/");
+    for _ in 0..2 {
+        let response = app.api_client.get(&click_url).send().await.unwrap();
+        assert_eq!(response.status().as_u16(), 302);
+        assert_eq!(
+            response.headers().get("Location").unwrap(),
+            "https://example.com/post?a=1&b=2"
+        );
+    }
+
+    let report = app.get_newsletter_report_html(issue_id).await;
+    assert!(report
+        .contains("<tr><td>https://example.com/post?a=1&amp;b=2</td><td>1</td><td>2</td></tr>"));
+}
+
+#[tokio::test]
+async fn the_tracking_pixel_records_unique_opens() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    subscribe_and_confirm(&app, "ursula@gmail.com", None).await;
+    let issue_id = publish(&app, None).await;
+
+    let pixel_url = tracking_url(&app, &sent_html(&app).await, "/t/o/");
+    for _ in 0..3 {
+        let response = app.api_client.get(&pixel_url).send().await.unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(response.headers().get("Content-Type").unwrap(), "image/gif");
+        assert_eq!(response.headers().get("Cache-Control").unwrap(), "no-store");
+    }
+
+    let report = app.get_newsletter_report_html(issue_id).await;
+    assert!(report.contains("<p>Unique opens: 1</p>"));
+}
+
+#[tokio::test]
+async fn tampered_tracking_links_are_not_followed() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    subscribe_and_confirm(&app, "ursula@gmail.com", None).await;
+    publish(&app, None).await;
+
+    let click_url = tracking_url(&app, &sent_html(&app).await, "/t/c/");
+    let pixel_url = tracking_url(&app, &sent_html(&app).await, "/t/o/");
+    let tampered = format!("{}A", click_url);
+    // A valid open token is not a link to follow
+    let open_token = pixel_url
+        .trim_start_matches(&format!("{}/t/o/", app.address))
+        .trim_end_matches(".gif");
+    let open_as_click = format!("{}/t/c/{}", app.address, open_token);
+    for url in [tampered.as_str(), open_as_click.as_str()] {
+        let response = app.api_client.get(url).send().await.unwrap();
+        assert_eq!(response.status().as_u16(), 404);
+        assert!(response.headers().get("Location").is_none());
+    }
+
+    let n_events = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM tracking_events"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_events, 0);
+}
+
+#[tokio::test]
+async fn issues_can_opt_out_of_tracking() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    subscribe_and_confirm(&app, "ursula@gmail.com", None).await;
+    let issue_id = publish(&app, Some("true")).await;
+
+    let html = sent_html(&app).await;
+    assert!(html.contains(HTML_CONTENT));
+    assert!(!html.contains("/t/c/"));
+    assert!(!html.contains("/t/o/"));
+    let report = app.get_newsletter_report_html(issue_id).await;
+    assert!(report.contains("Opens and clicks are not tracked for this issue."));
+}
@@ -0,0 +1,249 @@
+use crate::helpers::{spawn_app, TestApp};
+use crate::lists::{publish_to, recipients, subscribe_and_confirm};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const MESSAGE_ID: &str = "b7bc2f4a-e38e-4336-af7d-e6c392c2f817";
+
+async fn post_webhook(
+    app: &TestApp,
+    password: &str,
+    event: &serde_json::Value,
+) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/webhooks/postmark", app.address))
+        .basic_auth("postmark", Some(password))
+        .json(event)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+// Sends an issue to a single confirmed subscriber, delivered as `MESSAGE_ID`
+async fn send_issue_to(app: &TestApp, email: &str) -> Uuid {
+    subscribe_and_confirm(app, email, None).await;
+    let _mock_guard = Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+            { "ErrorCode": 0, "Message": "OK", "MessageID": MESSAGE_ID }
+        ])))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    publish_to(app, "", "").await;
+    app.dispatch_all_pending_emails().await;
+    sqlx::query_scalar!("SELECT newsletter_issue_id FROM issue_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+fn bounce(record_type: &str, bounce_type: &str, email: &str) -> serde_json::Value {
+    serde_json::json!({
+        "RecordType": record_type,
+        "ID": 4323372036854775807u64,
+        "Type": bounce_type,
+        "TypeCode": 1,
+        "MessageID": MESSAGE_ID,
+        "Email": email,
+        "From": "test@gmail.com",
+        "BouncedAt": "2024-01-21T16:33:54.9070259Z",
+        "Description": "The server was unable to deliver your message.",
+        "Inactive": true,
+    })
+}
+
+async fn subscriber_status(app: &TestApp, email: &str) -> String {
+    sqlx::query_scalar!("SELECT status FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn webhooks_without_valid_credentials_are_rejected() {
+    let app = spawn_app().await;
+    let event = bounce("Bounce", "HardBounce", "ursula@gmail.com");
+
+    let response = app
+        .api_client
+        .post(format!("{}/webhooks/postmark", app.address))
+        .json(&event)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        response.headers()["WWW-Authenticate"],
+        r#"Basic realm="webhooks""#
+    );
+
+    let response = post_webhook(&app, "wrong-secret", &event).await;
+    assert_eq!(response.status().as_u16(), 401);
+    // Authenticated before the payload is even looked at
+    let response = post_webhook(&app, "wrong-secret", &serde_json::json!({})).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn hard_bounces_are_logged_and_stop_future_deliveries() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = send_issue_to(&app, "ursula@gmail.com").await;
+
+    let response = post_webhook(
+        &app,
+        "my_webhook_secret",
+        &bounce("Bounce", "HardBounce", "ursula@gmail.com"),
+    )
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app, "ursula@gmail.com").await, "bounced");
+    let event = sqlx::query!("SELECT kind, newsletter_issue_id, details FROM delivery_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(event.kind, "hard_bounced");
+    assert_eq!(event.newsletter_issue_id, Some(issue_id));
+    assert!(event.details.unwrap().starts_with("HardBounce: "));
+    let source =
+        sqlx::query_scalar!("SELECT source FROM suppressions WHERE email = 'ursula@gmail.com'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(source, "bounce");
+    let html_page = app.get_newsletter_report_html(issue_id).await;
+    assert!(html_page.contains("<li>hard_bounced: 1</li>"));
+
+    sqlx::query!("DELETE FROM issue_deliveries")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    publish_to(&app, "", "").await;
+    assert!(recipients(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn soft_bounces_are_logged_without_changing_the_subscriber() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    send_issue_to(&app, "ursula@gmail.com").await;
+
+    let response = post_webhook(
+        &app,
+        "my_webhook_secret",
+        &bounce("Bounce", "SoftBounce", "ursula@gmail.com"),
+    )
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        subscriber_status(&app, "ursula@gmail.com").await,
+        "confirmed"
+    );
+    let kind = sqlx::query_scalar!("SELECT kind FROM delivery_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(kind, "soft_bounced");
+    let suppressions = sqlx::query!("SELECT email FROM suppressions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(suppressions.is_empty());
+}
+
+#[tokio::test]
+async fn spam_complaints_are_recorded_once_and_override_bounces() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    send_issue_to(&app, "ursula@gmail.com").await;
+    let complaint = bounce("SpamComplaint", "SpamComplaint", "ursula@gmail.com");
+
+    post_webhook(
+        &app,
+        "my_webhook_secret",
+        &bounce("Bounce", "HardBounce", "ursula@gmail.com"),
+    )
+    .await;
+    for _ in 0..2 {
+        let response = post_webhook(&app, "my_webhook_secret", &complaint).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    assert_eq!(
+        subscriber_status(&app, "ursula@gmail.com").await,
+        "complained"
+    );
+    let complaints = sqlx::query!("SELECT kind FROM delivery_events WHERE kind = 'complained'")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(complaints.len(), 1);
+    // The bounce came first
+    let source = sqlx::query_scalar!("SELECT source FROM suppressions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(source, "bounce");
+}
+
+#[tokio::test]
+async fn deliveries_are_logged_and_other_record_types_are_ignored() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = send_issue_to(&app, "ursula@gmail.com").await;
+
+    let response = post_webhook(
+        &app,
+        "my_webhook_secret",
+        &serde_json::json!({
+            "RecordType": "Delivery",
+            "MessageID": MESSAGE_ID,
+            "Recipient": "ursula@gmail.com",
+            "DeliveredAt": "2024-01-21T16:33:54-05:00",
+            "Details": "Test delivery webhook details",
+        }),
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = post_webhook(
+        &app,
+        "my_webhook_secret",
+        &serde_json::json!({
+            "RecordType": "Open",
+            "MessageID": MESSAGE_ID,
+            "Recipient": "ursula@gmail.com",
+        }),
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let events = sqlx::query!("SELECT kind, newsletter_issue_id FROM delivery_events")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].kind, "delivered");
+    assert_eq!(events[0].newsletter_issue_id, Some(issue_id));
+    assert_eq!(
+        subscriber_status(&app, "ursula@gmail.com").await,
+        "confirmed"
+    );
+}
+
+#[tokio::test]
+async fn malformed_events_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = post_webhook(
+        &app,
+        "my_webhook_secret",
+        &serde_json::json!({ "RecordType": "Bounce", "Email": "ursula@gmail.com" }),
+    )
+    .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
@@ -0,0 +1,205 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::helpers::{slow_polling_worker_settings, spawn_app, TestApp};
+use crate::newsletter::create_confirmed_subscriber;
+use server_scaffold::domain::SubscriberEmail;
+use server_scaffold::email_client::EmailSender;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn publish_newsletter(app: &TestApp) {
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text.",
+        "html_content": "<p>Newsletter body as HTML.</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    }))
+    .await;
+}
+
+// Confirmation emails sent while setting up subscribers are already in the
+// mock server's log, only a batch request means the issue is in flight
+async fn wait_for_the_first_batch_request(app: &TestApp) {
+    for _ in 0..50 {
+        if app
+            .email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .any(|request| request.url.path() == "/email/batch")
+        {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("The workers never tried to send the newsletter");
+}
+
+#[tokio::test]
+async fn idle_workers_stop_right_away_on_shutdown() {
+    let app = spawn_app().await;
+    let workers = app.spawn_workers(slow_polling_worker_settings());
+    // Let the workers find the queue empty and go idle
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // Idle workers do not wait for their next poll to stop
+    workers.stop(Duration::from_secs(2)).await.unwrap();
+}
+
+#[tokio::test]
+async fn in_flight_deliveries_are_completed_before_the_workers_stop() {
+    let app = spawn_app().await;
+    for _ in 0..3 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(2)))
+        // Neither the shutdown nor the next run of the workers sends it again
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let workers = app.spawn_workers(slow_polling_worker_settings());
+    publish_newsletter(&app).await;
+    wait_for_the_first_batch_request(&app).await;
+
+    // The batch is in flight: its rows are claimed and nothing is settled yet
+    workers.stop(Duration::from_secs(10)).await.unwrap();
+
+    let queued = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.count, 0);
+    let sent =
+        sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_deliveries WHERE status = 'sent'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(sent.count, 3);
+
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn tasks_queued_after_shutdown_wait_for_the_next_workers() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let workers = app.spawn_workers(slow_polling_worker_settings());
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    workers.stop(Duration::from_secs(2)).await.unwrap();
+
+    publish_newsletter(&app).await;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let queued = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.count, 1);
+
+    let workers = app.spawn_workers(slow_polling_worker_settings());
+    wait_for_the_first_batch_request(&app).await;
+    for _ in 0..50 {
+        let queued = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+        if queued.count == 0 {
+            workers.stop(Duration::from_secs(2)).await.unwrap();
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("The restarted workers never delivered the queued task");
+}
+
+// A transport without a batch API, taking its time over every email
+#[derive(Default)]
+struct SlowTransport {
+    recipients: Mutex<Vec<String>>,
+}
+
+impl SlowTransport {
+    fn recipients(&self) -> Vec<String> {
+        self.recipients.lock().unwrap().clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for SlowTransport {
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        _subject: &str,
+        _html_content: &str,
+        _text_content: &str,
+        _headers: &[(&str, &str)],
+    ) -> Result<Option<String>, anyhow::Error> {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        self.recipients
+            .lock()
+            .unwrap()
+            .push(recipient.as_ref().to_owned());
+        Ok(None)
+    }
+}
+
+async fn wait_for_recipients(transport: &SlowTransport, n: usize) {
+    for _ in 0..100 {
+        if transport.recipients().len() >= n {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("The workers never sent {} emails", n);
+}
+
+#[tokio::test]
+async fn a_deadline_in_the_middle_of_a_batch_sends_no_email_twice() {
+    let app = spawn_app().await;
+    for _ in 0..5 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.test_user.login(&app).await;
+    let transport = Arc::new(SlowTransport::default());
+
+    let workers = app.spawn_workers_with(transport.clone(), slow_polling_worker_settings());
+    publish_newsletter(&app).await;
+    wait_for_recipients(&transport, 1).await;
+    // Long enough for the email in flight, not for the rest of the batch
+    workers.stop(Duration::from_millis(500)).await.unwrap();
+
+    let sent_before_shutdown = transport.recipients();
+    assert!(sent_before_shutdown.len() < 5);
+    let due = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue WHERE execute_after <= now()"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(due.count as usize, 5 - sent_before_shutdown.len());
+
+    let workers = app.spawn_workers_with(transport.clone(), slow_polling_worker_settings());
+    wait_for_recipients(&transport, 5).await;
+    workers.stop(Duration::from_secs(2)).await.unwrap();
+
+    let mut recipients = transport.recipients();
+    recipients.sort();
+    recipients.dedup();
+    assert_eq!(recipients.len(), 5);
+    assert_eq!(transport.recipients().len(), 5);
+}